@@ -0,0 +1,36 @@
+use axum::{
+    http::header::{CACHE_CONTROL, CONTENT_TYPE},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+
+// Compiled into the binary so the dashboard needs no files next to it
+const INDEX: &str = include_str!("dashboard/index.html");
+const SCRIPT: &str = include_str!("dashboard/app.js");
+const STYLE: &str = include_str!("dashboard/style.css");
+
+/// Static dashboard assets. They hold no data and are served without the admin token;
+/// the page asks for the token and sends it with its API requests.
+pub fn router<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new()
+        .route(
+            "/admin/dashboard",
+            get(|| asset("text/html; charset=utf-8", INDEX)),
+        )
+        .route(
+            "/admin/dashboard/app.js",
+            get(|| asset("text/javascript; charset=utf-8", SCRIPT)),
+        )
+        .route(
+            "/admin/dashboard/style.css",
+            get(|| asset("text/css; charset=utf-8", STYLE)),
+        )
+}
+
+async fn asset(content_type: &'static str, body: &'static str) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, content_type), (CACHE_CONTROL, "no-cache")],
+        body,
+    )
+}
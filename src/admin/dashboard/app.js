@@ -0,0 +1,300 @@
+"use strict";
+
+// Usage dashboard for the admin API. Data comes from /admin/usage and the live feed from
+// /admin/usage/live, both authenticated with the admin token kept in session storage.
+
+const COLORS = ["#3b6ef5", "#f5a623", "#2bb673", "#d64545", "#8e5cf7", "#17a2b8", "#e83e8c", "#6c757d"];
+const MAX_SERIES = 7;
+const MAX_TAIL = 100;
+const REFRESH_MS = 15000;
+
+const $ = (id) => document.getElementById(id);
+const state = { metric: "requests", buckets: [], paused: false, live: null, timer: null };
+
+function token() {
+  return sessionStorage.getItem("gateway-admin-token");
+}
+
+async function api(path, options = {}) {
+  const response = await fetch(path, {
+    ...options,
+    headers: { Authorization: `Bearer ${token()}` },
+  });
+  if (response.status === 401) {
+    signOut("The admin token was rejected");
+    throw new Error("unauthorized");
+  }
+  if (!response.ok) {
+    throw new Error(`${path}: HTTP ${response.status}`);
+  }
+  return response;
+}
+
+function signIn(event) {
+  event.preventDefault();
+  sessionStorage.setItem("gateway-admin-token", $("token").value.trim());
+  $("token").value = "";
+  start();
+}
+
+function signOut(message = "") {
+  sessionStorage.removeItem("gateway-admin-token");
+  if (state.live) state.live.abort();
+  clearInterval(state.timer);
+  $("dashboard").hidden = true;
+  $("controls").hidden = true;
+  $("login").hidden = false;
+  $("login-error").textContent = message;
+}
+
+async function start() {
+  $("login").hidden = true;
+  $("dashboard").hidden = false;
+  $("controls").hidden = false;
+  try {
+    await refresh();
+    const recent = await (await api("/admin/usage/recent")).json();
+    $("tail").replaceChildren();
+    recent.data.reverse().forEach(addRequest);
+  } catch (e) {
+    console.error(e);
+    return;
+  }
+  state.timer = setInterval(() => refresh().catch(console.error), REFRESH_MS);
+  follow();
+}
+
+// Usage over time
+
+async function refresh() {
+  const minutes = $("range").value;
+  const body = await (await api(`/admin/usage?minutes=${minutes}`)).json();
+  state.buckets = body.data;
+  render();
+}
+
+function value(bucket, metric) {
+  switch (metric) {
+    case "tokens":
+      return bucket.prompt_tokens + bucket.completion_tokens;
+    case "cost":
+      return bucket.cost;
+    case "errors":
+      return bucket.errors;
+    default:
+      return bucket.requests;
+  }
+}
+
+function groupOf(bucket) {
+  return bucket[$("group").value] || "(none)";
+}
+
+function render() {
+  const group = $("group");
+  $("group-name").textContent = group.options[group.selectedIndex].text;
+
+  const totals = new Map();
+  const sum = { requests: 0, errors: 0, prompt: 0, completion: 0, cost: 0 };
+  for (const bucket of state.buckets) {
+    const name = groupOf(bucket);
+    const row = totals.get(name) || { requests: 0, errors: 0, prompt: 0, completion: 0, cost: 0 };
+    row.requests += bucket.requests;
+    row.errors += bucket.errors;
+    row.prompt += bucket.prompt_tokens;
+    row.completion += bucket.completion_tokens;
+    row.cost += bucket.cost;
+    totals.set(name, row);
+    sum.requests += bucket.requests;
+    sum.errors += bucket.errors;
+    sum.prompt += bucket.prompt_tokens;
+    sum.completion += bucket.completion_tokens;
+    sum.cost += bucket.cost;
+  }
+
+  $("total-requests").textContent = number(sum.requests);
+  $("total-tokens").textContent = number(sum.prompt + sum.completion);
+  $("total-cost").textContent = money(sum.cost);
+  const rate = sum.requests ? ((100 * sum.errors) / sum.requests).toFixed(1) : "0.0";
+  $("total-errors").textContent = `${number(sum.errors)} (${rate}%)`;
+
+  const rows = [...totals.entries()].sort((a, b) => b[1].requests - a[1].requests);
+  $("breakdown").replaceChildren(
+    ...rows.map(([name, row]) =>
+      tr([name, number(row.requests), number(row.errors), number(row.prompt), number(row.completion), money(row.cost)])
+    )
+  );
+
+  chart(rows.map(([name]) => name));
+}
+
+// Stacked bars per time slot for the largest groups, the rest shown as "other"
+function chart(ranked) {
+  const svg = $("chart");
+  const width = 960;
+  const height = 260;
+  const left = 56;
+  const bottom = 22;
+  const minutes = Number($("range").value);
+  const slots = Math.min(minutes, 60);
+  const slotMinutes = minutes / slots;
+  const end = Math.floor(Date.now() / 60000) + 1;
+  const startMinute = end - minutes;
+
+  const shown = ranked.slice(0, MAX_SERIES);
+  const series = ranked.length > MAX_SERIES ? [...shown, "other"] : shown;
+  const stacks = Array.from({ length: slots }, () => new Array(series.length).fill(0));
+  for (const bucket of state.buckets) {
+    const minute = Math.floor(Date.parse(bucket.time) / 60000);
+    const slot = Math.floor((minute - startMinute) / slotMinutes);
+    if (slot < 0 || slot >= slots) continue;
+    let index = shown.indexOf(groupOf(bucket));
+    if (index < 0) index = series.length - 1;
+    stacks[slot][index] += value(bucket, state.metric);
+  }
+
+  const max = Math.max(...stacks.map((stack) => stack.reduce((a, b) => a + b, 0)), 0) || 1;
+  const plot = height - bottom;
+  const barWidth = (width - left) / slots;
+  const parts = [];
+  for (let i = 0; i <= 4; i++) {
+    const y = plot - (plot * i) / 4 + (i === 4 ? 6 : 0);
+    const label = state.metric === "cost" ? money((max * i) / 4) : number(Math.round((max * i) / 4));
+    parts.push(`<line x1="${left}" x2="${width}" y1="${y}" y2="${y}"></line>`);
+    parts.push(`<text x="${left - 6}" y="${y + 4}" text-anchor="end">${label}</text>`);
+  }
+  stacks.forEach((stack, slot) => {
+    let y = plot;
+    const x = left + slot * barWidth + 1;
+    stack.forEach((amount, index) => {
+      if (!amount) return;
+      const h = (plot - 6) * (amount / max);
+      y -= h;
+      parts.push(
+        `<rect x="${x}" y="${y}" width="${Math.max(barWidth - 2, 1)}" height="${h}" fill="${COLORS[index % COLORS.length]}"><title>${escape(series[index])}: ${amount}</title></rect>`
+      );
+    });
+  });
+  for (const slot of [0, Math.floor(slots / 2), slots - 1]) {
+    const time = new Date((startMinute + slot * slotMinutes) * 60000);
+    const x = left + slot * barWidth + barWidth / 2;
+    parts.push(`<text x="${x}" y="${height - 4}" text-anchor="middle">${clock(time)}</text>`);
+  }
+  svg.innerHTML = parts.join("");
+
+  $("legend").replaceChildren(
+    ...series.map((name, index) => {
+      const item = document.createElement("li");
+      const swatch = document.createElement("i");
+      swatch.style.background = COLORS[index % COLORS.length];
+      item.append(swatch, name);
+      return item;
+    })
+  );
+}
+
+// Live tail
+
+async function follow() {
+  state.live = new AbortController();
+  $("live-status").textContent = "connecting";
+  try {
+    const response = await api("/admin/usage/live", { signal: state.live.signal });
+    $("live-status").textContent = "live";
+    const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+    let pending = "";
+    for (;;) {
+      const { value: text, done } = await reader.read();
+      if (done) break;
+      pending += text;
+      let end;
+      while ((end = pending.indexOf("\n\n")) >= 0) {
+        const event = pending.slice(0, end);
+        pending = pending.slice(end + 2);
+        const data = event
+          .split("\n")
+          .filter((line) => line.startsWith("data:"))
+          .map((line) => line.slice(5).trim())
+          .join("\n");
+        if (data && !state.paused) addRequest(JSON.parse(data));
+      }
+    }
+  } catch (e) {
+    if (e.name === "AbortError" || e.message === "unauthorized") return;
+    console.error(e);
+  }
+  $("live-status").textContent = "reconnecting";
+  setTimeout(follow, 3000);
+}
+
+function addRequest(record) {
+  const row = tr([
+    clock(new Date(record.time), true),
+    record.key || "",
+    record.provider,
+    record.model || "",
+    `${record.method} ${record.path}`,
+    record.status,
+    `${number(record.latency_ms)} ms`,
+    number(record.prompt_tokens + record.completion_tokens),
+    money(record.cost),
+  ]);
+  if (record.status >= 400 || record.error) {
+    const status = row.children[5];
+    status.className = "failed";
+    status.title = record.error || "";
+  }
+  const tail = $("tail");
+  tail.prepend(row);
+  while (tail.children.length > MAX_TAIL) tail.lastChild.remove();
+}
+
+// Formatting
+
+function tr(cells) {
+  const row = document.createElement("tr");
+  for (const cell of cells) {
+    const td = document.createElement("td");
+    td.textContent = cell;
+    row.append(td);
+  }
+  return row;
+}
+
+function number(n) {
+  return Number(n).toLocaleString();
+}
+
+function money(n) {
+  return `$${n < 1 && n > 0 ? n.toFixed(4) : n.toFixed(2)}`;
+}
+
+function clock(date, seconds = false) {
+  return date.toLocaleTimeString([], { hour: "2-digit", minute: "2-digit", second: seconds ? "2-digit" : undefined });
+}
+
+function escape(text) {
+  return String(text).replace(/[&<>"]/g, (c) => ({ "&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;" })[c]);
+}
+
+$("login").addEventListener("submit", signIn);
+$("sign-out").addEventListener("click", () => signOut());
+$("range").addEventListener("change", () => refresh().catch(console.error));
+$("group").addEventListener("change", render);
+$("pause").addEventListener("click", (event) => {
+  state.paused = !state.paused;
+  event.target.textContent = state.paused ? "Resume" : "Pause";
+});
+$("metric").addEventListener("click", (event) => {
+  const metric = event.target.dataset.metric;
+  if (!metric) return;
+  state.metric = metric;
+  for (const button of $("metric").children) button.classList.toggle("active", button === event.target);
+  render();
+});
+
+if (token()) {
+  start();
+} else {
+  $("login").hidden = false;
+}
@@ -0,0 +1,481 @@
+use crate::{
+    config::{AppConfig, BudgetConfig, FileConfig, KeyConfig, ModelConfig, TeamConfig},
+    error::AppError,
+    shutdown::Phase,
+    state::Gateway,
+};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::header::AUTHORIZATION,
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{TimeDelta, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+mod dashboard;
+pub mod store;
+
+use store::Store;
+
+/// Prefix of generated gateway keys
+const KEY_PREFIX: &str = "sk-gw-";
+
+/// State of the admin API listener
+pub struct Admin {
+    gateway: Arc<Gateway>,
+    store: Arc<dyn Store>,
+    token: String,
+    /// Serializes configuration changes so concurrent writes cannot lose each other
+    changes: tokio::sync::Mutex<()>,
+}
+
+impl Admin {
+    pub fn new(gateway: Arc<Gateway>, store: Arc<dyn Store>, token: String) -> Self {
+        Self {
+            gateway,
+            store,
+            token,
+            changes: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Entries managed through the API
+    async fn managed(&self) -> Result<FileConfig, AppError> {
+        self.with_store(|store| store::managed(store)).await
+    }
+
+    /// Run a store operation on the blocking pool so database calls never stall the runtime
+    async fn with_store<T, F>(&self, operation: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Store) -> Result<T, String> + Send + 'static,
+    {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || operation(store.as_ref()))
+            .await
+            .map_err(|e| AppError::StoreError(e.to_string()))?
+            .map_err(AppError::StoreError)
+    }
+}
+
+/// Admin API routes, every route except the dashboard's static assets requires
+/// `Authorization: Bearer <ADMIN_TOKEN>`
+pub fn router(admin: Arc<Admin>) -> Router {
+    let router = Router::new()
+        .route("/admin/providers", get(providers))
+        .route("/admin/reload", post(reload))
+        .route("/admin/usage", get(usage))
+        .route("/admin/usage/recent", get(recent_usage))
+        .route("/admin/usage/live", get(live_usage));
+    let router = resource::<KeyConfig>(router);
+    let router = resource::<TeamConfig>(router);
+    let router = resource::<ModelConfig>(router);
+    let router = resource::<BudgetConfig>(router);
+    router
+        .route_layer(middleware::from_fn_with_state(admin.clone(), authenticate))
+        .merge(dashboard::router())
+        .with_state(admin)
+}
+
+fn resource<R: Resource>(router: Router<Arc<Admin>>) -> Router<Arc<Admin>> {
+    let path = format!("/admin/{}", R::KIND);
+    router.route(&path, get(list::<R>).post(create::<R>)).route(
+        &format!("{}/:name", path),
+        get(show::<R>).put(update::<R>).delete(remove::<R>),
+    )
+}
+
+async fn authenticate(State(admin): State<Arc<Admin>>, request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+        .map(str::trim);
+    if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), admin.token.as_bytes())) {
+        warn!(
+            "Admin request without a valid token: {}",
+            request.uri().path()
+        );
+        return AppError::MissingApiKey.into_response();
+    }
+    next.run(request).await
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Configuration entries the admin API manages, stored as one record per name
+trait Resource: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Record kind, also the route segment, e.g. `keys`
+    const KIND: &'static str;
+
+    fn name(&self) -> &str;
+
+    fn set_name(&mut self, name: String);
+
+    /// Entries of this kind in a config file or set of managed entries
+    fn entries(config: &mut FileConfig) -> &mut Vec<Arc<Self>>;
+
+    /// Entries of this kind in the running configuration
+    fn configured(config: &AppConfig) -> &[Arc<Self>];
+
+    /// Fill in settings a write leaves out, from the entry it replaces
+    fn prepare(&mut self, _previous: Option<&Self>) {}
+
+    /// The entry as written to the store
+    fn stored(&self) -> Self {
+        self.clone()
+    }
+
+    /// The entry as listed by the API
+    fn view(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl Resource for KeyConfig {
+    const KIND: &'static str = "keys";
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    fn entries(config: &mut FileConfig) -> &mut Vec<Arc<Self>> {
+        &mut config.keys
+    }
+
+    fn configured(config: &AppConfig) -> &[Arc<Self>] {
+        &config.keys
+    }
+
+    /// Keep the existing key when an update leaves it out, generate one for new keys
+    fn prepare(&mut self, previous: Option<&Self>) {
+        if self.key.is_some() || self.key_env.is_some() || self.key_hash.is_some() {
+            return;
+        }
+        match previous {
+            Some(previous) => {
+                self.key = previous.key.clone();
+                self.key_env = previous.key_env.clone();
+                self.key_hash = previous.key_hash.clone();
+                self.key_suffix = previous.key_suffix.clone();
+            }
+            None => self.key = Some(generate_key()),
+        }
+    }
+
+    /// Only a hash of the key is stored
+    fn stored(&self) -> Self {
+        self.hashed()
+    }
+
+    /// Keys are only shown in full when they are created
+    fn view(&self) -> Value {
+        let mut view = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = view.as_object_mut() {
+            fields.remove("key_hash");
+            fields.remove("key_suffix");
+        }
+        if let Some(suffix) = self.suffix() {
+            view["key"] = json!(format!("...{}", suffix));
+        }
+        view
+    }
+}
+
+impl Resource for TeamConfig {
+    const KIND: &'static str = "teams";
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    fn entries(config: &mut FileConfig) -> &mut Vec<Arc<Self>> {
+        &mut config.teams
+    }
+
+    fn configured(config: &AppConfig) -> &[Arc<Self>] {
+        &config.teams
+    }
+}
+
+impl Resource for ModelConfig {
+    const KIND: &'static str = "models";
+
+    fn name(&self) -> &str {
+        &self.id
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.id = name;
+    }
+
+    fn entries(config: &mut FileConfig) -> &mut Vec<Arc<Self>> {
+        &mut config.models
+    }
+
+    fn configured(config: &AppConfig) -> &[Arc<Self>] {
+        &config.models
+    }
+}
+
+impl Resource for BudgetConfig {
+    const KIND: &'static str = "budgets";
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    fn entries(config: &mut FileConfig) -> &mut Vec<Arc<Self>> {
+        &mut config.budgets
+    }
+
+    fn configured(config: &AppConfig) -> &[Arc<Self>] {
+        &config.budgets
+    }
+}
+
+/// Random gateway key, e.g. `sk-gw-3f9c...`
+fn generate_key() -> String {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes).expect("Failed to generate random key");
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", KEY_PREFIX, hex)
+}
+
+/// API view of an entry, `managed` when it is stored through the API rather than
+/// defined only in the config file
+fn view<R: Resource>(entry: &R, managed: &[Arc<R>]) -> Value {
+    let mut view = entry.view();
+    view["managed"] = json!(managed.iter().any(|m| m.name() == entry.name()));
+    view
+}
+
+async fn list<R: Resource>(State(admin): State<Arc<Admin>>) -> Result<Json<Value>, AppError> {
+    let mut managed = admin.managed().await?;
+    let managed = R::entries(&mut managed);
+    let state = admin.gateway.state();
+    let data: Vec<Value> = R::configured(&state.config)
+        .iter()
+        .map(|entry| view(entry.as_ref(), managed))
+        .collect();
+    Ok(Json(json!({ "object": "list", "data": data })))
+}
+
+async fn show<R: Resource>(
+    State(admin): State<Arc<Admin>>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let mut managed = admin.managed().await?;
+    let managed = R::entries(&mut managed);
+    let state = admin.gateway.state();
+    R::configured(&state.config)
+        .iter()
+        .find(|entry| entry.name() == name)
+        .map(|entry| Json(view(entry.as_ref(), managed)))
+        .ok_or_else(|| AppError::NotFound(format!("{} {}", R::KIND, name)))
+}
+
+/// Create an entry. The response is the only place a generated key is shown in full.
+async fn create<R: Resource>(
+    State(admin): State<Arc<Admin>>,
+    Json(body): Json<Value>,
+) -> Result<Response, AppError> {
+    let entry: R = serde_json::from_value(body)?;
+    let entry = write(&admin, entry, true).await?;
+    let mut body = serde_json::to_value(&entry)?;
+    body["managed"] = json!(true);
+    Ok((http::StatusCode::CREATED, Json(body)).into_response())
+}
+
+/// Create or replace an entry
+async fn update<R: Resource>(
+    State(admin): State<Arc<Admin>>,
+    Path(name): Path<String>,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, AppError> {
+    let mut entry: R = serde_json::from_value(body)?;
+    entry.set_name(name);
+    let entry = write(&admin, entry, false).await?;
+    let mut body = entry.view();
+    body["managed"] = json!(true);
+    Ok(Json(body))
+}
+
+/// Validate the configuration with `entry` in place, then store it and apply it. With
+/// `create`, an existing entry of the same name is a conflict rather than replaced, checked
+/// under the lock so concurrent creates cannot both succeed.
+async fn write<R: Resource>(admin: &Admin, mut entry: R, create: bool) -> Result<R, AppError> {
+    let _change = admin.changes.lock().await;
+    let previous = R::configured(&admin.gateway.state().config)
+        .iter()
+        .find(|e| e.name() == entry.name())
+        .cloned();
+    if create && previous.is_some() {
+        return Err(AppError::Conflict(format!(
+            "{} {} already exists",
+            R::KIND,
+            entry.name()
+        )));
+    }
+    entry.prepare(previous.as_deref());
+
+    let stored = entry.stored();
+    let mut managed = admin.managed().await?;
+    let entries = R::entries(&mut managed);
+    entries.retain(|e| e.name() != entry.name());
+    entries.push(Arc::new(stored.clone()));
+    let state = admin
+        .gateway
+        .build(managed)
+        .map_err(AppError::InvalidConfig)?;
+
+    let name = entry.name().to_string();
+    let record = serde_json::to_value(&stored)?;
+    admin
+        .with_store(move |store| store.put(R::KIND, &name, &record))
+        .await?;
+    admin.gateway.swap(state);
+    info!(target: "audit", kind = R::KIND, name = entry.name(), "Admin API stored entry");
+    Ok(entry)
+}
+
+/// Delete an entry stored through the API, entries of the same name in the config file apply again
+async fn remove<R: Resource>(
+    State(admin): State<Arc<Admin>>,
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    let _change = admin.changes.lock().await;
+    let mut managed = admin.managed().await?;
+    let entries = R::entries(&mut managed);
+    let count = entries.len();
+    entries.retain(|e| e.name() != name);
+    if entries.len() == count {
+        let configured = R::configured(&admin.gateway.state().config)
+            .iter()
+            .any(|e| e.name() == name);
+        if configured {
+            return Err(AppError::Conflict(format!(
+                "{} {} is defined in the config file",
+                R::KIND,
+                name
+            )));
+        }
+        return Err(AppError::NotFound(format!("{} {}", R::KIND, name)));
+    }
+    let state = admin
+        .gateway
+        .build(managed)
+        .map_err(AppError::InvalidConfig)?;
+
+    let record = name.clone();
+    admin
+        .with_store(move |store| store.delete(R::KIND, &record))
+        .await?;
+    admin.gateway.swap(state);
+    info!(target: "audit", kind = R::KIND, name = %name, "Admin API deleted entry");
+    Ok(http::StatusCode::NO_CONTENT.into_response())
+}
+
+/// Registered providers with the outcome of their recent requests
+async fn providers(State(admin): State<Arc<Admin>>) -> Json<Value> {
+    let state = admin.gateway.state();
+    let data: Vec<Value> = state
+        .providers
+        .names()
+        .into_iter()
+        .map(|name| {
+            json!({
+                "name": name,
+                "configured": state.config.providers.iter().any(|p| p.name == name),
+                "health": state.health.get(name),
+            })
+        })
+        .collect();
+    Json(json!({ "object": "list", "data": data }))
+}
+
+/// Reload the environment, config file and stored entries
+async fn reload(State(admin): State<Arc<Admin>>) -> Result<Json<Value>, AppError> {
+    let _change = admin.changes.lock().await;
+    let state = admin
+        .gateway
+        .build(admin.managed().await?)
+        .map_err(AppError::InvalidConfig)?;
+    admin.gateway.swap(state);
+    Ok(Json(json!({ "status": "reloaded" })))
+}
+
+#[derive(Deserialize)]
+struct UsageQuery {
+    /// Minutes of usage to return, up to a day
+    minutes: Option<i64>,
+}
+
+/// Per-minute request, token, cost and error totals by key, provider and model
+async fn usage(State(admin): State<Arc<Admin>>, Query(query): Query<UsageQuery>) -> Json<Value> {
+    let minutes = query.minutes.unwrap_or(60).clamp(1, 24 * 60);
+    let since = Utc::now() - TimeDelta::minutes(minutes - 1);
+    let data = admin.gateway.state().usage.buckets(since);
+    Json(json!({ "object": "list", "data": data }))
+}
+
+/// Most recent requests, newest first
+async fn recent_usage(State(admin): State<Arc<Admin>>) -> Json<Value> {
+    let data = admin.gateway.state().usage.recent();
+    Json(json!({ "object": "list", "data": data }))
+}
+
+/// Requests as they finish, one `request` event each
+async fn live_usage(
+    State(admin): State<Arc<Admin>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = admin.gateway.state().usage.subscribe();
+    let events = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(record) => {
+                    let event = Event::default()
+                        .event("request")
+                        .json_data(&record)
+                        .unwrap_or_default();
+                    return Some((Ok(event), receiver));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Live usage subscriber skipped {} requests", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    // Followers must not keep the admin listener open during shutdown
+    let shutdown = admin.gateway.state().shutdown;
+    let events = events.take_until(async move { shutdown.reached(Phase::Closing).await });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
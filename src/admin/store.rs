@@ -0,0 +1,137 @@
+use crate::config::{FileConfig, KeyConfig};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::info;
+
+/// Persistence for entries managed through the admin API, stored as JSON records by kind
+/// (`keys`, `teams`, `models`, `budgets`) and name
+pub trait Store: Send + Sync {
+    /// All records of a kind, ordered by name
+    fn list(&self, kind: &str) -> Result<Vec<Value>, String>;
+
+    /// Insert or replace a record
+    fn put(&self, kind: &str, name: &str, record: &Value) -> Result<(), String>;
+
+    /// Delete a record, returning whether it existed
+    fn delete(&self, kind: &str, name: &str) -> Result<bool, String>;
+}
+
+/// Store backed by a SQLite database file
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        info!("Opening admin database: {}", path);
+        let connection = Connection::open(path)
+            .map_err(|e| format!("Failed to open admin database {}: {}", path, e))?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS records (
+                    kind TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    data TEXT NOT NULL,
+                    updated_at TEXT NOT NULL,
+                    PRIMARY KEY (kind, name)
+                )",
+            )
+            .map_err(|e| format!("Failed to initialize admin database {}: {}", path, e))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+// Calls block on SQLite, async callers run them on the blocking pool
+impl Store for SqliteStore {
+    fn list(&self, kind: &str) -> Result<Vec<Value>, String> {
+        let connection = self.connection.lock();
+        let mut statement = connection
+            .prepare_cached("SELECT data FROM records WHERE kind = ?1 ORDER BY name")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![kind], |row| row.get::<_, String>(0))
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| {
+            let data = row.map_err(|e| e.to_string())?;
+            serde_json::from_str(&data).map_err(|e| e.to_string())
+        })
+        .collect()
+    }
+
+    fn put(&self, kind: &str, name: &str, record: &Value) -> Result<(), String> {
+        self.connection
+            .lock()
+            .execute(
+                "INSERT INTO records (kind, name, data, updated_at) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (kind, name) DO UPDATE SET data = ?3, updated_at = ?4",
+                params![
+                    kind,
+                    name,
+                    record.to_string(),
+                    chrono::Utc::now().to_rfc3339()
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn delete(&self, kind: &str, name: &str) -> Result<bool, String> {
+        self.connection
+            .lock()
+            .execute(
+                "DELETE FROM records WHERE kind = ?1 AND name = ?2",
+                params![kind, name],
+            )
+            .map(|deleted| deleted > 0)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Entries managed through the admin API, in the shape of the config file
+pub fn managed(store: &dyn Store) -> Result<FileConfig, String> {
+    fn records<T: serde::de::DeserializeOwned>(
+        store: &dyn Store,
+        kind: &str,
+    ) -> Result<Vec<Arc<T>>, String> {
+        store
+            .list(kind)?
+            .into_iter()
+            .map(|record| {
+                serde_json::from_value(record)
+                    .map(Arc::new)
+                    .map_err(|e| format!("Invalid {} record in admin database: {}", kind, e))
+            })
+            .collect()
+    }
+
+    Ok(FileConfig {
+        keys: records(store, "keys")?,
+        teams: records(store, "teams")?,
+        models: records(store, "models")?,
+        budgets: records(store, "budgets")?,
+        ..FileConfig::default()
+    })
+}
+
+/// Replace plaintext keys stored by earlier versions with their hashes
+pub fn hash_keys(store: &dyn Store) -> Result<(), String> {
+    let mut hashed = 0;
+    for record in store.list("keys")? {
+        if !record.get("key").is_some_and(Value::is_string) {
+            continue;
+        }
+        let key: KeyConfig = serde_json::from_value(record)
+            .map_err(|e| format!("Invalid keys record in admin database: {}", e))?;
+        let record = serde_json::to_value(key.hashed()).map_err(|e| e.to_string())?;
+        store.put("keys", &key.name, &record)?;
+        hashed += 1;
+    }
+    if hashed > 0 {
+        info!("Replaced {} stored plaintext keys with hashes", hashed);
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use crate::{
+    config::{AppConfig, KeyConfig, ModelConfig},
+    error::AppError,
+    proxy::proxy_request_to_provider,
+    state::AppState,
+};
+use axum::body::{to_bytes, Body};
+use axum::http::{Method, Request};
+use futures_util::future::join_all;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Upper bound for a single upstream model listing
+const LISTING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Catalog entries and the time they were loaded
+type CachedCatalog = (Instant, Arc<Vec<ModelEntry>>);
+
+static CATALOG: Lazy<RwLock<Option<CachedCatalog>>> = Lazy::new(|| RwLock::new(None));
+
+/// Held while the catalog is rebuilt, so concurrent requests for a stale catalog wait for
+/// one refresh instead of each querying every provider
+static REFRESH: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Drop the cached catalog, e.g. after the configured models changed
+pub fn invalidate() {
+    *CATALOG.write() = None;
+}
+
+/// A model in the aggregated `/v1/models` catalog
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelEntry {
+    pub id: String,
+    pub object: &'static str,
+    pub created: u64,
+    pub owned_by: String,
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias_for: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modalities: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<Pricing>,
+    pub deprecated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecation_date: Option<String>,
+}
+
+/// Prices in USD per million tokens
+#[derive(Debug, Clone, Serialize)]
+pub struct Pricing {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<f64>,
+}
+
+impl ModelEntry {
+    fn new(id: &str, provider: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            object: "model",
+            created: 0,
+            owned_by: provider.to_string(),
+            provider: provider.to_string(),
+            alias_for: None,
+            context_window: None,
+            modalities: Vec::new(),
+            pricing: None,
+            deprecated: false,
+            deprecation_date: None,
+        }
+    }
+
+    /// Apply configured metadata, keeping upstream values the config leaves unset
+    fn apply(&mut self, model: &ModelConfig) {
+        self.context_window = model.context_window.or(self.context_window);
+        if !model.modalities.is_empty() {
+            self.modalities = model.modalities.clone();
+        }
+        if model.input_price.is_some() || model.output_price.is_some() {
+            self.pricing = Some(Pricing {
+                input: model.input_price,
+                output: model.output_price,
+            });
+        }
+        self.deprecated |= model.deprecated;
+        self.deprecation_date = model
+            .deprecation_date
+            .clone()
+            .or(self.deprecation_date.take());
+    }
+}
+
+/// The model catalog visible to `key`, refreshed when older than `MODEL_CATALOG_TTL`
+pub async fn models(state: &AppState, key: Option<&KeyConfig>) -> Vec<ModelEntry> {
+    let entries = match cached(state) {
+        Some(entries) => entries,
+        None => {
+            let _refresh = REFRESH.lock().await;
+            // Another request may have refreshed the catalog while this one waited
+            match cached(state) {
+                Some(entries) => entries,
+                None => {
+                    let entries = Arc::new(build(state).await);
+                    *CATALOG.write() = Some((Instant::now(), entries.clone()));
+                    entries
+                }
+            }
+        }
+    };
+
+    entries
+        .iter()
+        .filter(|entry| key.is_none_or(|key| state.config.allows(key, &entry.provider, &entry.id)))
+        .cloned()
+        .collect()
+}
+
+/// The cached catalog unless it is older than `MODEL_CATALOG_TTL`
+fn cached(state: &AppState) -> Option<Arc<Vec<ModelEntry>>> {
+    CATALOG
+        .read()
+        .as_ref()
+        .filter(|(loaded, _)| {
+            loaded.elapsed() < Duration::from_secs(state.config.model_catalog_ttl)
+        })
+        .map(|(_, entries)| entries.clone())
+}
+
+/// Providers queried for upstream listings
+fn listing_providers(config: &AppConfig) -> Vec<String> {
+    match &config.model_catalog_providers {
+        Some(providers) => providers.clone(),
+        // Only providers the gateway can authenticate to without the caller's key. Built-in
+        // providers take the caller's key, so they are only listed when named explicitly.
+        None => config
+            .providers
+            .iter()
+            .filter(|p| p.static_api_key().is_some() || !p.auth_required)
+            .map(|p| p.name.clone())
+            .collect(),
+    }
+}
+
+async fn build(state: &AppState) -> Vec<ModelEntry> {
+    let config = &state.config;
+    let providers = listing_providers(config);
+    info!(
+        "Refreshing model catalog from {} providers",
+        providers.len()
+    );
+
+    let listings = join_all(
+        providers
+            .iter()
+            .map(|provider| fetch_listing(state, provider)),
+    )
+    .await;
+
+    let mut entries: Vec<ModelEntry> = Vec::new();
+    for (provider, listing) in providers.iter().zip(listings) {
+        match listing {
+            Ok(models) => entries.extend(models),
+            Err(e) => warn!("Failed to list models for {}: {}", provider, e),
+        }
+    }
+
+    // Azure deployments are known from configuration
+    for model in config.azure_deployments.keys() {
+        entries.push(ModelEntry::new(model, "azure"));
+    }
+
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    entries.retain(|entry| seen.insert((entry.provider.clone(), entry.id.clone())));
+
+    // Configured models add metadata to upstream entries, or are listed on their own
+    for model in &config.models {
+        let entry = if model.model.is_none() {
+            entries
+                .iter_mut()
+                .find(|e| e.provider == model.provider && e.id == model.id)
+        } else {
+            None
+        };
+        match entry {
+            Some(entry) => entry.apply(model),
+            None => {
+                let mut entry = ModelEntry::new(&model.id, &model.provider);
+                entry.alias_for = model.model.clone();
+                entry.apply(model);
+                entries.push(entry);
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| (&a.provider, &a.id).cmp(&(&b.provider, &b.id)));
+    debug!("Model catalog contains {} models", entries.len());
+    entries
+}
+
+/// List a provider's models through the regular proxy pipeline
+async fn fetch_listing(state: &AppState, provider: &str) -> Result<Vec<ModelEntry>, AppError> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/v1/models")
+        .body(Body::empty())
+        .map_err(|e| AppError::RequestError(e.to_string()))?;
+
+    let response = tokio::time::timeout(
+        LISTING_TIMEOUT,
+        proxy_request_to_provider(state, provider, request),
+    )
+    .await
+    .map_err(|_| AppError::RequestError("model listing timed out".to_string()))??;
+
+    if !response.status().is_success() {
+        return Err(AppError::RequestError(format!(
+            "model listing returned {}",
+            response.status()
+        )));
+    }
+
+    let body: Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await?)?;
+    Ok(body["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|model| {
+            let mut entry = ModelEntry::new(model["id"].as_str()?, provider);
+            entry.created = model["created"].as_u64().unwrap_or_default();
+            if let Some(owner) = model["owned_by"].as_str() {
+                entry.owned_by = owner.to_string();
+            }
+            Some(entry)
+        })
+        .collect())
+}
@@ -0,0 +1,342 @@
+use crate::{
+    config::{ClientIpConfig, IpRange},
+    error::AppError,
+    server::Peer,
+    state::AppState,
+};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header::FORWARDED, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
+
+const DEFAULT_HEADER: &str = "x-forwarded-for";
+
+/// Paths that stay reachable for orchestrator probes whatever the IP access rules say
+const PROBE_PATHS: [&str; 3] = ["/health", "/livez", "/readyz"];
+
+/// Address of the client behind any trusted proxies, added to every request for logging
+/// and access rules. `None` for local clients on the Unix socket that did not forward
+/// an address.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ip) => write!(f, "{}", ip),
+            None => write!(f, "local"),
+        }
+    }
+}
+
+/// Resolve the client address of a request and apply the `[client_ip]` allow and deny lists
+pub async fn handle(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let config = &state.config.client_ip;
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<Peer>>()
+        .map(|ConnectInfo(peer)| *peer);
+    let client = ClientIp(resolve(config, peer, request.headers()));
+
+    if !PROBE_PATHS.contains(&request.uri().path()) && !allowed(config, client.0) {
+        warn!(
+            "Refusing request from {} for {}",
+            client,
+            request.uri().path()
+        );
+        return AppError::ClientNotAllowed(client.to_string()).into_response();
+    }
+    request.extensions_mut().insert(client);
+    next.run(request).await
+}
+
+/// Walk the forwarding chain from the connection towards the client, stepping over every
+/// trusted proxy. The first address not in `trusted_proxies` is the client, so clients
+/// cannot pose as someone else by sending their own forwarding headers.
+fn resolve(config: &ClientIpConfig, peer: Option<Peer>, headers: &HeaderMap) -> Option<IpAddr> {
+    let trusted = |ip: Option<IpAddr>| match ip {
+        Some(ip) => in_ranges(&config.trusted_proxies, &ip),
+        // Local processes on the Unix socket are part of the deployment, like a sidecar's app
+        None => true,
+    };
+
+    let mut client = match peer {
+        Some(Peer::Tcp { address, proxied }) => {
+            let ip = Some(address.ip());
+            match proxied {
+                Some(proxied) if trusted(ip) => Some(proxied.ip()),
+                _ => ip,
+            }
+        }
+        Some(Peer::Unix(_)) | None => None,
+    };
+    for hop in chain(config, headers).into_iter().rev() {
+        if !trusted(client) {
+            break;
+        }
+        match hop {
+            Some(ip) => client = Some(ip),
+            // An obfuscated or unknown hop hides everything in front of it
+            None => break,
+        }
+    }
+    client
+}
+
+/// Forwarded addresses from the client to the nearest proxy, `None` for hops that
+/// did not disclose one
+fn chain(config: &ClientIpConfig, headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let name = config.header.as_deref().unwrap_or(DEFAULT_HEADER);
+    let values = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    if name == FORWARDED.as_str() {
+        // Forwarded: for=192.0.2.60;proto=https, for="[2001:db8::1]:4711"
+        values
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(key, _)| key.eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_ip(node.trim_matches('"')))
+            })
+            .collect()
+    } else {
+        values
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|hop| !hop.is_empty())
+            .map(parse_ip)
+            .collect()
+    }
+}
+
+/// An address with or without a port, IPv6 optionally in brackets
+fn parse_ip(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .or_else(|_| node.parse::<SocketAddr>().map(|address| address.ip()))
+        .or_else(|_| node.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
+
+fn allowed(config: &ClientIpConfig, client: Option<IpAddr>) -> bool {
+    let Some(ip) = client else {
+        return true;
+    };
+    !in_ranges(&config.deny, &ip) && (config.allow.is_empty() || in_ranges(&config.allow, &ip))
+}
+
+fn in_ranges(ranges: &[IpRange], ip: &IpAddr) -> bool {
+    ranges.iter().any(|range| range.contains(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn config(settings: &str) -> ClientIpConfig {
+        toml::from_str(settings).unwrap()
+    }
+
+    fn trusting(header: &str) -> ClientIpConfig {
+        config(&format!(
+            "trusted_proxies = [\"10.0.0.0/8\", \"fd00::/8\"]\nheader = \"{}\"",
+            header
+        ))
+    }
+
+    fn tcp(address: &str) -> Option<Peer> {
+        Some(Peer::Tcp {
+            address: address.parse().unwrap(),
+            proxied: None,
+        })
+    }
+
+    fn headers(name: &str, values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(
+                axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn ip(address: &str) -> Option<IpAddr> {
+        Some(address.parse().unwrap())
+    }
+
+    #[test]
+    fn ignores_forwarding_headers_from_untrusted_peers() {
+        let config = trusting("x-forwarded-for");
+        let spoofed = headers("x-forwarded-for", &["198.51.100.7"]);
+        assert_eq!(
+            resolve(&config, tcp("203.0.113.9:4000"), &spoofed),
+            ip("203.0.113.9")
+        );
+        let spoofed = headers("forwarded", &["for=198.51.100.7"]);
+        assert_eq!(
+            resolve(&trusting("forwarded"), tcp("203.0.113.9:4000"), &spoofed),
+            ip("203.0.113.9")
+        );
+        // Without trusted proxies nobody's headers are believed
+        assert_eq!(
+            resolve(
+                &ClientIpConfig::default(),
+                tcp("10.0.0.1:4000"),
+                &headers("x-forwarded-for", &["198.51.100.7"])
+            ),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn steps_over_trusted_proxies_only() {
+        let config = trusting("x-forwarded-for");
+        let chain = headers("x-forwarded-for", &["198.51.100.7, 10.0.0.5"]);
+        assert_eq!(
+            resolve(&config, tcp("10.0.0.1:4000"), &chain),
+            ip("198.51.100.7")
+        );
+        // The client prepended made-up hops, the trusted proxy appended the real address
+        let spoofed = headers(
+            "x-forwarded-for",
+            &["10.0.0.9, 192.0.2.1", "198.51.100.7, 10.0.0.5"],
+        );
+        assert_eq!(
+            resolve(&config, tcp("10.0.0.1:4000"), &spoofed),
+            ip("198.51.100.7")
+        );
+        // Only trusted proxies all the way: the leftmost hop is the client
+        let internal = headers("x-forwarded-for", &["10.1.2.3, 10.0.0.5"]);
+        assert_eq!(
+            resolve(&config, tcp("10.0.0.1:4000"), &internal),
+            ip("10.1.2.3")
+        );
+    }
+
+    #[test]
+    fn reads_forwarded_elements() {
+        let config = trusting("forwarded");
+        let forwarded = headers(
+            "forwarded",
+            &["for=192.0.2.60;proto=https, for=\"[2001:db8::1]:4711\";by=10.0.0.5"],
+        );
+        assert_eq!(
+            resolve(&config, tcp("10.0.0.1:4000"), &forwarded),
+            ip("2001:db8::1")
+        );
+        let spoofed = headers(
+            "forwarded",
+            &[
+                "for=10.0.0.7",
+                "proto=https;For=198.51.100.7",
+                "for=fd00::2",
+            ],
+        );
+        assert_eq!(
+            resolve(&config, tcp("[fd00::1]:4000"), &spoofed),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn obfuscated_hops_hide_everything_in_front_of_them() {
+        let config = trusting("forwarded");
+        for value in [
+            "for=198.51.100.7, for=_hidden",
+            "for=198.51.100.7, for=unknown",
+            "for=198.51.100.7, proto=https",
+        ] {
+            let forwarded = headers("forwarded", &[value]);
+            assert_eq!(
+                resolve(&config, tcp("10.0.0.1:4000"), &forwarded),
+                ip("10.0.0.1"),
+                "{}",
+                value
+            );
+        }
+        // An obfuscated hop in front of the client does not matter
+        let forwarded = headers("forwarded", &["for=_hidden, for=198.51.100.7"]);
+        assert_eq!(
+            resolve(&config, tcp("10.0.0.1:4000"), &forwarded),
+            ip("198.51.100.7")
+        );
+        let garbage = headers("x-forwarded-for", &["198.51.100.7, not-an-ip"]);
+        assert_eq!(
+            resolve(&trusting("x-forwarded-for"), tcp("10.0.0.1:4000"), &garbage),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn believes_proxy_protocol_sources_from_trusted_proxies() {
+        let config = trusting("x-forwarded-for");
+        let proxied = |address: &str| {
+            Some(Peer::Tcp {
+                address: address.parse().unwrap(),
+                proxied: Some("198.51.100.7:51234".parse().unwrap()),
+            })
+        };
+        assert_eq!(
+            resolve(&config, proxied("10.0.0.1:4000"), &HeaderMap::new()),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            resolve(&config, proxied("203.0.113.9:4000"), &HeaderMap::new()),
+            ip("203.0.113.9")
+        );
+        // The PROXY source is a client, its own forwarding headers are not believed
+        let spoofed = headers("x-forwarded-for", &["192.0.2.1"]);
+        assert_eq!(
+            resolve(&config, proxied("10.0.0.1:4000"), &spoofed),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn trusts_local_clients_on_the_unix_socket() {
+        let config = trusting("x-forwarded-for");
+        let forwarded = headers("x-forwarded-for", &["198.51.100.7"]);
+        assert_eq!(
+            resolve(&config, Some(Peer::Unix(None)), &forwarded),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            resolve(&config, Some(Peer::Unix(None)), &HeaderMap::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_addresses_with_and_without_ports() {
+        assert_eq!(parse_ip("192.0.2.7"), ip("192.0.2.7"));
+        assert_eq!(parse_ip("192.0.2.7:8080"), ip("192.0.2.7"));
+        assert_eq!(parse_ip("2001:db8::1"), ip("2001:db8::1"));
+        assert_eq!(parse_ip("[2001:db8::1]"), ip("2001:db8::1"));
+        assert_eq!(parse_ip("[2001:db8::1]:443"), ip("2001:db8::1"));
+        for node in ["_hidden", "unknown", "", "192.0.2", "192.0.2.7:port"] {
+            assert_eq!(parse_ip(node), None, "{}", node);
+        }
+    }
+
+    #[test]
+    fn applies_allow_and_deny_lists() {
+        let config =
+            config("allow = [\"203.0.113.0/24\"]\ndeny = [\"203.0.113.66\"]\ntrusted_proxies = []");
+        assert!(allowed(&config, ip("203.0.113.7")));
+        assert!(!allowed(&config, ip("203.0.113.66")));
+        assert!(!allowed(&config, ip("198.51.100.7")));
+        assert!(allowed(&config, ip("::ffff:203.0.113.7")));
+        assert!(allowed(&config, None));
+    }
+}
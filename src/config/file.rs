@@ -0,0 +1,888 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::info;
+
+/// Structured configuration loaded from the file named by `GATEWAY_CONFIG`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    #[serde(default)]
+    pub providers: Vec<Arc<ProviderConfig>>,
+    #[serde(default)]
+    pub models: Vec<Arc<ModelConfig>>,
+    #[serde(default)]
+    pub keys: Vec<Arc<KeyConfig>>,
+    #[serde(default)]
+    pub teams: Vec<Arc<TeamConfig>>,
+    #[serde(default)]
+    pub budgets: Vec<Arc<BudgetConfig>>,
+    /// Upstream HTTP client settings per provider name, e.g. `[clients.vllm]`
+    #[serde(default)]
+    pub clients: HashMap<String, ClientConfig>,
+    #[serde(default)]
+    pub guardrails: Vec<Arc<GuardrailConfig>>,
+    /// Active health checks and readiness, the `[health]` table
+    #[serde(default)]
+    pub health: HealthConfig,
+    /// Cross-origin requests from browsers, the `[cors]` table
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// Client address resolution behind proxies and IP access rules, the `[client_ip]` table
+    #[serde(default)]
+    pub client_ip: ClientIpConfig,
+}
+
+/// An OpenAI-compatible provider defined entirely by configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// Name used in the `x-provider` header
+    pub name: String,
+    /// Upstream base URL, e.g. `https://api.deepseek.com`
+    pub base_url: String,
+    /// Prefix removed from the incoming path, e.g. `/v1` when the base URL already ends in `/v1`
+    pub strip_path_prefix: Option<String>,
+    /// Prefix prepended to the path after stripping, e.g. `/api`
+    pub path_prefix: Option<String>,
+    /// Header carrying the API key
+    #[serde(default = "default_auth_header")]
+    pub auth_header: String,
+    /// Format of the auth header value, `{key}` is replaced with the API key
+    #[serde(default = "default_auth_format")]
+    pub auth_format: String,
+    /// Static API key used instead of the caller's key
+    pub api_key: Option<String>,
+    /// Environment variable holding a static API key
+    pub api_key_env: Option<String>,
+    /// Whether requests without any API key are rejected
+    #[serde(default = "default_true")]
+    pub auth_required: bool,
+    /// Extra static headers sent with every request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Prefix removed from the `model` field, e.g. `deepseek/`
+    pub strip_model_prefix: Option<String>,
+}
+
+impl ProviderConfig {
+    /// The configured static API key, if any
+    pub fn static_api_key(&self) -> Option<String> {
+        self.api_key.clone().or_else(|| {
+            self.api_key_env
+                .as_ref()
+                .and_then(|var| std::env::var(var).ok())
+        })
+    }
+}
+
+/// Model metadata or alias shown in the `/v1/models` catalog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    /// Model ID as listed in the catalog and sent by callers
+    pub id: String,
+    /// Provider serving the model
+    pub provider: String,
+    /// Upstream model name, set when `id` is an alias
+    pub model: Option<String>,
+    /// Maximum context window in tokens
+    pub context_window: Option<u64>,
+    /// Supported modalities, e.g. `["text", "image"]`
+    #[serde(default)]
+    pub modalities: Vec<String>,
+    /// Price in USD per million input tokens
+    pub input_price: Option<f64>,
+    /// Price in USD per million output tokens
+    pub output_price: Option<f64>,
+    /// Whether the model is deprecated
+    #[serde(default)]
+    pub deprecated: bool,
+    /// Date the model is retired upstream, e.g. `2025-06-30`
+    pub deprecation_date: Option<String>,
+}
+
+impl ModelConfig {
+    /// Upstream model name the entry refers to
+    pub fn upstream_model(&self) -> &str {
+        self.model.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// A gateway API key and the providers and models it may see
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyConfig {
+    /// Name used in logs
+    pub name: String,
+    /// The key sent by callers as `Authorization: Bearer <key>`
+    pub key: Option<String>,
+    /// Environment variable holding the key
+    pub key_env: Option<String>,
+    /// SHA-256 of the key as lowercase hex, how keys written through the admin API are stored
+    pub key_hash: Option<String>,
+    /// Last characters of a hashed key, shown in listings
+    pub key_suffix: Option<String>,
+    /// Team the key belongs to, the team's restrictions apply in addition to the key's own
+    pub team: Option<String>,
+    /// Allowed providers, empty allows all
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// Allowed model IDs, a trailing `*` matches any suffix, empty allows all
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// PII handling for requests made with this key, overriding the action of `pii` guardrails
+    pub pii: Option<PiiPolicy>,
+    /// Client certificate names identifying callers over mutual TLS, matched against the
+    /// subject common name and DNS and URI alternative names, a trailing `*` matches any suffix
+    #[serde(default)]
+    pub client_certs: Vec<String>,
+    /// Origins browsers may use this key from, replacing `[cors] allowed_origins` for its
+    /// requests. Requests with another `Origin` are refused.
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+}
+
+impl KeyConfig {
+    /// The configured key value, if any
+    pub fn secret(&self) -> Option<String> {
+        self.key.clone().or_else(|| {
+            self.key_env
+                .as_ref()
+                .and_then(|var| std::env::var(var).ok())
+        })
+    }
+
+    /// Whether a caller's bearer token is this key, `token_hash` being its `hash_key`
+    pub fn matches_token(&self, token: &str, token_hash: &str) -> bool {
+        match self.secret() {
+            Some(secret) => secret == token,
+            None => self
+                .key_hash
+                .as_deref()
+                .is_some_and(|hash| hash.eq_ignore_ascii_case(token_hash)),
+        }
+    }
+
+    /// The entry with its plaintext `key` replaced by `key_hash` and `key_suffix`
+    pub fn hashed(&self) -> Self {
+        let mut hashed = self.clone();
+        if let Some(key) = hashed.key.take() {
+            hashed.key_hash = Some(hash_key(&key));
+            hashed.key_suffix = Some(key_suffix(&key));
+        }
+        hashed
+    }
+
+    /// Last characters of the key, safe to show in listings
+    pub fn suffix(&self) -> Option<String> {
+        self.key
+            .as_deref()
+            .map(key_suffix)
+            .or_else(|| self.key_suffix.clone())
+    }
+
+    /// Whether this key may use `model` on `provider`
+    pub fn allows(&self, provider: &str, model: &str) -> bool {
+        allowed(&self.providers, &self.models, provider, model)
+    }
+
+    /// Whether this key may use `provider`
+    pub fn allows_provider(&self, provider: &str) -> bool {
+        provider_allowed(&self.providers, provider)
+    }
+
+    /// Whether a client certificate with `names` identifies this key
+    pub fn matches_certificate(&self, names: &[String]) -> bool {
+        self.client_certs.iter().any(|pattern| {
+            names.iter().any(|name| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => pattern == name,
+            })
+        })
+    }
+}
+
+/// SHA-256 of a gateway key as lowercase hex
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Last four characters of a key
+fn key_suffix(key: &str) -> String {
+    let mut suffix: Vec<char> = key.chars().rev().take(4).collect();
+    suffix.reverse();
+    suffix.into_iter().collect()
+}
+
+/// Whether allow lists of providers and model patterns admit `model` on `provider`
+fn allowed(providers: &[String], models: &[String], provider: &str, model: &str) -> bool {
+    let model_allowed = models.is_empty()
+        || models
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => model.starts_with(prefix),
+                None => pattern == model,
+            });
+    provider_allowed(providers, provider) && model_allowed
+}
+
+fn provider_allowed(providers: &[String], provider: &str) -> bool {
+    providers.is_empty() || providers.iter().any(|p| p == provider)
+}
+
+/// A group of keys sharing provider and model restrictions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TeamConfig {
+    pub name: String,
+    /// Allowed providers, empty allows all
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// Allowed model IDs, a trailing `*` matches any suffix, empty allows all
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+impl TeamConfig {
+    /// Whether keys of this team may use `model` on `provider`
+    pub fn allows(&self, provider: &str, model: &str) -> bool {
+        allowed(&self.providers, &self.models, provider, model)
+    }
+
+    /// Whether keys of this team may use `provider`
+    pub fn allows_provider(&self, provider: &str) -> bool {
+        provider_allowed(&self.providers, provider)
+    }
+}
+
+/// A spending limit for a key or a team
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    pub name: String,
+    /// Key the budget applies to
+    pub key: Option<String>,
+    /// Team the budget applies to
+    pub team: Option<String>,
+    /// Limit in USD per period
+    pub limit: f64,
+    #[serde(default)]
+    pub period: BudgetPeriod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    Weekly,
+    #[default]
+    Monthly,
+}
+
+/// Upstream HTTP client settings. Unset fields fall back to the `UPSTREAM_*` environment defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Seconds allowed to establish a connection, including the TLS handshake
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for the upstream response headers
+    pub read_timeout: Option<u64>,
+    /// Seconds allowed for a complete non-streaming response
+    pub timeout: Option<u64>,
+    /// Seconds allowed between the response headers and the first chunk of a streamed response
+    pub stream_first_byte_timeout: Option<u64>,
+    /// Seconds allowed between chunks of a streamed response
+    pub stream_idle_timeout: Option<u64>,
+    /// Seconds of upstream silence after which an SSE `: keep-alive` comment is sent to the caller
+    pub stream_keepalive_interval: Option<u64>,
+    /// HTTP version used upstream
+    pub http_version: Option<HttpVersion>,
+    /// Outbound proxy URL, `http://`, `https://`, `socks5://` or `socks5h://`
+    pub proxy: Option<String>,
+    /// PEM file with additional CA certificates to trust
+    pub ca_bundle: Option<String>,
+    /// PEM file with a client certificate chain and private key for mutual TLS
+    pub client_cert: Option<String>,
+    /// Idle connections kept per upstream host
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Largest non-streaming response body in bytes read from the upstream
+    pub max_response_size: Option<usize>,
+}
+
+impl ClientConfig {
+    /// Settings from `self`, with unset fields taken from `defaults`
+    pub fn or(&self, defaults: &ClientConfig) -> ClientConfig {
+        ClientConfig {
+            connect_timeout: self.connect_timeout.or(defaults.connect_timeout),
+            read_timeout: self.read_timeout.or(defaults.read_timeout),
+            timeout: self.timeout.or(defaults.timeout),
+            stream_first_byte_timeout: self
+                .stream_first_byte_timeout
+                .or(defaults.stream_first_byte_timeout),
+            stream_idle_timeout: self.stream_idle_timeout.or(defaults.stream_idle_timeout),
+            stream_keepalive_interval: self
+                .stream_keepalive_interval
+                .or(defaults.stream_keepalive_interval),
+            http_version: self.http_version.or(defaults.http_version),
+            proxy: self.proxy.clone().or_else(|| defaults.proxy.clone()),
+            ca_bundle: self
+                .ca_bundle
+                .clone()
+                .or_else(|| defaults.ca_bundle.clone()),
+            client_cert: self
+                .client_cert
+                .clone()
+                .or_else(|| defaults.client_cert.clone()),
+            pool_max_idle_per_host: self
+                .pool_max_idle_per_host
+                .or(defaults.pool_max_idle_per_host),
+            max_response_size: self.max_response_size.or(defaults.max_response_size),
+        }
+    }
+}
+
+/// HTTP version negotiation for upstream connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    /// HTTP/2 when the server offers it through TLS ALPN, HTTP/1.1 otherwise
+    #[default]
+    Auto,
+    /// HTTP/1.1 only
+    Http1,
+    /// HTTP/2 with prior knowledge, also for plaintext `http://` upstreams
+    Http2,
+}
+
+/// Active health checks of provider targets and the readiness policy of `/readyz`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Seconds between probes of a target, default 30
+    pub interval: Option<u64>,
+    /// Seconds a probe may take, default 5
+    pub timeout: Option<u64>,
+    /// Consecutive failed requests or probes after which a target is unhealthy, default 3
+    pub unhealthy_threshold: Option<u64>,
+    /// Models and providers that each need a healthy target for the gateway to be ready
+    #[serde(default)]
+    pub required: Vec<String>,
+    #[serde(default)]
+    pub probes: Vec<ProbeConfig>,
+}
+
+/// CORS policy applied to every browser request. The default allows any origin, method
+/// and header, as the gateway did before the policy was configurable.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins such as `https://app.example.com`, `https://*.example.com` matches any
+    /// subdomain and `*` any origin. Empty turns CORS off.
+    #[serde(default = "any")]
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in preflight requests, `*` allows the requested method
+    #[serde(default = "any")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in preflight requests, `*` allows the requested headers
+    #[serde(default = "any")]
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts may read besides the CORS-safelisted ones
+    #[serde(default)]
+    pub exposed_headers: Vec<String>,
+    /// Whether browsers may send cookies and HTTP authentication
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// Seconds browsers may cache a preflight response, default 3600
+    pub max_age: Option<u64>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: any(),
+            allowed_methods: any(),
+            allowed_headers: any(),
+            exposed_headers: Vec::new(),
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+fn any() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+/// How the address of a client behind load balancers is found, and which clients may
+/// use the gateway
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientIpConfig {
+    /// Proxies whose forwarding headers and PROXY protocol headers are believed,
+    /// e.g. `10.0.0.0/8`
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpRange>,
+    /// Header carrying the forwarding chain: `x-forwarded-for` (default), `forwarded`, or
+    /// a header such as `x-real-ip` holding the client address
+    pub header: Option<String>,
+    /// Only clients in these ranges may use the gateway, empty allows all
+    #[serde(default)]
+    pub allow: Vec<IpRange>,
+    /// Clients in these ranges are refused, even when `allow` contains them
+    #[serde(default)]
+    pub deny: Vec<IpRange>,
+}
+
+/// An address or CIDR range such as `192.0.2.7` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct IpRange(IpNet);
+
+impl IpRange {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        // IPv4 clients reach IPv6 listeners as mapped addresses such as ::ffff:192.0.2.7
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*ip),
+            IpAddr::V4(_) => *ip,
+        };
+        self.0.contains(&ip)
+    }
+}
+
+impl TryFrom<String> for IpRange {
+    type Error = String;
+
+    fn try_from(range: String) -> Result<Self, Self::Error> {
+        range
+            .parse::<IpNet>()
+            .or_else(|_| range.parse::<IpAddr>().map(IpNet::from))
+            .map(|net| IpRange(net.trunc()))
+            .map_err(|_| format!("invalid IP address or CIDR range: {}", range))
+    }
+}
+
+/// Active health check of one provider target, a `[[health.probes]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProbeConfig {
+    pub provider: String,
+    #[serde(default)]
+    pub kind: ProbeKind,
+    /// `host:port` a `tcp` probe connects to, taken from the provider's base URL when unset
+    pub address: Option<String>,
+    /// Seconds between probes, overriding `[health] interval`
+    pub interval: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeKind {
+    /// `GET /v1/models` through the provider, healthy on a 2xx response
+    #[default]
+    Models,
+    /// Open a TCP connection to the provider
+    Tcp,
+}
+
+impl std::str::FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "http1" => Ok(Self::Http1),
+            "http2" => Ok(Self::Http2),
+            other => Err(format!("unknown HTTP version {}", other)),
+        }
+    }
+}
+
+/// A content check run on requests before they reach the provider, on responses, or both
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuardrailConfig {
+    /// Name used in logs, errors and the `x-guardrails` response header
+    pub name: String,
+    /// Check to run
+    pub check: GuardrailCheck,
+    /// When the check runs
+    #[serde(default)]
+    pub stage: GuardrailStage,
+    /// What happens when the check matches
+    #[serde(default)]
+    pub action: GuardrailAction,
+    /// Providers the check applies to, empty applies to all
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// `deny_list`: regular expressions
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// `deny_list`: case-insensitive whole words or phrases
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// `max_length`: maximum characters of message content
+    pub max_chars: Option<usize>,
+    /// `pii`: entity types to detect, empty detects all
+    #[serde(default)]
+    pub entities: Vec<String>,
+    /// `pii`: put the original values back in place of placeholders in the response
+    #[serde(default)]
+    pub restore: bool,
+    /// `prompt_injection`: score between 0 and 1 at which content counts as an attack
+    pub threshold: Option<f64>,
+    /// `json_schema`: inline JSON schema
+    pub schema: Option<serde_json::Value>,
+    /// `json_schema`: file holding the JSON schema
+    pub schema_file: Option<String>,
+    /// `moderation`: moderation endpoint accepting OpenAI `/v1/moderations` requests
+    pub url: Option<String>,
+    /// `moderation`: environment variable holding the moderation API key
+    pub api_key_env: Option<String>,
+    /// `moderation`: seconds to wait for the moderation service
+    pub timeout: Option<u64>,
+    /// `moderation`: let content through when the moderation service fails
+    #[serde(default = "default_true")]
+    pub fail_open: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailCheck {
+    DenyList,
+    MaxLength,
+    Pii,
+    JsonSchema,
+    Moderation,
+    PromptInjection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailStage {
+    #[default]
+    Request,
+    Response,
+    Both,
+}
+
+impl GuardrailStage {
+    pub fn includes(self, stage: GuardrailStage) -> bool {
+        self == GuardrailStage::Both || self == stage
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GuardrailStage::Request => "request",
+            GuardrailStage::Response => "response",
+            GuardrailStage::Both => "both",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailAction {
+    /// Reject the request or response
+    #[default]
+    Block,
+    /// Mask the matched content and continue
+    Redact,
+    /// Log and report the match and continue
+    Flag,
+    /// Only write the audit record and continue
+    Log,
+}
+
+impl GuardrailAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GuardrailAction::Block => "block",
+            GuardrailAction::Redact => "redact",
+            GuardrailAction::Flag => "flag",
+            GuardrailAction::Log => "log",
+        }
+    }
+}
+
+/// Per-key override for `pii` guardrails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiPolicy {
+    /// Skip PII detection
+    Off,
+    /// Log and report PII and send it unchanged
+    Flag,
+    /// Mask PII with placeholders
+    Redact,
+    /// Mask PII with placeholders and restore the original values in the response
+    Restore,
+    /// Reject requests containing PII
+    Block,
+}
+
+impl PiiPolicy {
+    /// Guardrail action the policy maps to, `None` when PII checks are off
+    pub fn action(self) -> Option<GuardrailAction> {
+        match self {
+            PiiPolicy::Off => None,
+            PiiPolicy::Flag => Some(GuardrailAction::Flag),
+            PiiPolicy::Redact | PiiPolicy::Restore => Some(GuardrailAction::Redact),
+            PiiPolicy::Block => Some(GuardrailAction::Block),
+        }
+    }
+}
+
+fn default_auth_header() -> String {
+    "authorization".to_string()
+}
+
+fn default_auth_format() -> String {
+    "Bearer {key}".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl FileConfig {
+    /// Read the config file without validating it
+    pub fn read(path: &str) -> Result<Self, String> {
+        info!("Loading gateway configuration file: {}", path);
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config file {}: {}", path, e))
+    }
+
+    /// Replace keys, teams, models and budgets with the `managed` entries of the same name
+    /// and add the others
+    pub fn merge(&mut self, managed: FileConfig) {
+        fn merge<T>(entries: &mut Vec<Arc<T>>, managed: Vec<Arc<T>>, name: fn(&T) -> &str) {
+            for entry in managed {
+                match entries.iter_mut().find(|e| name(e) == name(&entry)) {
+                    Some(existing) => *existing = entry,
+                    None => entries.push(entry),
+                }
+            }
+        }
+        merge(&mut self.keys, managed.keys, |k| &k.name);
+        merge(&mut self.teams, managed.teams, |t| &t.name);
+        merge(&mut self.models, managed.models, |m| &m.id);
+        merge(&mut self.budgets, managed.budgets, |b| &b.name);
+    }
+
+    /// Normalize names and check references and check settings
+    pub fn validate(&mut self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for provider in &mut self.providers {
+            let provider = Arc::make_mut(provider);
+            provider.name = provider.name.to_lowercase();
+            provider.base_url = provider.base_url.trim_end_matches('/').to_string();
+            if !names.insert(provider.name.clone()) {
+                return Err(format!(
+                    "Duplicate provider name in config: {}",
+                    provider.name
+                ));
+            }
+        }
+
+        let mut models = HashSet::new();
+        let mut aliases = HashSet::new();
+        for model in &mut self.models {
+            let model = Arc::make_mut(model);
+            model.provider = model.provider.to_lowercase();
+            if model.model.is_some() && !aliases.insert(model.id.clone()) {
+                return Err(format!("Duplicate model alias in config: {}", model.id));
+            }
+            if !models.insert((model.provider.clone(), model.id.clone())) {
+                return Err(format!(
+                    "Duplicate model in config: {} ({})",
+                    model.id, model.provider
+                ));
+            }
+        }
+
+        self.clients = std::mem::take(&mut self.clients)
+            .into_iter()
+            .map(|(name, client)| (name.to_lowercase(), client))
+            .collect();
+
+        let mut guardrails = HashSet::new();
+        for guardrail in &mut self.guardrails {
+            let guardrail = Arc::make_mut(guardrail);
+            if !guardrails.insert(guardrail.name.clone()) {
+                return Err(format!(
+                    "Duplicate guardrail name in config: {}",
+                    guardrail.name
+                ));
+            }
+            for provider in &mut guardrail.providers {
+                *provider = provider.to_lowercase();
+            }
+            let missing = match guardrail.check {
+                GuardrailCheck::DenyList => {
+                    guardrail.patterns.is_empty() && guardrail.keywords.is_empty()
+                }
+                GuardrailCheck::MaxLength => guardrail.max_chars.is_none(),
+                GuardrailCheck::Pii => false,
+                GuardrailCheck::JsonSchema => {
+                    guardrail.schema.is_none() && guardrail.schema_file.is_none()
+                }
+                GuardrailCheck::Moderation => guardrail.url.is_none(),
+                GuardrailCheck::PromptInjection => false,
+            };
+            if missing {
+                return Err(format!(
+                    "Guardrail {} is missing the settings its {:?} check needs",
+                    guardrail.name, guardrail.check
+                ));
+            }
+            let redactable = matches!(
+                guardrail.check,
+                GuardrailCheck::DenyList | GuardrailCheck::Pii
+            );
+            if guardrail.action == GuardrailAction::Redact && !redactable {
+                return Err(format!(
+                    "Guardrail {} cannot redact, only deny_list and pii checks can",
+                    guardrail.name
+                ));
+            }
+            if guardrail.check == GuardrailCheck::PromptInjection
+                && guardrail.stage != GuardrailStage::Request
+            {
+                return Err(format!(
+                    "Guardrail {} checks prompts for injection and can only run on requests",
+                    guardrail.name
+                ));
+            }
+            if guardrail
+                .threshold
+                .is_some_and(|threshold| !(0.0..=1.0).contains(&threshold))
+            {
+                return Err(format!(
+                    "Guardrail {} threshold must be between 0 and 1",
+                    guardrail.name
+                ));
+            }
+            if guardrail.restore
+                && (guardrail.check != GuardrailCheck::Pii
+                    || guardrail.action != GuardrailAction::Redact)
+            {
+                return Err(format!(
+                    "Guardrail {} cannot restore, only redacting pii checks can",
+                    guardrail.name
+                ));
+            }
+        }
+
+        for key in &mut self.keys {
+            let key = Arc::make_mut(key);
+            if key.secret().is_none() && key.key_hash.is_none() && key.client_certs.is_empty() {
+                return Err(format!(
+                    "Key {} in config has no key, key_env, key_hash or client_certs value",
+                    key.name
+                ));
+            }
+            for provider in &mut key.providers {
+                *provider = provider.to_lowercase();
+            }
+            for origin in &mut key.cors_origins {
+                *origin = normalize_origin(origin);
+            }
+        }
+
+        let mut teams = HashSet::new();
+        for team in &mut self.teams {
+            let team = Arc::make_mut(team);
+            if !teams.insert(team.name.clone()) {
+                return Err(format!("Duplicate team name in config: {}", team.name));
+            }
+            for provider in &mut team.providers {
+                *provider = provider.to_lowercase();
+            }
+        }
+
+        let mut keys = HashSet::new();
+        for key in &self.keys {
+            if !keys.insert(key.name.as_str()) {
+                return Err(format!("Duplicate key name in config: {}", key.name));
+            }
+            if let Some(team) = key.team.as_ref().filter(|team| !teams.contains(*team)) {
+                return Err(format!("Key {} belongs to unknown team {}", key.name, team));
+            }
+        }
+
+        let mut budgets = HashSet::new();
+        for budget in &self.budgets {
+            if !budgets.insert(budget.name.as_str()) {
+                return Err(format!("Duplicate budget name in config: {}", budget.name));
+            }
+            match (&budget.key, &budget.team) {
+                (Some(key), None) if keys.contains(key.as_str()) => {}
+                (None, Some(team)) if teams.contains(team) => {}
+                (Some(_), None) | (None, Some(_)) => {
+                    return Err(format!(
+                        "Budget {} refers to an unknown key or team",
+                        budget.name
+                    ))
+                }
+                _ => {
+                    return Err(format!(
+                        "Budget {} must apply to exactly one key or team",
+                        budget.name
+                    ))
+                }
+            }
+            if budget.limit.is_nan() || budget.limit < 0.0 {
+                return Err(format!("Budget {} limit must not be negative", budget.name));
+            }
+        }
+
+        let health = &mut self.health;
+        if health.interval == Some(0) || health.probes.iter().any(|p| p.interval == Some(0)) {
+            return Err("Health probe interval must be at least one second".to_string());
+        }
+        if health.unhealthy_threshold == Some(0) {
+            return Err("Health unhealthy_threshold must be at least 1".to_string());
+        }
+        let mut probed = HashSet::new();
+        for probe in &mut health.probes {
+            probe.provider = probe.provider.to_lowercase();
+            if !probed.insert(probe.provider.clone()) {
+                return Err(format!(
+                    "Duplicate health probe for provider {}",
+                    probe.provider
+                ));
+            }
+        }
+
+        if let Some(header) = &mut self.client_ip.header {
+            *header = header.to_lowercase();
+            if axum::http::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                return Err(format!("Invalid client_ip header name: {}", header));
+            }
+        }
+
+        let cors = &mut self.cors;
+        for origin in &mut cors.allowed_origins {
+            *origin = normalize_origin(origin);
+        }
+        if cors.allow_credentials && cors.allowed_origins.iter().any(|o| o == "*") {
+            return Err(
+                "CORS allow_credentials needs explicit allowed_origins instead of *".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Origins compare lowercase and without a trailing slash, as browsers send them
+fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_lowercase()
+}
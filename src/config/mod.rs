@@ -0,0 +1,440 @@
+use crate::server::tls::ClientCertificate;
+use axum::http::{header::AUTHORIZATION, Request};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+mod file;
+
+pub use file::{
+    BudgetConfig, ClientConfig, ClientIpConfig, CorsConfig, FileConfig, GuardrailAction,
+    GuardrailCheck, GuardrailConfig, GuardrailStage, HealthConfig, HttpVersion, IpRange, KeyConfig,
+    ModelConfig, PiiPolicy, ProbeConfig, ProbeKind, ProviderConfig, TeamConfig,
+};
+
+pub struct AppConfig {
+    pub port: u16,
+    /// Comma-separated addresses the gateway listens on, e.g. `0.0.0.0,::`
+    pub host: String,
+    pub worker_threads: usize,
+    pub max_connections: usize,
+    pub tcp_keepalive_interval: u64,
+    pub tcp_nodelay: bool,
+    pub buffer_size: usize,
+    pub max_body_size: usize,
+    pub route_body_sizes: Vec<(String, usize)>,
+    pub azure_endpoint: Option<String>,
+    /// Endpoints besides `azure_endpoint` that callers may select with `x-azure-endpoint`
+    pub azure_allowed_endpoints: Vec<String>,
+    pub azure_api_version: String,
+    pub azure_deployments: HashMap<String, String>,
+    pub vertex_project: Option<String>,
+    pub vertex_location: String,
+    pub vertex_token_uri: Option<String>,
+    pub google_credentials: Option<String>,
+    pub ollama_base_url: String,
+    pub ollama_admin_api: bool,
+    pub providers: Vec<Arc<ProviderConfig>>,
+    pub models: Vec<Arc<ModelConfig>>,
+    pub keys: Vec<Arc<KeyConfig>>,
+    pub teams: Vec<Arc<TeamConfig>>,
+    pub budgets: Vec<Arc<BudgetConfig>>,
+    pub model_catalog_ttl: u64,
+    pub model_catalog_providers: Option<Vec<String>>,
+    pub client: ClientConfig,
+    pub clients: HashMap<String, ClientConfig>,
+    pub guardrails: Vec<Arc<GuardrailConfig>>,
+    pub health: HealthConfig,
+    pub cors: CorsConfig,
+    pub client_ip: ClientIpConfig,
+    /// Whether TCP connections start with a PROXY protocol v1 or v2 header
+    pub proxy_protocol: bool,
+    /// Seconds `/readyz` reports not ready after a shutdown signal before the listeners close
+    pub shutdown_delay: u64,
+    /// Seconds in-flight requests and streams may take after the listeners close
+    pub shutdown_timeout: u64,
+}
+
+/// Admin API listener settings, the API is enabled when `ADMIN_PORT` is set
+pub struct AdminConfig {
+    pub port: u16,
+    pub host: String,
+    pub token: String,
+    /// SQLite database holding keys, teams, models and budgets managed through the API
+    pub database: String,
+}
+
+impl AdminConfig {
+    pub fn from_env() -> Option<Self> {
+        dotenv::dotenv().ok();
+        let port = env::var("ADMIN_PORT")
+            .ok()?
+            .parse()
+            .expect("ADMIN_PORT must be a number");
+        Some(Self {
+            port,
+            host: env::var("ADMIN_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
+            token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty())
+                .expect("ADMIN_TOKEN must be set when ADMIN_PORT is set"),
+            database: env::var("ADMIN_DATABASE").unwrap_or_else(|_| "gateway.db".to_string()),
+        })
+    }
+}
+
+impl AppConfig {
+    /// Load the environment and config file settings, with `managed` entries from the
+    /// admin API replacing config file entries of the same name
+    pub fn load(managed: FileConfig) -> Result<Self, String> {
+        info!("Loading environment configuration");
+        dotenv::dotenv().ok();
+
+        // Optimize thread count based on CPU cores
+        let cpu_count = num_cpus::get();
+        debug!("Detected {} CPU cores", cpu_count);
+
+        let default_workers = if cpu_count <= 4 {
+            cpu_count * 2
+        } else {
+            cpu_count + 4
+        };
+        debug!("Calculated default worker threads: {}", default_workers);
+
+        let mut file = match env::var("GATEWAY_CONFIG") {
+            Ok(path) => FileConfig::read(&path)?,
+            Err(_) => FileConfig::default(),
+        };
+        file.merge(managed);
+        file.validate()?;
+
+        let config = Self {
+            port: env::var("PORT")
+                .unwrap_or_else(|_| "3000".to_string())
+                .parse()
+                .expect("PORT must be a number"),
+            host: env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
+            worker_threads: env::var("WORKER_THREADS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default_workers),
+            max_connections: env::var("MAX_CONNECTIONS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10_000),
+            tcp_keepalive_interval: env::var("TCP_KEEPALIVE_INTERVAL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            tcp_nodelay: env::var("TCP_NODELAY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(true),
+            buffer_size: env::var("BUFFER_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(8 * 1024), // 8KB default
+            max_body_size: env::var("MAX_BODY_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(32 * 1024 * 1024), // 32MB default
+            route_body_sizes: route_body_sizes(
+                &env::var("MAX_BODY_SIZE_ROUTES")
+                    .unwrap_or_else(|_| DEFAULT_ROUTE_BODY_SIZES.to_string()),
+            ),
+            azure_endpoint: env::var("AZURE_OPENAI_ENDPOINT").ok(),
+            azure_allowed_endpoints: env::var("AZURE_OPENAI_ALLOWED_ENDPOINTS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|e| e.trim().trim_end_matches('/').to_string())
+                        .filter(|e| !e.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            azure_api_version: env::var("AZURE_OPENAI_API_VERSION")
+                .unwrap_or_else(|_| "2024-10-21".to_string()),
+            azure_deployments: env::var("AZURE_OPENAI_DEPLOYMENTS")
+                .map(|v| parse_key_value_list(&v))
+                .unwrap_or_default(),
+            vertex_project: env::var("VERTEX_PROJECT").ok(),
+            vertex_location: env::var("VERTEX_LOCATION")
+                .unwrap_or_else(|_| "us-central1".to_string()),
+            vertex_token_uri: env::var("VERTEX_TOKEN_URI").ok(),
+            google_credentials: env::var("GOOGLE_APPLICATION_CREDENTIALS")
+                .ok()
+                .and_then(|path| match std::fs::read_to_string(&path) {
+                    Ok(credentials) => Some(credentials),
+                    Err(e) => {
+                        warn!("Failed to read Google credentials from {}: {}", path, e);
+                        None
+                    }
+                }),
+            ollama_base_url: env::var("OLLAMA_BASE_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|_| "http://localhost:11434".to_string()),
+            ollama_admin_api: env::var("OLLAMA_ADMIN_API")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            providers: file.providers,
+            models: file.models,
+            keys: file.keys,
+            teams: file.teams,
+            budgets: file.budgets,
+            model_catalog_ttl: env::var("MODEL_CATALOG_TTL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(300),
+            model_catalog_providers: env::var("MODEL_CATALOG_PROVIDERS").ok().map(|v| {
+                v.split(',')
+                    .map(|p| p.trim().to_lowercase())
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            }),
+            client: ClientConfig {
+                connect_timeout: env::var("UPSTREAM_CONNECT_TIMEOUT")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                read_timeout: env::var("UPSTREAM_READ_TIMEOUT")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                timeout: env::var("UPSTREAM_TIMEOUT")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                stream_first_byte_timeout: env::var("UPSTREAM_STREAM_FIRST_BYTE_TIMEOUT")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                stream_idle_timeout: env::var("UPSTREAM_STREAM_IDLE_TIMEOUT")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                stream_keepalive_interval: env::var("STREAM_KEEPALIVE_INTERVAL")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                http_version: env::var("UPSTREAM_HTTP_VERSION").ok().map(|v| {
+                    v.parse()
+                        .expect("UPSTREAM_HTTP_VERSION must be auto, http1 or http2")
+                }),
+                proxy: env::var("UPSTREAM_PROXY").ok(),
+                ca_bundle: env::var("UPSTREAM_CA_BUNDLE").ok(),
+                client_cert: env::var("UPSTREAM_CLIENT_CERT").ok(),
+                pool_max_idle_per_host: env::var("UPSTREAM_POOL_MAX_IDLE_PER_HOST")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                max_response_size: env::var("UPSTREAM_MAX_RESPONSE_SIZE")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+            },
+            clients: file.clients,
+            guardrails: file.guardrails,
+            health: file.health,
+            cors: file.cors,
+            client_ip: file.client_ip,
+            proxy_protocol: env::var("PROXY_PROTOCOL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(false),
+            shutdown_delay: env::var("SHUTDOWN_DELAY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+            shutdown_timeout: env::var("SHUTDOWN_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+        };
+
+        info!(
+            "Configuration loaded: port={}, host={}",
+            config.port, config.host
+        );
+        debug!(
+            "Advanced settings: workers={}, max_conn={}, buffer_size={}, max_body_size={}",
+            config.worker_threads, config.max_connections, config.buffer_size, config.max_body_size
+        );
+        debug!(
+            "Configured providers: {}, models: {}, keys: {}",
+            config.providers.len(),
+            config.models.len(),
+            config.keys.len()
+        );
+
+        Ok(config)
+    }
+}
+
+/// TLS termination settings, enabled when `TLS_CERT` is set
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert: String,
+    /// PEM private key
+    pub key: String,
+    /// PEM CA certificates verifying client certificates, enables mutual TLS
+    pub client_ca: Option<String>,
+    /// Whether connections without a client certificate are refused when `client_ca` is set
+    pub client_auth_required: bool,
+    /// Port of separate TLS listeners, `PORT` itself serves TLS when unset
+    pub port: Option<u16>,
+    /// Seconds between checks of the certificate files for changes
+    pub reload_interval: u64,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Option<Self> {
+        dotenv::dotenv().ok();
+        let cert = env::var("TLS_CERT").ok()?;
+        Some(Self {
+            cert,
+            key: env::var("TLS_KEY").expect("TLS_KEY must be set when TLS_CERT is set"),
+            client_ca: env::var("TLS_CLIENT_CA").ok(),
+            client_auth_required: match env::var("TLS_CLIENT_AUTH").as_deref() {
+                Ok("required") | Err(_) => true,
+                Ok("optional") => false,
+                Ok(other) => panic!(
+                    "TLS_CLIENT_AUTH must be required or optional, got {}",
+                    other
+                ),
+            },
+            port: env::var("TLS_PORT")
+                .ok()
+                .map(|v| v.parse().expect("TLS_PORT must be a number")),
+            reload_interval: env::var("TLS_RELOAD_INTERVAL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+        })
+    }
+}
+
+/// Unix domain socket listener, enabled when `UNIX_SOCKET` is set
+pub struct UnixSocketConfig {
+    pub path: String,
+    /// File mode of the socket, e.g. `0o660` to limit access to the owner and group
+    pub mode: Option<u32>,
+}
+
+impl UnixSocketConfig {
+    pub fn from_env() -> Option<Self> {
+        dotenv::dotenv().ok();
+        let path = env::var("UNIX_SOCKET")
+            .ok()
+            .filter(|path| !path.is_empty())?;
+        Some(Self {
+            path,
+            mode: env::var("UNIX_SOCKET_MODE").ok().map(|mode| {
+                u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                    .expect("UNIX_SOCKET_MODE must be an octal file mode such as 660")
+            }),
+        })
+    }
+}
+
+impl AppConfig {
+    /// Body size limit for a request path, the longest matching route prefix wins
+    pub fn max_body_size_for(&self, path: &str) -> usize {
+        self.route_body_sizes
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map(|(_, limit)| *limit)
+            .unwrap_or(self.max_body_size)
+    }
+
+    /// Upstream HTTP client settings for a provider, falling back to the environment defaults
+    pub fn client_config(&self, provider: &str) -> Option<ClientConfig> {
+        self.clients
+            .get(provider)
+            .map(|client| client.or(&self.client))
+    }
+
+    /// Configured alias for a model ID, if any
+    pub fn model_alias(&self, id: &str) -> Option<&ModelConfig> {
+        self.models
+            .iter()
+            .map(Arc::as_ref)
+            .find(|model| model.model.is_some() && model.id == id)
+    }
+
+    /// Configured key matching the caller's `Authorization` bearer token
+    pub fn key_for(&self, token: &str) -> Option<&KeyConfig> {
+        let token_hash = file::hash_key(token);
+        self.keys
+            .iter()
+            .map(Arc::as_ref)
+            .find(|key| key.matches_token(token, &token_hash))
+    }
+
+    /// Whether `key` and its team may use `model` on `provider`
+    pub fn allows(&self, key: &KeyConfig, provider: &str, model: &str) -> bool {
+        let team = key
+            .team
+            .as_ref()
+            .and_then(|name| self.teams.iter().find(|team| &team.name == name));
+        key.allows(provider, model) && team.is_none_or(|team| team.allows(provider, model))
+    }
+
+    /// Whether `key` and its team may use `provider`, for requests that name no model
+    pub fn allows_provider(&self, key: &KeyConfig, provider: &str) -> bool {
+        let team = key
+            .team
+            .as_ref()
+            .and_then(|name| self.teams.iter().find(|team| &team.name == name));
+        key.allows_provider(provider) && team.is_none_or(|team| team.allows_provider(provider))
+    }
+
+    /// Configured key the caller identifies with: the key in the `Authorization` header or,
+    /// when the bearer token is not a gateway key, the key matching the client certificate
+    /// of a mutual TLS connection. Callers may send their own provider key as the bearer
+    /// token, which must not shed the restrictions of their certificate's key.
+    pub fn key_for_request<B>(&self, request: &Request<B>) -> Option<&KeyConfig> {
+        self.key_for_bearer(request).or_else(|| {
+            let certificate = request.extensions().get::<ClientCertificate>()?;
+            self.keys
+                .iter()
+                .map(Arc::as_ref)
+                .find(|key| key.matches_certificate(&certificate.names))
+        })
+    }
+
+    /// Configured key whose secret is the bearer token in the `Authorization` header
+    pub fn key_for_bearer<B>(&self, request: &Request<B>) -> Option<&KeyConfig> {
+        let token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|auth| auth.strip_prefix("Bearer "))?;
+        self.key_for(token.trim())
+    }
+}
+
+/// OpenAI's upload limits: 25MB audio files, 512MB files
+const DEFAULT_ROUTE_BODY_SIZES: &str = "/v1/audio=26214400,/v1/files=536870912";
+
+/// Parse `prefix=bytes` route limits, sorted longest prefix first
+fn route_body_sizes(value: &str) -> Vec<(String, usize)> {
+    let mut limits: Vec<(String, usize)> = parse_key_value_list(value)
+        .into_iter()
+        .map(|(prefix, limit)| {
+            let limit = limit.parse().unwrap_or_else(|_| {
+                panic!("MAX_BODY_SIZE_ROUTES limit for {} must be a number", prefix)
+            });
+            (prefix, limit)
+        })
+        .collect();
+    limits.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    limits
+}
+
+/// Parse a comma separated list of `key=value` pairs, e.g. `gpt-4o=prod-gpt4o,gpt-4o-mini=mini`
+fn parse_key_value_list(value: &str) -> HashMap<String, String> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let (key, value) = (key.trim(), value.trim());
+            (!key.is_empty() && !value.is_empty()).then(|| (key.to_string(), value.to_string()))
+        })
+        .collect()
+}
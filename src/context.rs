@@ -1,19 +1,41 @@
-use axum::http::HeaderMap;
-use serde_json::Value;
+use axum::http::{Extensions, HeaderMap};
 
+/// Per-request state shared by the proxy and the provider handling the request.
+/// Providers are long-lived and shared, so anything they learn about a request lives here.
 #[derive(Debug, Clone)]
 pub struct RequestContext {
-    pub model: String,
-    pub request_body: Value,
+    /// Incoming gateway path, e.g. `/v1/chat/completions`
+    pub path: String,
+    /// Headers of the incoming request
     pub headers: HeaderMap,
+    /// Model named in the request body, when the body was read
+    pub model: Option<String>,
+    /// Provider-specific request state
+    state: Extensions,
 }
 
 impl RequestContext {
-    pub fn new(model: String, request_body: Value, headers: HeaderMap) -> Self {
+    pub fn new(path: &str, headers: HeaderMap) -> Self {
         Self {
-            model,
-            request_body,
+            path: path.to_string(),
             headers,
+            model: None,
+            state: Extensions::new(),
         }
     }
+
+    /// Value of an incoming request header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|h| h.to_str().ok())
+    }
+
+    /// Store provider-specific state for this request
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, state: T) {
+        self.state.insert(state);
+    }
+
+    /// Provider-specific state stored by `before_request`
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<&T> {
+        self.state.get::<T>()
+    }
 }
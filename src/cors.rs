@@ -0,0 +1,174 @@
+use crate::{
+    config::{AppConfig, CorsConfig},
+    error::AppError,
+    state::AppState,
+};
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+            ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+            ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+            ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+        },
+        HeaderMap, HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::{debug, warn};
+
+const DEFAULT_MAX_AGE: u64 = 3600;
+
+/// Apply the `[cors]` policy, and the `cors_origins` of the request's key, to browser
+/// requests. Preflight requests are answered here without reaching a provider.
+pub async fn handle(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(origin) = request.headers().get(ORIGIN).cloned() else {
+        return next.run(request).await;
+    };
+    let config = &state.config;
+    let cors = &config.cors;
+    let name = origin.to_str().unwrap_or_default().to_lowercase();
+
+    if is_preflight(&request) {
+        // Preflight requests carry no credentials, so any key's origins are accepted here
+        // and the request that follows is checked against its own key
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        if allowed_anywhere(config, &name) {
+            debug!("Answering CORS preflight from {}", name);
+            allow_origin(headers, cors, &cors.allowed_origins, &origin);
+            allow_requested(headers, cors, request.headers());
+        }
+        headers.insert(
+            VARY,
+            HeaderValue::from_static(
+                "origin, access-control-request-method, access-control-request-headers",
+            ),
+        );
+        return response;
+    }
+
+    let key_origins = config
+        .key_for_request(&request)
+        .map(|key| key.cors_origins.clone())
+        .filter(|origins| !origins.is_empty());
+    let origins = match &key_origins {
+        Some(origins) if !matches(origins, &name) => {
+            warn!(
+                "Refusing request from origin {} not allowed for its key",
+                name
+            );
+            return AppError::OriginNotAllowed(name).into_response();
+        }
+        Some(origins) => origins,
+        None => &cors.allowed_origins,
+    };
+    let allowed = matches(origins, &name);
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    // Upstream and provider CORS headers would contradict the gateway's policy
+    let upstream: Vec<_> = headers
+        .keys()
+        .filter(|header| header.as_str().starts_with("access-control-"))
+        .cloned()
+        .collect();
+    for header in upstream {
+        headers.remove(header);
+    }
+    if allowed {
+        allow_origin(headers, cors, origins, &origin);
+        if !cors.exposed_headers.is_empty() {
+            insert_list(
+                headers,
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                &cors.exposed_headers,
+            );
+        }
+    }
+    headers.append(VARY, HeaderValue::from_static("origin"));
+    response
+}
+
+fn is_preflight(request: &Request) -> bool {
+    request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// Whether the global policy or any key allows `origin`
+fn allowed_anywhere(config: &AppConfig, origin: &str) -> bool {
+    matches(&config.cors.allowed_origins, origin)
+        || config
+            .keys
+            .iter()
+            .any(|key| matches(&key.cors_origins, origin))
+}
+
+/// Whether `origin` matches one of `patterns`, where `*` in a pattern matches any
+/// non-empty part of the origin, e.g. `https://*.example.com`
+fn matches(patterns: &[String], origin: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                origin.len() > prefix.len() + suffix.len()
+                    && origin.starts_with(prefix)
+                    && origin.ends_with(suffix)
+            }
+            None => pattern == origin,
+        })
+}
+
+fn allow_origin(
+    headers: &mut HeaderMap,
+    cors: &CorsConfig,
+    origins: &[String],
+    origin: &HeaderValue,
+) {
+    // Credentialed requests need the exact origin, validation rejects `*` with credentials
+    let any = origins.iter().any(|o| o == "*");
+    let value = if any {
+        HeaderValue::from_static("*")
+    } else {
+        origin.clone()
+    };
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, value);
+    if cors.allow_credentials {
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
+
+fn allow_requested(headers: &mut HeaderMap, cors: &CorsConfig, request: &HeaderMap) {
+    let any = |list: &[String]| list.iter().any(|item| item == "*");
+    if any(&cors.allowed_methods) {
+        if let Some(method) = request.get(ACCESS_CONTROL_REQUEST_METHOD) {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, method.clone());
+        }
+    } else {
+        insert_list(headers, ACCESS_CONTROL_ALLOW_METHODS, &cors.allowed_methods);
+    }
+    if any(&cors.allowed_headers) {
+        if let Some(requested) = request.get(ACCESS_CONTROL_REQUEST_HEADERS) {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, requested.clone());
+        }
+    } else {
+        insert_list(headers, ACCESS_CONTROL_ALLOW_HEADERS, &cors.allowed_headers);
+    }
+    headers.insert(
+        ACCESS_CONTROL_MAX_AGE,
+        HeaderValue::from(cors.max_age.unwrap_or(DEFAULT_MAX_AGE)),
+    );
+}
+
+fn insert_list(headers: &mut HeaderMap, name: axum::http::HeaderName, values: &[String]) {
+    if let Ok(value) = HeaderValue::from_str(&values.join(", ")) {
+        headers.insert(name, value);
+    }
+}
@@ -6,7 +6,7 @@ use axum::{
 };
 use http::header::InvalidHeaderValue;
 use http::status::InvalidStatusCode;
-use serde_json::json;
+use serde_json::{json, Value};
 use std::{convert::Infallible, io};
 
 #[derive(Debug, thiserror::Error)]
@@ -20,6 +20,7 @@ pub enum AppError {
     #[error("Axum error: {0}")]
     AxumError(#[from] axum::Error),
 
+    #[allow(dead_code)]
     #[error("Invalid HTTP method")]
     InvalidMethod,
 
@@ -56,89 +57,313 @@ pub enum AppError {
     #[error("Request error: {0}")]
     RequestError(String),
 
+    #[error("Request body exceeds the {0} byte limit")]
+    PayloadTooLarge(usize),
+
+    #[error("Provider did not respond within {0} seconds")]
+    UpstreamTimeout(u64),
+
+    #[error("Content blocked by guardrail {0}")]
+    GuardrailBlocked(String),
+
     #[error("Failed to parse event stream: {0}")]
     EventStreamError(String),
 
     #[error("UTF-8 conversion error: {0}")]
     Utf8Error(#[from] std::string::FromUtf8Error),
+
+    #[error("Origin {0} is not allowed to use this key")]
+    OriginNotAllowed(String),
+
+    #[error("Client {0} is not allowed to use the gateway")]
+    ClientNotAllowed(String),
+
+    #[error("This key is not allowed to use {0}")]
+    NotAllowed(String),
+
+    #[error("{0}")]
+    UpstreamUnavailable(String),
+
+    #[error("Provider response exceeds the {0} byte limit")]
+    ResponseTooLarge(usize),
+
+    #[error("{0} not found")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Storage error: {0}")]
+    StoreError(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match &self {
-            AppError::ReqwestError(e) => (
-                StatusCode::BAD_GATEWAY,
-                format!("Provider request failed: {}", e),
-            ),
-            AppError::IoError(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Internal server error: {}", e),
-            ),
-            AppError::AxumError(e) => (
+impl AppError {
+    /// HTTP status, error type and stable error code returned to callers
+    fn kind(&self) -> (StatusCode, &'static str, &'static str) {
+        match self {
+            AppError::ReqwestError(_) | AppError::UpstreamUnavailable(_) => {
+                (StatusCode::BAD_GATEWAY, API_ERROR, "upstream_unavailable")
+            }
+            AppError::IoError(_) | AppError::AxumError(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Server error: {}", e),
+                API_ERROR,
+                "internal_error",
             ),
-            AppError::InvalidMethod => (StatusCode::BAD_REQUEST, "Invalid HTTP method".to_string()),
+            AppError::InvalidMethod => (StatusCode::BAD_REQUEST, INVALID_REQUEST, "invalid_method"),
             AppError::InvalidStatus(_) => (
                 StatusCode::BAD_GATEWAY,
-                "Invalid status code from provider".to_string(),
+                API_ERROR,
+                "invalid_upstream_status",
             ),
-            AppError::InvalidHeader => {
-                (StatusCode::BAD_REQUEST, "Invalid header value".to_string())
+            AppError::InvalidHeader | AppError::InvalidHeaderValue(_) => {
+                (StatusCode::BAD_REQUEST, INVALID_REQUEST, "invalid_header")
             }
             AppError::UnsupportedProvider => (
                 StatusCode::BAD_REQUEST,
-                "Unsupported AI provider".to_string(),
+                INVALID_REQUEST,
+                "unsupported_provider",
             ),
             AppError::MissingApiKey => (
                 StatusCode::UNAUTHORIZED,
-                "Missing or invalid API key".to_string(),
-            ),
-            AppError::InvalidRequestFormat => (
-                StatusCode::BAD_REQUEST,
-                "Invalid request format".to_string(),
+                "authentication_error",
+                "invalid_api_key",
             ),
+            AppError::InvalidRequestFormat | AppError::RequestError(_) => {
+                (StatusCode::BAD_REQUEST, INVALID_REQUEST, "invalid_request")
+            }
             AppError::UnsupportedModel => {
-                (StatusCode::BAD_REQUEST, "Unsupported model".to_string())
+                (StatusCode::BAD_REQUEST, INVALID_REQUEST, "model_not_found")
             }
-            AppError::JsonError(e) => (
-                StatusCode::BAD_REQUEST,
-                format!("JSON parsing error: {}", e),
-            ),
-            AppError::AwsSigningError(e) => (
+            AppError::JsonError(_) => (StatusCode::BAD_REQUEST, INVALID_REQUEST, "invalid_json"),
+            AppError::AwsSigningError(_) | AppError::AwsParamsError(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("AWS signing error: {}", e),
+                API_ERROR,
+                "signing_failed",
             ),
-            AppError::AwsParamsError(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("AWS params build error: {}", e),
+            AppError::PayloadTooLarge(_) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                INVALID_REQUEST,
+                "payload_too_large",
             ),
-            AppError::InvalidHeaderValue(e) => (
-                StatusCode::BAD_REQUEST,
-                format!("Invalid header value: {}", e),
+            AppError::UpstreamTimeout(_) => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "timeout_error",
+                "upstream_timeout",
             ),
-            AppError::RequestError(e) => (StatusCode::BAD_REQUEST, format!("Request error: {}", e)),
-            AppError::EventStreamError(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to parse event stream: {}", e),
+            AppError::GuardrailBlocked(_) => {
+                (StatusCode::BAD_REQUEST, INVALID_REQUEST, "content_blocked")
+            }
+            AppError::ResponseTooLarge(_) => (
+                StatusCode::BAD_GATEWAY,
+                API_ERROR,
+                "upstream_response_too_large",
+            ),
+            AppError::EventStreamError(_) | AppError::Utf8Error(_) => (
+                StatusCode::BAD_GATEWAY,
+                API_ERROR,
+                "invalid_upstream_response",
             ),
-            AppError::Utf8Error(e) => (
+            AppError::OriginNotAllowed(_) => (
+                StatusCode::FORBIDDEN,
+                "permission_error",
+                "origin_not_allowed",
+            ),
+            AppError::ClientNotAllowed(_) => {
+                (StatusCode::FORBIDDEN, "permission_error", "ip_not_allowed")
+            }
+            AppError::NotAllowed(_) => (
+                StatusCode::FORBIDDEN,
+                "permission_error",
+                "model_not_allowed",
+            ),
+            AppError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found_error", "not_found"),
+            AppError::Conflict(_) => (StatusCode::CONFLICT, INVALID_REQUEST, "conflict"),
+            AppError::InvalidConfig(_) => {
+                (StatusCode::BAD_REQUEST, INVALID_REQUEST, "invalid_config")
+            }
+            AppError::StoreError(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("UTF-8 conversion error: {}", e),
+                API_ERROR,
+                "storage_error",
             ),
-        };
+        }
+    }
 
-        let body = Json(json!({
-            "error": {
-                "message": error_message,
-                "type": format!("{:?}", self),
-            }
-        }));
+    /// Request field or header the error refers to
+    fn param(&self) -> Option<&'static str> {
+        match self {
+            AppError::UnsupportedProvider => Some("x-provider"),
+            AppError::UnsupportedModel => Some("model"),
+            AppError::MissingApiKey => Some("authorization"),
+            AppError::OriginNotAllowed(_) => Some("origin"),
+            AppError::NotAllowed(_) => Some("model"),
+            _ => None,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::ReqwestError(e) => format!("Provider request failed: {}", e),
+            AppError::IoError(e) => format!("Internal server error: {}", e),
+            AppError::AxumError(e) => format!("Server error: {}", e),
+            AppError::InvalidStatus(_) => "Invalid status code from provider".to_string(),
+            AppError::UnsupportedProvider => "Unsupported AI provider".to_string(),
+            AppError::JsonError(e) => format!("JSON parsing error: {}", e),
+            AppError::AwsParamsError(e) => format!("AWS params build error: {}", e),
+            other => other.to_string(),
+        }
+    }
+
+    /// OpenAI-compatible error object for this error
+    pub fn body(&self) -> Value {
+        let (_, error_type, code) = self.kind();
+        error_body(&self.message(), error_type, code, self.param())
+    }
+}
 
-        (status, body).into_response()
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, _, _) = self.kind();
+        (status, Json(self.body())).into_response()
     }
 }
 
+const INVALID_REQUEST: &str = "invalid_request_error";
+const API_ERROR: &str = "api_error";
+
+/// OpenAI-compatible error object
+pub fn error_body(message: &str, error_type: &str, code: &str, param: Option<&str>) -> Value {
+    json!({
+        "error": {
+            "message": message,
+            "type": error_type,
+            "code": code,
+            "param": param,
+        }
+    })
+}
+
+/// Error type for an upstream HTTP status
+fn status_error_type(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        408 | 504 => "timeout_error",
+        429 => "rate_limit_error",
+        529 => "overloaded_error",
+        400..=499 => INVALID_REQUEST,
+        _ => API_ERROR,
+    }
+}
+
+/// Error type for a provider exception name, e.g. Bedrock's `ThrottlingException`
+/// or Gemini's `RESOURCE_EXHAUSTED`
+fn kind_error_type(kind: &str) -> Option<&'static str> {
+    let kind = kind.to_ascii_lowercase().replace('_', "");
+    let error_type = match kind.trim_end_matches("exception") {
+        "validation" | "invalidargument" | "failedprecondition" | "outofrange" => INVALID_REQUEST,
+        "unauthenticated" | "unrecognizedclient" | "expiredtoken" => "authentication_error",
+        "accessdenied" | "permissiondenied" => "permission_error",
+        "resourcenotfound" | "notfound" => "not_found_error",
+        "throttling" | "resourceexhausted" | "servicequotaexceeded" | "toomanyrequests" => {
+            "rate_limit_error"
+        }
+        "modeltimeout" | "deadlineexceeded" => "timeout_error",
+        "serviceunavailable" | "modelnotready" | "unavailable" => "overloaded_error",
+        "modelstreamerror" | "internalserver" | "internal" | "modelerror" => API_ERROR,
+        _ => return None,
+    };
+    Some(error_type)
+}
+
+/// Stable error code for an error type
+fn error_code(error_type: &str) -> &'static str {
+    match error_type {
+        INVALID_REQUEST => "invalid_request",
+        "authentication_error" => "invalid_api_key",
+        "permission_error" => "permission_denied",
+        "not_found_error" => "not_found",
+        "rate_limit_error" => "rate_limit_exceeded",
+        "timeout_error" => "timeout",
+        "overloaded_error" => "overloaded",
+        _ => "upstream_error",
+    }
+}
+
+/// Convert a provider error body into the gateway error schema.
+/// `kind` is the provider's exception name when it is sent outside the body,
+/// and the original error is kept under `provider_error`.
+pub fn provider_error(
+    provider: &str,
+    status: StatusCode,
+    body: &[u8],
+    kind: Option<&str>,
+) -> Value {
+    let original = serde_json::from_slice::<Value>(body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).trim().to_string()));
+    let error = original.get("error").unwrap_or(&original);
+
+    let message = [
+        error.get("message"),
+        original.get("error"),
+        original.get("message"),
+        original.get("Message"),
+        original.get("detail"),
+        Some(&original),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|value| match value {
+        Value::String(message) if !message.is_empty() => Some(message.clone()),
+        Value::Array(details) if !details.is_empty() => Some(
+            details
+                .iter()
+                .map(|d| d.get("msg").and_then(Value::as_str).unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+        _ => None,
+    })
+    .unwrap_or_else(|| {
+        status
+            .canonical_reason()
+            .unwrap_or("Provider request failed")
+            .to_string()
+    });
+
+    // Anthropic errors carry an OpenAI style type, Gemini a gRPC status name
+    let kind = kind
+        .or_else(|| error.get("status").and_then(Value::as_str))
+        .or_else(|| original.get("__type").and_then(Value::as_str));
+    let upstream_type = error
+        .get("type")
+        .and_then(Value::as_str)
+        .filter(|t| t.ends_with("_error") || *t == "content_filter");
+    let error_type = upstream_type
+        .or_else(|| kind.and_then(kind_error_type))
+        .unwrap_or_else(|| status_error_type(status));
+
+    // OpenAI-compatible providers send codes clients already rely on, e.g. `context_length_exceeded`
+    let code = error
+        .get("code")
+        .and_then(Value::as_str)
+        .unwrap_or_else(|| error_code(error_type));
+
+    let mut normalized = error_body(
+        &message,
+        error_type,
+        code,
+        error.get("param").and_then(Value::as_str),
+    );
+    normalized["error"]["provider"] = json!(provider);
+    normalized["error"]["provider_error"] = original;
+    normalized
+}
+
 impl From<Infallible> for AppError {
     fn from(_: Infallible) -> Self {
         unreachable!("Infallible error cannot occur")
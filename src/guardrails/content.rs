@@ -0,0 +1,191 @@
+use serde_json::Value;
+
+/// Text guardrails inspect in a request body: chat message content (plain or text parts),
+/// system prompts, completion prompts and embedding or Responses API inputs
+pub fn request_texts(body: &mut Value) -> Vec<&mut String> {
+    let mut texts = Vec::new();
+    let Value::Object(body) = body else {
+        return texts;
+    };
+    for (key, value) in body.iter_mut() {
+        match key.as_str() {
+            "messages" => {
+                if let Value::Array(messages) = value {
+                    for message in messages {
+                        if let Some(content) = message.get_mut("content") {
+                            content_texts(content, &mut texts);
+                        }
+                    }
+                }
+            }
+            "system" | "prompt" | "input" => match value {
+                Value::Array(items) => {
+                    for item in items {
+                        match item {
+                            Value::String(text) => texts.push(text),
+                            Value::Object(_) => {
+                                if let Some(content) = item.get_mut("content") {
+                                    content_texts(content, &mut texts);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                other => content_texts(other, &mut texts),
+            },
+            _ => {}
+        }
+    }
+    texts
+}
+
+/// Text supplied by users or tools rather than the application: user and tool message
+/// content, tool results, completion prompts and Responses API user input
+pub fn untrusted_texts(body: &mut Value) -> Vec<&mut String> {
+    let mut texts = Vec::new();
+    let Value::Object(body) = body else {
+        return texts;
+    };
+    for (key, value) in body.iter_mut() {
+        match (key.as_str(), value) {
+            ("messages", Value::Array(messages)) => {
+                for message in messages {
+                    let untrusted = matches!(
+                        message.get("role").and_then(Value::as_str),
+                        Some("user" | "tool" | "function")
+                    );
+                    if let Some(content) = message.get_mut("content").filter(|_| untrusted) {
+                        untrusted_content_texts(content, &mut texts);
+                    }
+                }
+            }
+            ("prompt" | "input", Value::Array(items)) => {
+                for item in items {
+                    match item {
+                        Value::String(text) => texts.push(text),
+                        Value::Object(fields) => {
+                            let untrusted = matches!(
+                                fields.get("role").and_then(Value::as_str),
+                                Some("user" | "tool")
+                            );
+                            for (field, value) in fields.iter_mut() {
+                                match (field.as_str(), value) {
+                                    ("content", content) if untrusted => {
+                                        untrusted_content_texts(content, &mut texts)
+                                    }
+                                    ("output", Value::String(output)) => texts.push(output),
+                                    _ => {}
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ("prompt" | "input", other) => content_texts(other, &mut texts),
+            _ => {}
+        }
+    }
+    texts
+}
+
+/// Message content including the content of Anthropic style `tool_result` blocks
+fn untrusted_content_texts<'a>(content: &'a mut Value, texts: &mut Vec<&'a mut String>) {
+    let Value::Array(parts) = content else {
+        return content_texts(content, texts);
+    };
+    for part in parts {
+        if part.get("type").and_then(Value::as_str) == Some("tool_result") {
+            if let Some(result) = part.get_mut("content") {
+                content_texts(result, texts);
+            }
+        } else if let Some(Value::String(text)) = part.get_mut("text") {
+            texts.push(text);
+        }
+    }
+}
+
+/// Text guardrails inspect in a non-streaming response: chat and completion choices,
+/// or Anthropic style content blocks
+pub fn response_texts(body: &mut Value) -> Vec<&mut String> {
+    if body.get("choices").is_some() {
+        return choice_texts(body, "message");
+    }
+    let mut texts = Vec::new();
+    if let Some(content) = body.get_mut("content") {
+        content_texts(content, &mut texts);
+    }
+    texts
+}
+
+/// Text guardrails inspect in a stream chunk, OpenAI choice deltas or Anthropic text deltas,
+/// with the index of the choice or content block each text belongs to
+pub fn chunk_texts(chunk: &mut Value) -> Vec<(u64, &mut String)> {
+    if chunk.get("choices").is_some() {
+        let mut texts = Vec::new();
+        let Some(Value::Array(choices)) = chunk.get_mut("choices") else {
+            return texts;
+        };
+        for (position, choice) in choices.iter_mut().enumerate() {
+            let index = choice
+                .get("index")
+                .and_then(Value::as_u64)
+                .unwrap_or(position as u64);
+            let mut choice_texts = Vec::new();
+            single_choice_texts(choice, "delta", &mut choice_texts);
+            texts.extend(choice_texts.into_iter().map(|text| (index, text)));
+        }
+        return texts;
+    }
+    let index = chunk
+        .get("index")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    match chunk.pointer_mut("/delta/text") {
+        Some(Value::String(text)) => vec![(index, text)],
+        _ => Vec::new(),
+    }
+}
+
+fn choice_texts<'a>(body: &'a mut Value, field: &str) -> Vec<&'a mut String> {
+    let mut texts = Vec::new();
+    let Some(Value::Array(choices)) = body.get_mut("choices") else {
+        return texts;
+    };
+    for choice in choices {
+        single_choice_texts(choice, field, &mut texts);
+    }
+    texts
+}
+
+fn single_choice_texts<'a>(choice: &'a mut Value, field: &str, texts: &mut Vec<&'a mut String>) {
+    let Value::Object(choice) = choice else {
+        return;
+    };
+    for (key, value) in choice.iter_mut() {
+        if key == field {
+            if let Some(content) = value.get_mut("content") {
+                content_texts(content, texts);
+            }
+        } else if key == "text" {
+            if let Value::String(text) = value {
+                texts.push(text);
+            }
+        }
+    }
+}
+
+fn content_texts<'a>(content: &'a mut Value, texts: &mut Vec<&'a mut String>) {
+    match content {
+        Value::String(text) => texts.push(text),
+        Value::Array(parts) => {
+            for part in parts {
+                if let Some(Value::String(text)) = part.get_mut("text") {
+                    texts.push(text);
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,466 @@
+use crate::config::{
+    AppConfig, GuardrailAction, GuardrailCheck, GuardrailConfig, GuardrailStage, PiiPolicy,
+};
+use crate::error::AppError;
+use crate::metrics::Metrics;
+use crate::proxy::client::UpstreamClient;
+use axum::body::Bytes;
+use axum::http::HeaderValue;
+use regex::Regex;
+use serde_json::Value;
+use std::ops::Range;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+mod content;
+pub mod injection;
+mod moderation;
+pub mod pii;
+pub mod stream;
+
+use injection::InjectionDetector;
+use moderation::Moderation;
+use pii::{PiiEntity, PiiVault};
+
+/// Response header listing the guardrails that flagged or redacted content
+pub const GUARDRAILS_HEADER: &str = "x-guardrails";
+
+/// Text that replaces deny list matches
+const REDACTED: &str = "[REDACTED]";
+
+/// Selects the text a check inspects in a request, response or stream chunk
+type Texts = fn(&mut Value) -> Vec<&mut String>;
+
+/// Configured guardrails, compiled once at startup
+pub struct Guardrails {
+    guardrails: Vec<Guardrail>,
+    metrics: Arc<Metrics>,
+}
+
+struct Guardrail {
+    name: String,
+    stage: GuardrailStage,
+    action: GuardrailAction,
+    restore: bool,
+    providers: Vec<String>,
+    check: Check,
+    metrics: Arc<Metrics>,
+}
+
+enum Check {
+    DenyList(Vec<Regex>),
+    MaxLength(usize),
+    Pii(Vec<PiiEntity>),
+    JsonSchema(jsonschema::Validator),
+    Moderation(Moderation),
+    PromptInjection(InjectionDetector),
+}
+
+/// A guardrail that matched without blocking
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub guardrail: String,
+    pub action: GuardrailAction,
+}
+
+impl Finding {
+    /// `x-guardrails` value, e.g. `pii=redact,profanity=flag`
+    pub fn header_value(findings: &[Finding]) -> Option<HeaderValue> {
+        let value = findings
+            .iter()
+            .map(|f| format!("{}={}", f.guardrail, f.action.as_str()))
+            .collect::<Vec<_>>()
+            .join(",");
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+impl Guardrails {
+    /// Compile the configured guardrails. Moderation services are called with `client`.
+    pub fn new(
+        config: &AppConfig,
+        client: &UpstreamClient,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self, String> {
+        let guardrails: Vec<Guardrail> = config
+            .guardrails
+            .iter()
+            .map(|guardrail| Guardrail::new(guardrail, &client.client, metrics.clone()))
+            .collect::<Result<_, String>>()?;
+        if !guardrails.is_empty() {
+            info!("Loaded {} guardrails", guardrails.len());
+        }
+        Ok(Self {
+            guardrails,
+            metrics,
+        })
+    }
+
+    /// Whether any guardrail runs at `stage` for `provider`
+    pub fn applies(&self, stage: GuardrailStage, provider: &str) -> bool {
+        self.guardrails.iter().any(|g| g.applies(stage, provider))
+    }
+
+    /// Run request guardrails on a JSON request body, returning the possibly redacted body and,
+    /// when redacted PII is restored in the response, the placeholders handed out
+    pub async fn check_request(
+        &self,
+        provider: &str,
+        policy: Option<PiiPolicy>,
+        body: Bytes,
+    ) -> Result<(Bytes, Vec<Finding>, Option<PiiVault>), AppError> {
+        let mut vault = PiiVault::default();
+        let (body, findings) = self
+            .check_body(
+                GuardrailStage::Request,
+                provider,
+                policy,
+                body,
+                content::request_texts,
+                &mut vault,
+            )
+            .await?;
+        let restore = self
+            .guardrails
+            .iter()
+            .any(|g| g.applies(GuardrailStage::Request, provider) && g.restores(policy));
+        Ok((
+            body,
+            findings,
+            (restore && !vault.is_empty()).then_some(vault),
+        ))
+    }
+
+    /// Run response guardrails on a non-streaming JSON response body
+    pub async fn check_response(
+        &self,
+        provider: &str,
+        policy: Option<PiiPolicy>,
+        body: Bytes,
+    ) -> Result<(Bytes, Vec<Finding>), AppError> {
+        self.check_body(
+            GuardrailStage::Response,
+            provider,
+            policy,
+            body,
+            content::response_texts,
+            &mut PiiVault::default(),
+        )
+        .await
+    }
+
+    /// Put the original PII values back into a non-streaming JSON response body
+    pub fn restore_response(&self, body: Bytes, vault: &PiiVault) -> Result<Bytes, AppError> {
+        let Ok(mut value) = serde_json::from_slice::<Value>(&body) else {
+            return Ok(body);
+        };
+        let mut restored = 0;
+        for text in content::response_texts(&mut value) {
+            let (original, count) = vault.restore(text);
+            *text = original;
+            restored += count;
+        }
+        if restored == 0 {
+            return Ok(body);
+        }
+        self.record_restored(restored);
+        Ok(Bytes::from(serde_json::to_vec(&value)?))
+    }
+
+    fn record_restored(&self, restored: usize) {
+        debug!("Restored {} PII placeholders in response", restored);
+        self.metrics
+            .increment("gateway_pii_restorations_total", &[], restored as u64);
+    }
+
+    async fn check_body(
+        &self,
+        stage: GuardrailStage,
+        provider: &str,
+        policy: Option<PiiPolicy>,
+        body: Bytes,
+        texts: Texts,
+        vault: &mut PiiVault,
+    ) -> Result<(Bytes, Vec<Finding>), AppError> {
+        let Ok(mut value) = serde_json::from_slice::<Value>(&body) else {
+            return Ok((body, Vec::new()));
+        };
+        let findings = self
+            .run(stage, provider, policy, &mut value, texts, vault)
+            .await?;
+        if findings.iter().any(|f| f.action == GuardrailAction::Redact) {
+            return Ok((Bytes::from(serde_json::to_vec(&value)?), findings));
+        }
+        Ok((body, findings))
+    }
+
+    /// Run every guardrail for `stage` in configured order, redacting `body` in place.
+    /// The first blocking match stops the pipeline.
+    async fn run(
+        &self,
+        stage: GuardrailStage,
+        provider: &str,
+        policy: Option<PiiPolicy>,
+        body: &mut Value,
+        texts: Texts,
+        vault: &mut PiiVault,
+    ) -> Result<Vec<Finding>, AppError> {
+        let mut findings = Vec::new();
+        for guardrail in self
+            .guardrails
+            .iter()
+            .filter(|g| g.applies(stage, provider))
+        {
+            let Some(action) = guardrail.action_for(policy) else {
+                continue;
+            };
+            if let Some(detail) = guardrail.evaluate(stage, action, body, texts, vault).await {
+                findings.extend(guardrail.triggered(stage, action, provider, &detail)?);
+            }
+        }
+        Ok(findings)
+    }
+}
+
+impl Guardrail {
+    /// Compile a guardrail, failing on invalid patterns, schemas or entity names
+    fn new(
+        config: &GuardrailConfig,
+        client: &reqwest::Client,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self, String> {
+        let check = match config.check {
+            GuardrailCheck::DenyList => {
+                let keywords = config
+                    .keywords
+                    .iter()
+                    .map(|keyword| format!(r"(?i)\b{}\b", regex::escape(keyword)));
+                let patterns = config
+                    .patterns
+                    .iter()
+                    .cloned()
+                    .chain(keywords)
+                    .map(|pattern| {
+                        Regex::new(&pattern).map_err(|e| {
+                            format!("Invalid pattern in guardrail {}: {}", config.name, e)
+                        })
+                    })
+                    .collect::<Result<_, String>>()?;
+                Check::DenyList(patterns)
+            }
+            GuardrailCheck::MaxLength => Check::MaxLength(config.max_chars.unwrap_or_default()),
+            GuardrailCheck::Pii if config.entities.is_empty() => {
+                Check::Pii(PiiEntity::ALL.to_vec())
+            }
+            GuardrailCheck::Pii => Check::Pii(
+                config
+                    .entities
+                    .iter()
+                    .map(|name| {
+                        PiiEntity::from_name(name).ok_or_else(|| {
+                            format!("Unknown PII entity {} in guardrail {}", name, config.name)
+                        })
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            GuardrailCheck::JsonSchema => {
+                let schema = match (&config.schema, &config.schema_file) {
+                    (Some(schema), _) => schema.clone(),
+                    (None, Some(path)) => std::fs::read_to_string(path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+                        .map_err(|e| format!("Failed to load schema {}: {}", path, e))?,
+                    (None, None) => unreachable!("validated at config load"),
+                };
+                Check::JsonSchema(jsonschema::validator_for(&schema).map_err(|e| {
+                    format!("Invalid JSON schema in guardrail {}: {}", config.name, e)
+                })?)
+            }
+            GuardrailCheck::Moderation => {
+                Check::Moderation(Moderation::new(config, client.clone()))
+            }
+            GuardrailCheck::PromptInjection => {
+                Check::PromptInjection(InjectionDetector::new(config.threshold))
+            }
+        };
+
+        Ok(Self {
+            name: config.name.clone(),
+            stage: config.stage,
+            action: config.action,
+            restore: config.restore,
+            providers: config.providers.clone(),
+            check,
+            metrics,
+        })
+    }
+
+    fn applies(&self, stage: GuardrailStage, provider: &str) -> bool {
+        self.stage.includes(stage)
+            && (self.providers.is_empty() || self.providers.iter().any(|p| p == provider))
+    }
+
+    /// Action taken for a request, a key's PII policy overrides `pii` checks.
+    /// `None` when the policy turns the check off.
+    fn action_for(&self, policy: Option<PiiPolicy>) -> Option<GuardrailAction> {
+        match (&self.check, policy) {
+            (Check::Pii(_), Some(policy)) => policy.action(),
+            _ => Some(self.action),
+        }
+    }
+
+    /// Whether PII this guardrail redacts from requests is restored in the response
+    fn restores(&self, policy: Option<PiiPolicy>) -> bool {
+        match (&self.check, policy) {
+            (Check::Pii(_), Some(policy)) => policy == PiiPolicy::Restore,
+            _ => self.restore,
+        }
+    }
+
+    /// Count redacted PII entities for `/metrics`
+    fn record_redacted(&self, stage: GuardrailStage, found: &[(PiiEntity, Range<usize>)]) {
+        for (entity, _) in found {
+            self.metrics.increment(
+                "gateway_pii_redactions_total",
+                &[
+                    ("guardrail", &self.name),
+                    ("stage", stage.as_str()),
+                    ("entity", entity.name()),
+                ],
+                1,
+            );
+        }
+    }
+
+    /// Why the content fails the check, `None` when it passes. Redacting guardrails
+    /// mask their matches in place, PII in requests with placeholders numbered by `vault`.
+    async fn evaluate(
+        &self,
+        stage: GuardrailStage,
+        action: GuardrailAction,
+        body: &mut Value,
+        texts: Texts,
+        vault: &mut PiiVault,
+    ) -> Option<String> {
+        let redact = action == GuardrailAction::Redact;
+        match &self.check {
+            Check::DenyList(patterns) => {
+                let mut matched = false;
+                for text in texts(body) {
+                    for pattern in patterns {
+                        if !pattern.is_match(text) {
+                            continue;
+                        }
+                        matched = true;
+                        if redact {
+                            *text = pattern.replace_all(text, REDACTED).into_owned();
+                        }
+                    }
+                }
+                matched.then(|| "matched deny list".to_string())
+            }
+            Check::MaxLength(max_chars) => {
+                let length: usize = texts(body).iter().map(|t| t.chars().count()).sum();
+                (length > *max_chars)
+                    .then(|| format!("content is {} characters, limit is {}", length, max_chars))
+            }
+            Check::Pii(entities) => {
+                let mut found_entities = Vec::new();
+                for text in texts(body) {
+                    let found = pii::find(text, entities);
+                    for (entity, _) in &found {
+                        if !found_entities.contains(&entity.name()) {
+                            found_entities.push(entity.name());
+                        }
+                    }
+                    if redact && !found.is_empty() {
+                        self.record_redacted(stage, &found);
+                        *text = match stage {
+                            GuardrailStage::Request => vault.redact(text, &found),
+                            _ => pii::redact(text, &found),
+                        };
+                    }
+                }
+                (!found_entities.is_empty()).then(|| format!("found {}", found_entities.join(", ")))
+            }
+            // Requests are validated as a whole, responses by their message content
+            Check::JsonSchema(validator) if stage == GuardrailStage::Request => {
+                validator.validate(body).err().map(|e| e.to_string())
+            }
+            Check::JsonSchema(validator) => {
+                texts(body)
+                    .into_iter()
+                    .find_map(|text| match serde_json::from_str::<Value>(text) {
+                        Ok(value) => validator.validate(&value).err().map(|e| e.to_string()),
+                        Err(e) => Some(format!("content is not JSON: {}", e)),
+                    })
+            }
+            Check::Moderation(moderation) => {
+                let texts: Vec<&str> = texts(body)
+                    .into_iter()
+                    .map(|t| t.as_str())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+                if texts.is_empty() {
+                    return None;
+                }
+                match moderation.check(&texts).await {
+                    Ok(flagged) => flagged,
+                    Err(e) if moderation.fail_open => {
+                        warn!(
+                            "Guardrail {} moderation failed, allowing content: {}",
+                            self.name, e
+                        );
+                        None
+                    }
+                    Err(e) => Some(format!("moderation service unavailable: {}", e)),
+                }
+            }
+            // Only user and tool content can carry injected instructions
+            Check::PromptInjection(detector) => detector
+                .check(
+                    content::untrusted_texts(body)
+                        .into_iter()
+                        .map(|t| t.as_str()),
+                )
+                .map(|detection| detection.detail()),
+        }
+    }
+
+    /// Write the audit record for a match and block, or report it as a finding unless the
+    /// guardrail only logs
+    fn triggered(
+        &self,
+        stage: GuardrailStage,
+        action: GuardrailAction,
+        provider: &str,
+        detail: &str,
+    ) -> Result<Option<Finding>, AppError> {
+        info!(
+            target: "audit",
+            guardrail = %self.name,
+            stage = stage.as_str(),
+            action = action.as_str(),
+            provider,
+            detail,
+            "Guardrail triggered"
+        );
+        self.metrics.increment(
+            "gateway_guardrail_triggers_total",
+            &[
+                ("guardrail", &self.name),
+                ("stage", stage.as_str()),
+                ("action", action.as_str()),
+            ],
+            1,
+        );
+        match action {
+            GuardrailAction::Block => Err(AppError::GuardrailBlocked(self.name.clone())),
+            GuardrailAction::Log => Ok(None),
+            _ => Ok(Some(Finding {
+                guardrail: self.name.clone(),
+                action,
+            })),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::config::GuardrailConfig;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::debug;
+
+const DEFAULT_TIMEOUT: u64 = 5;
+
+/// External moderation service speaking the OpenAI `/v1/moderations` protocol
+pub struct Moderation {
+    /// Shared upstream client, with the `UPSTREAM_*` proxy, CA bundle and connect timeout
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    timeout: Duration,
+    pub fail_open: bool,
+}
+
+impl Moderation {
+    pub fn new(config: &GuardrailConfig, client: reqwest::Client) -> Self {
+        Self {
+            client,
+            url: config.url.clone().unwrap_or_default(),
+            api_key: config
+                .api_key_env
+                .as_ref()
+                .and_then(|name| std::env::var(name).ok()),
+            timeout: Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT)),
+            fail_open: config.fail_open,
+        }
+    }
+
+    /// Categories the service flagged, `None` when the text passed
+    pub async fn check(&self, texts: &[&str]) -> Result<Option<String>, String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .json(&json!({ "input": texts }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("moderation service returned {}", response.status()));
+        }
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        let results = body["results"]
+            .as_array()
+            .ok_or("moderation response has no results")?;
+        debug!("Moderation service returned {} results", results.len());
+
+        let flagged: Vec<&str> = results
+            .iter()
+            .filter(|result| result["flagged"].as_bool().unwrap_or(false))
+            .flat_map(|result| {
+                result["categories"]
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .filter(|(_, flagged)| flagged.as_bool().unwrap_or(false))
+                    .map(|(category, _)| category.as_str())
+            })
+            .collect();
+        if flagged.is_empty() && !results.iter().any(|r| r["flagged"] == true) {
+            return Ok(None);
+        }
+
+        let mut categories = flagged;
+        categories.sort_unstable();
+        categories.dedup();
+        Ok(Some(if categories.is_empty() {
+            "flagged by moderation service".to_string()
+        } else {
+            format!("flagged by moderation service: {}", categories.join(", "))
+        }))
+    }
+}
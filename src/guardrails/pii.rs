@@ -0,0 +1,404 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Personal data the `pii` guardrail detects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PiiEntity {
+    Email,
+    Card,
+    NationalId,
+    Phone,
+}
+
+impl PiiEntity {
+    /// Detection order, earlier entities win where matches overlap
+    pub const ALL: [PiiEntity; 4] = [
+        PiiEntity::Email,
+        PiiEntity::Card,
+        PiiEntity::NationalId,
+        PiiEntity::Phone,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "email" => Some(PiiEntity::Email),
+            "card" | "credit_card" => Some(PiiEntity::Card),
+            "national_id" | "ssn" => Some(PiiEntity::NationalId),
+            "phone" => Some(PiiEntity::Phone),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PiiEntity::Email => "email",
+            PiiEntity::Card => "card",
+            PiiEntity::NationalId => "national_id",
+            PiiEntity::Phone => "phone",
+        }
+    }
+
+    /// Text that replaces a redacted entity
+    pub fn placeholder(self) -> &'static str {
+        match self {
+            PiiEntity::Email => "[EMAIL]",
+            PiiEntity::Card => "[CARD]",
+            PiiEntity::NationalId => "[NATIONAL_ID]",
+            PiiEntity::Phone => "[PHONE]",
+        }
+    }
+
+    /// Placeholder label, e.g. `EMAIL` in `[EMAIL_1]`
+    fn label(self) -> &'static str {
+        let placeholder = self.placeholder();
+        &placeholder[1..placeholder.len() - 1]
+    }
+
+    pub(super) fn pattern(self) -> &'static Regex {
+        match self {
+            PiiEntity::Email => &EMAIL,
+            PiiEntity::Card => &CARD,
+            PiiEntity::NationalId => &NATIONAL_ID,
+            PiiEntity::Phone => &PHONE,
+        }
+    }
+
+    /// Reject pattern matches that are not plausible entities. Checks work on the ASCII
+    /// digits of the match rather than byte offsets, so no input can make them panic.
+    fn is_valid(self, text: &str) -> bool {
+        let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+        match self {
+            PiiEntity::Email => true,
+            PiiEntity::Card => (13..=19).contains(&digits.len()) && luhn(&digits),
+            PiiEntity::NationalId => {
+                // US social security numbers never use area 000, 666 or 9xx, group 00 or serial 0000
+                if digits.len() != 9 {
+                    return false;
+                }
+                let (area, rest) = digits.split_at(3);
+                let (group, serial) = rest.split_at(2);
+                area != [0, 0, 0]
+                    && area != [6, 6, 6]
+                    && area[0] != 9
+                    && group != [0, 0]
+                    && serial != [0, 0, 0, 0]
+            }
+            PiiEntity::Phone => (10..=15).contains(&digits.len()),
+        }
+    }
+}
+
+// Digits are spelled `[0-9]` because `\d` also matches other scripts' digits
+static EMAIL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b").unwrap()
+});
+static CARD: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b[0-9](?:[ -]?[0-9]){12,18}\b").unwrap());
+static NATIONAL_ID: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b[0-9]{3}-[0-9]{2}-[0-9]{4}\b").unwrap());
+static PHONE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?:\+[0-9]{1,3}[ .-]?)?(?:\([0-9]{2,4}\)[ .-]?|\b[0-9]{2,4}[ .-])[0-9]{3,4}[ .-]?[0-9]{3,4}\b",
+    )
+    .unwrap()
+});
+
+fn luhn(digits: &[u32]) -> bool {
+    if digits.is_empty() {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match i % 2 {
+            0 => d,
+            _ if d * 2 > 9 => d * 2 - 9,
+            _ => d * 2,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Non-overlapping entities found in `text`, ordered by position
+pub fn find(text: &str, entities: &[PiiEntity]) -> Vec<(PiiEntity, Range<usize>)> {
+    let mut found: Vec<(PiiEntity, Range<usize>)> = Vec::new();
+    for entity in PiiEntity::ALL.into_iter().filter(|e| entities.contains(e)) {
+        for m in entity.pattern().find_iter(text) {
+            let overlaps = found
+                .iter()
+                .any(|(_, r)| r.start < m.end() && m.start() < r.end);
+            if !overlaps && entity.is_valid(m.as_str()) {
+                found.push((entity, m.range()));
+            }
+        }
+    }
+    found.sort_by_key(|(_, range)| range.start);
+    found
+}
+
+/// Replace entities in `text` with their placeholders
+pub fn redact(text: &str, found: &[(PiiEntity, Range<usize>)]) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut last = 0;
+    for (entity, range) in found {
+        redacted.push_str(&text[last..range.start]);
+        redacted.push_str(entity.placeholder());
+        last = range.end;
+    }
+    redacted.push_str(&text[last..]);
+    redacted
+}
+
+/// Numbered placeholders, e.g. `[EMAIL_2]`
+static NUMBERED_PLACEHOLDER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[(?:EMAIL|CARD|NATIONAL_ID|PHONE)_[0-9]+\]").unwrap());
+
+/// Longest trailing text held back while it may be the start of a numbered placeholder
+const MAX_PLACEHOLDER_LEN: usize = 24;
+
+/// Original values behind the numbered placeholders handed out while redacting one request
+#[derive(Debug, Clone, Default)]
+pub struct PiiVault {
+    /// Original value of each placeholder
+    values: HashMap<String, String>,
+    /// Placeholder given to each original value, so repeated values share one
+    placeholders: HashMap<(PiiEntity, String), String>,
+    /// Placeholders handed out per entity
+    counts: HashMap<PiiEntity, usize>,
+}
+
+impl PiiVault {
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Replace entities in `text` with numbered placeholders, stable for the whole request
+    pub fn redact(&mut self, text: &str, found: &[(PiiEntity, Range<usize>)]) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut last = 0;
+        for (entity, range) in found {
+            redacted.push_str(&text[last..range.start]);
+            redacted.push_str(&self.placeholder(*entity, &text[range.clone()]));
+            last = range.end;
+        }
+        redacted.push_str(&text[last..]);
+        redacted
+    }
+
+    fn placeholder(&mut self, entity: PiiEntity, value: &str) -> String {
+        let key = (entity, value.to_string());
+        if let Some(placeholder) = self.placeholders.get(&key) {
+            return placeholder.clone();
+        }
+        let count = self.counts.entry(entity).or_default();
+        *count += 1;
+        let placeholder = format!("[{}_{}]", entity.label(), count);
+        self.values.insert(placeholder.clone(), value.to_string());
+        self.placeholders.insert(key, placeholder.clone());
+        placeholder
+    }
+
+    /// Put the original values back in place of this request's placeholders, returning the
+    /// restored text and the number of placeholders replaced
+    pub fn restore(&self, text: &str) -> (String, usize) {
+        let mut restored = 0;
+        let text = NUMBERED_PLACEHOLDER.replace_all(text, |caps: &regex::Captures| {
+            match self.values.get(&caps[0]) {
+                Some(value) => {
+                    restored += 1;
+                    value.clone()
+                }
+                None => caps[0].to_string(),
+            }
+        });
+        (text.into_owned(), restored)
+    }
+
+    /// Split off a trailing partial placeholder, e.g. `[EMAIL_` at the end of a streamed delta,
+    /// returning the text that is safe to restore now and the text to hold back
+    pub fn split_partial<'a>(&self, text: &'a str) -> (&'a str, &'a str) {
+        let Some(start) = text.rfind('[') else {
+            return (text, "");
+        };
+        let tail = &text[start..];
+        if tail.contains(']') || tail.len() > MAX_PLACEHOLDER_LEN {
+            return (text, "");
+        }
+        let partial = PiiEntity::ALL.iter().any(|entity| {
+            let label = format!("{}_", entity.label());
+            let rest = &tail[1..];
+            match rest.strip_prefix(label.as_str()) {
+                Some(number) => number.chars().all(|c| c.is_ascii_digit()),
+                None => label.starts_with(rest),
+            }
+        });
+        if partial {
+            (&text[..start], tail)
+        } else {
+            (text, "")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(text: &str, entity: PiiEntity) -> Vec<&str> {
+        find(text, &[entity])
+            .into_iter()
+            .map(|(_, range)| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn finds_cards_that_pass_the_luhn_check() {
+        for card in [
+            "4111 1111 1111 1111",
+            "4111-1111-1111-1111",
+            "5500000000000004",
+            "378282246310005",
+            "6011 0009 9013 9424",
+        ] {
+            let text = format!("Charge {} please", card);
+            assert_eq!(found(&text, PiiEntity::Card), [card], "{}", card);
+        }
+    }
+
+    #[test]
+    fn ignores_numbers_that_are_not_cards() {
+        for text in [
+            "Order 4111 1111 1111 1112 shipped",
+            "Tracking 1234567890123",
+            "Serial 41111111111111111111111",
+            "Call 0000 0000 0000",
+        ] {
+            assert!(found(text, PiiEntity::Card).is_empty(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn finds_valid_social_security_numbers() {
+        for ssn in ["123-45-6789", "001-01-0001", "665-99-9999", "899-12-3456"] {
+            let text = format!("SSN {}.", ssn);
+            assert_eq!(found(&text, PiiEntity::NationalId), [ssn], "{}", ssn);
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_social_security_numbers() {
+        for ssn in [
+            "000-12-3456",
+            "666-12-3456",
+            "900-12-3456",
+            "987-65-4321",
+            "123-00-4567",
+            "123-45-0000",
+        ] {
+            let text = format!("SSN {}.", ssn);
+            assert!(found(&text, PiiEntity::NationalId).is_empty(), "{}", ssn);
+        }
+    }
+
+    #[test]
+    fn ignores_digits_of_other_scripts() {
+        for text in [
+            "Card ٤١١١ ١١١١ ١١١١ ١١١١",
+            "Card ４１１１ １１１１ １１１１ １１１１",
+            "SSN １２３-４５-６７８９",
+            "SSN 123-45-678٩",
+            "SSN ১২৩-৪৫-৬৭৮৯",
+        ] {
+            let all = find(text, &PiiEntity::ALL);
+            assert!(all.is_empty(), "{} found {:?}", text, all);
+        }
+        // A trailing digit of another script does not complete a card number
+        assert!(found("Card 4111 1111 1111 111١", PiiEntity::Card).is_empty());
+        assert!(!luhn(&[]));
+    }
+
+    #[test]
+    fn finds_emails_and_phone_numbers() {
+        let text = "Reach jane.doe+news@mail.example.com or +1 415-555-2671.";
+        assert_eq!(
+            found(text, PiiEntity::Email),
+            ["jane.doe+news@mail.example.com"]
+        );
+        assert_eq!(found(text, PiiEntity::Phone), ["+1 415-555-2671"]);
+        assert!(found("Version 1.2.3 at 10:30", PiiEntity::Phone).is_empty());
+    }
+
+    #[test]
+    fn redacts_with_entity_placeholders() {
+        let text = "Mail jane@example.com, card 4111 1111 1111 1111.";
+        let redacted = redact(text, &find(text, &PiiEntity::ALL));
+        assert_eq!(redacted, "Mail [EMAIL], card [CARD].");
+    }
+
+    #[test]
+    fn vault_placeholders_round_trip() {
+        let mut vault = PiiVault::default();
+        let text = "From a@example.com to b@example.com, cc a@example.com, SSN 123-45-6789";
+        let redacted = vault.redact(text, &find(text, &PiiEntity::ALL));
+        assert_eq!(
+            redacted,
+            "From [EMAIL_1] to [EMAIL_2], cc [EMAIL_1], SSN [NATIONAL_ID_1]"
+        );
+        assert_eq!(vault.restore(&redacted), (text.to_string(), 4));
+
+        // Placeholders the vault did not hand out stay as they are
+        assert_eq!(
+            vault.restore("[EMAIL_3] and [CARD_1] and [EMAIL_2]"),
+            ("[EMAIL_3] and [CARD_1] and b@example.com".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn holds_back_partial_placeholders() {
+        let vault = PiiVault::default();
+        assert_eq!(vault.split_partial("Write to ["), ("Write to ", "["));
+        assert_eq!(vault.split_partial("Write to [EMA"), ("Write to ", "[EMA"));
+        assert_eq!(
+            vault.split_partial("Write to [EMAIL_12"),
+            ("Write to ", "[EMAIL_12")
+        );
+        assert_eq!(
+            vault.split_partial("Write to [EMAIL_1]"),
+            ("Write to [EMAIL_1]", "")
+        );
+        assert_eq!(vault.split_partial("items[0"), ("items[0", ""));
+        assert_eq!(vault.split_partial("see [note"), ("see [note", ""));
+        assert_eq!(vault.split_partial("[EMAIL_1x"), ("[EMAIL_1x", ""));
+    }
+
+    #[test]
+    fn restores_placeholders_split_across_chunks() {
+        let mut vault = PiiVault::default();
+        let original = "Send it to jane@example.com and joe@example.com today.";
+        let redacted = vault.redact(original, &find(original, &PiiEntity::ALL));
+
+        // Every split of the redacted text into two and three chunks restores the original
+        let boundaries: Vec<usize> = (0..=redacted.len()).collect();
+        for &first in &boundaries {
+            for &second in boundaries.iter().filter(|&&b| b >= first) {
+                let chunks = [
+                    &redacted[..first],
+                    &redacted[first..second],
+                    &redacted[second..],
+                ];
+                let mut held = String::new();
+                let mut restored = String::new();
+                for chunk in chunks {
+                    let combined = std::mem::take(&mut held) + chunk;
+                    let (ready, partial) = vault.split_partial(&combined);
+                    restored.push_str(&vault.restore(ready).0);
+                    held = partial.to_string();
+                }
+                restored.push_str(&held);
+                assert_eq!(restored, original, "split at {} and {}", first, second);
+            }
+        }
+    }
+}
@@ -0,0 +1,707 @@
+use super::{content, pii, Check, Guardrail, Guardrails, PiiVault, REDACTED};
+use crate::config::{GuardrailAction, GuardrailStage, PiiPolicy};
+use crate::error::AppError;
+use axum::body::{Body, Bytes};
+use futures_util::StreamExt;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Characters of earlier streamed text kept to catch matches split across chunks
+const WINDOW: usize = 256;
+/// Fields of OpenAI chunks repeated on the chunks the guard sends itself
+const CHUNK_FIELDS: [&str; 5] = ["id", "object", "created", "model", "system_fingerprint"];
+
+/// Apply response guardrails to an SSE response event by event.
+///
+/// Deny lists, PII and length limits run on every delta together with the tail of the text
+/// streamed before it, so matches split across chunks are still caught. Redacting guardrails
+/// hold back as much text as their longest match, at most `WINDOW` characters, and mask
+/// matches before the text is sent. Held text is sent when its choice or content block
+/// finishes. JSON schema and moderation checks need the complete text and run before the
+/// final `[DONE]` event. A blocking guardrail ends the stream with an error event.
+///
+/// With a `vault`, PII placeholders from the request are restored after the guardrails ran.
+/// A placeholder split across deltas is held back until the rest of it arrives.
+pub fn check_stream(
+    guardrails: Arc<Guardrails>,
+    provider: String,
+    policy: Option<PiiPolicy>,
+    vault: Option<PiiVault>,
+    body: Body,
+) -> Body {
+    let deferred = guardrails.guardrails.iter().any(|g| {
+        g.applies(GuardrailStage::Response, &provider)
+            && g.action_for(policy).is_some()
+            && matches!(g.check, Check::JsonSchema(_) | Check::Moderation(_))
+    });
+    let mut guard = StreamGuard {
+        guardrails,
+        provider,
+        policy,
+        vault,
+        held: BTreeMap::new(),
+        hold: 0,
+        unreleased: BTreeMap::new(),
+        fields: Map::new(),
+        anthropic: false,
+        pending: Vec::new(),
+        recent: String::new(),
+        length: 0,
+        text: String::new(),
+        deferred,
+        reported: HashSet::new(),
+    };
+    guard.hold = guard
+        .redacting()
+        .map(Guardrail::held_length)
+        .max()
+        .unwrap_or_default();
+
+    let stream =
+        futures_util::stream::unfold(Some((body.into_data_stream(), guard)), |state| async move {
+            let (mut upstream, mut guard) = state?;
+            loop {
+                match upstream.next().await {
+                    Some(Ok(bytes)) => match guard.push(&bytes).await {
+                        Ok(events) if events.is_empty() => continue,
+                        Ok(events) => return Some((Ok(events), Some((upstream, guard)))),
+                        Err(events) => return Some((Ok(events), None)),
+                    },
+                    Some(Err(e)) => return Some((Err(e), None)),
+                    None => {
+                        let events = guard.finish().await;
+                        return (!events.is_empty()).then_some((Ok(events), None));
+                    }
+                }
+            }
+        });
+    Body::from_stream(stream)
+}
+
+struct StreamGuard {
+    guardrails: Arc<Guardrails>,
+    provider: String,
+    policy: Option<PiiPolicy>,
+    /// Placeholders handed out for the request, when they are restored
+    vault: Option<PiiVault>,
+    /// Partial placeholders held back per choice
+    held: BTreeMap<u64, String>,
+    /// Characters at the end of the text held back for redacting guardrails
+    hold: usize,
+    /// Text held back per choice that the guardrails have not seen yet
+    unreleased: BTreeMap<u64, String>,
+    /// `CHUNK_FIELDS` of the latest upstream chunk
+    fields: Map<String, Value>,
+    /// Whether the stream carries Anthropic events rather than OpenAI chunks
+    anthropic: bool,
+    /// Bytes of an event that has not been terminated yet
+    pending: Vec<u8>,
+    /// Tail of the streamed text
+    recent: String,
+    /// Characters streamed so far
+    length: usize,
+    /// Complete text, only kept while deferred checks are pending
+    text: String,
+    deferred: bool,
+    /// Non-blocking guardrails already written to the audit log for this stream
+    reported: HashSet<usize>,
+}
+
+impl StreamGuard {
+    /// Complete events ready to send. A block returns the events before it followed by
+    /// an error event.
+    async fn push(&mut self, chunk: &[u8]) -> Result<Bytes, Bytes> {
+        self.pending.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = event_end(&self.pending) {
+            let event: Vec<u8> = self.pending.drain(..end).collect();
+            match self.event(event).await {
+                Ok(event) => events.extend_from_slice(&event),
+                Err(e) => {
+                    events.extend_from_slice(&error_event(e));
+                    return Err(events.into());
+                }
+            }
+        }
+        Ok(events.into())
+    }
+
+    /// Run deferred checks when the stream ended without `[DONE]` and flush leftover bytes
+    async fn finish(&mut self) -> Bytes {
+        let mut events = match self.flush_all() {
+            Ok(events) => events,
+            Err(e) => return error_event(e).into(),
+        };
+        if let Err(e) = self.run_deferred().await {
+            events.extend_from_slice(&error_event(e));
+            return events.into();
+        }
+        events.append(&mut self.pending);
+        events.into()
+    }
+
+    async fn event(&mut self, event: Vec<u8>) -> Result<Vec<u8>, AppError> {
+        let Ok(text) = std::str::from_utf8(&event) else {
+            return Ok(event);
+        };
+
+        let mut changed = false;
+        let mut flushed = Vec::new();
+        let mut lines = Vec::new();
+        for line in text.split_inclusive('\n') {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                lines.push(line.to_string());
+                continue;
+            };
+            if data == "[DONE]" {
+                flushed.extend_from_slice(&self.flush_all()?);
+                self.run_deferred().await?;
+            } else if let Ok(mut chunk) = serde_json::from_str::<Value>(data) {
+                let checked = self.check_chunk(&mut chunk)?;
+                // Text still held for a finished choice goes out before the chunk finishing it
+                for choice in ended_choices(&chunk) {
+                    flushed.extend_from_slice(&self.flush(choice)?);
+                }
+                if checked {
+                    changed = true;
+                    let ending = if line.ends_with("\r\n") { "\r\n" } else { "\n" };
+                    lines.push(format!("data: {}{}", chunk, ending));
+                    continue;
+                }
+            }
+            lines.push(line.to_string());
+        }
+
+        if changed {
+            flushed.extend_from_slice(lines.concat().as_bytes());
+        } else {
+            flushed.extend_from_slice(&event);
+        }
+        Ok(flushed)
+    }
+
+    /// Check the deltas of one chunk, returning whether any were held back, redacted or
+    /// restored
+    fn check_chunk(&mut self, chunk: &mut Value) -> Result<bool, AppError> {
+        if chunk.get("choices").is_some() {
+            for field in CHUNK_FIELDS {
+                if let Some(value) = chunk.get(field) {
+                    self.fields.insert(field.to_string(), value.clone());
+                }
+            }
+        } else if chunk.get("type").is_some() {
+            self.anthropic = true;
+        }
+        let ended = ended_choices(chunk);
+
+        let mut changed = false;
+        for (choice, text) in content::chunk_texts(chunk) {
+            let mut released = self.unreleased.remove(&choice).unwrap_or_default();
+            if text.is_empty() && released.is_empty() {
+                continue;
+            }
+            released.push_str(text);
+            let finished = ended.contains(&choice);
+            if !finished {
+                let rest = released.split_off(self.boundary(&released));
+                if !rest.is_empty() {
+                    self.unreleased.insert(choice, rest);
+                }
+            }
+            self.release(choice, &mut released)?;
+            if finished {
+                released.push_str(&self.held.remove(&choice).unwrap_or_default());
+            }
+            changed |= released != *text;
+            *text = released;
+        }
+        Ok(changed)
+    }
+
+    /// Byte offset up to which `text` can be released: everything but the last `hold`
+    /// characters, and never inside a match of a redacting guardrail
+    fn boundary(&self, text: &str) -> usize {
+        if self.hold == 0 {
+            return text.len();
+        }
+        let mut boundary = text
+            .char_indices()
+            .rev()
+            .nth(self.hold - 1)
+            .map_or(0, |(i, _)| i);
+        let matches: Vec<Range<usize>> = self
+            .redacting()
+            .flat_map(|guardrail| guardrail.match_ranges(text))
+            .collect();
+        while let Some(m) = matches
+            .iter()
+            .find(|m| m.start < boundary && m.end > boundary)
+        {
+            boundary = m.start;
+        }
+        boundary
+    }
+
+    /// Run the guardrails on text about to be sent, then restore PII placeholders in it
+    fn release(&mut self, choice: u64, text: &mut String) -> Result<(), AppError> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let delta = text.clone();
+        let guardrails = self
+            .guardrails
+            .guardrails
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.applies(GuardrailStage::Response, &self.provider));
+        for (index, guardrail) in guardrails {
+            let Some(action) = guardrail.action_for(self.policy) else {
+                continue;
+            };
+            let Some(detail) = guardrail.evaluate_delta(action, &self.recent, self.length, text)
+            else {
+                continue;
+            };
+            if action == GuardrailAction::Block || self.reported.insert(index) {
+                guardrail.triggered(GuardrailStage::Response, action, &self.provider, &detail)?;
+            }
+        }
+        self.restore(choice, text);
+
+        self.length += delta.chars().count();
+        if self.deferred {
+            self.text.push_str(&delta);
+        }
+        self.recent.push_str(&delta);
+        if let Some((start, _)) = self.recent.char_indices().rev().nth(WINDOW) {
+            self.recent.drain(..start);
+        }
+        Ok(())
+    }
+
+    /// Response guardrails that redact what they match
+    fn redacting(&self) -> impl Iterator<Item = &Guardrail> {
+        self.guardrails.guardrails.iter().filter(|g| {
+            g.applies(GuardrailStage::Response, &self.provider)
+                && g.action_for(self.policy) == Some(GuardrailAction::Redact)
+        })
+    }
+
+    /// Restore PII placeholders in a delta. A trailing partial placeholder is held back and prepended to the next delta of the same choice.
+    fn restore(&mut self, choice: u64, text: &mut String) {
+        let Some(vault) = &self.vault else {
+            return;
+        };
+        let held = self.held.remove(&choice).unwrap_or_default();
+        if held.is_empty() && !text.contains('[') {
+            return;
+        }
+        let combined = held + text.as_str();
+        let (ready, partial) = vault.split_partial(&combined);
+        let (restored, count) = vault.restore(ready);
+        if !partial.is_empty() {
+            self.held.insert(choice, partial.to_string());
+        }
+        if count > 0 {
+            self.guardrails.record_restored(count);
+        }
+        *text = restored;
+    }
+
+    /// Chunks carrying the text held back for every choice, when the stream ends
+    fn flush_all(&mut self) -> Result<Vec<u8>, AppError> {
+        let choices: BTreeSet<u64> = self
+            .unreleased
+            .keys()
+            .chain(self.held.keys())
+            .copied()
+            .collect();
+        let mut events = Vec::new();
+        for choice in choices {
+            events.extend_from_slice(&self.flush(choice)?);
+        }
+        Ok(events)
+    }
+
+    /// A chunk carrying the text held back for `choice`, releasing it after the guardrails
+    /// ran. A held partial placeholder was not a placeholder after all and goes out as it is.
+    fn flush(&mut self, choice: u64) -> Result<Vec<u8>, AppError> {
+        let mut text = self.unreleased.remove(&choice).unwrap_or_default();
+        self.release(choice, &mut text)?;
+        text.push_str(&self.held.remove(&choice).unwrap_or_default());
+        if text.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.anthropic {
+            let event = json!({
+                "type": "content_block_delta",
+                "index": choice,
+                "delta": { "type": "text_delta", "text": text },
+            });
+            return Ok(format!("event: content_block_delta\ndata: {}\n\n", event).into_bytes());
+        }
+        let mut chunk = self.fields.clone();
+        chunk.insert(
+            "choices".to_string(),
+            json!([{ "index": choice, "delta": { "content": text } }]),
+        );
+        Ok(format!("data: {}\n\n", Value::Object(chunk)).into_bytes())
+    }
+
+    /// JSON schema and moderation checks on the complete streamed text
+    async fn run_deferred(&mut self) -> Result<(), AppError> {
+        if !std::mem::take(&mut self.deferred) {
+            return Ok(());
+        }
+        let mut body = json!({
+            "choices": [{ "message": { "content": std::mem::take(&mut self.text) } }]
+        });
+        let guardrails = self.guardrails.guardrails.iter().filter(|g| {
+            g.applies(GuardrailStage::Response, &self.provider)
+                && matches!(g.check, Check::JsonSchema(_) | Check::Moderation(_))
+        });
+        for guardrail in guardrails {
+            let Some(action) = guardrail.action_for(self.policy) else {
+                continue;
+            };
+            let detail = guardrail
+                .evaluate(
+                    GuardrailStage::Response,
+                    action,
+                    &mut body,
+                    content::response_texts,
+                    &mut PiiVault::default(),
+                )
+                .await;
+            if let Some(detail) = detail {
+                guardrail.triggered(GuardrailStage::Response, action, &self.provider, &detail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Guardrail {
+    /// Check a streamed delta against the tail of the earlier text, `recent`, and the
+    /// `length` streamed so far. Only matches that end inside the delta count.
+    fn evaluate_delta(
+        &self,
+        action: GuardrailAction,
+        recent: &str,
+        length: usize,
+        delta: &mut String,
+    ) -> Option<String> {
+        let redact = action == GuardrailAction::Redact;
+        match &self.check {
+            Check::DenyList(patterns) => {
+                let window = format!("{}{}", recent, delta);
+                let matched = patterns
+                    .iter()
+                    .any(|p| p.find_iter(&window).any(|m| m.end() > recent.len()));
+                if matched && redact {
+                    for pattern in patterns {
+                        *delta = pattern.replace_all(delta, REDACTED).into_owned();
+                    }
+                }
+                matched.then(|| "matched deny list".to_string())
+            }
+            Check::Pii(entities) => {
+                let window = format!("{}{}", recent, delta);
+                let mut found_entities: Vec<&str> = pii::find(&window, entities)
+                    .into_iter()
+                    .filter(|(_, range)| range.end > recent.len())
+                    .map(|(entity, _)| entity.name())
+                    .collect();
+                if found_entities.is_empty() {
+                    return None;
+                }
+                found_entities.sort_unstable();
+                found_entities.dedup();
+                if redact {
+                    let found = pii::find(delta, entities);
+                    self.record_redacted(GuardrailStage::Response, &found);
+                    *delta = pii::redact(delta, &found);
+                }
+                Some(format!("found {}", found_entities.join(", ")))
+            }
+            Check::MaxLength(max_chars) => {
+                let total = length + delta.chars().count();
+                (length <= *max_chars && total > *max_chars)
+                    .then(|| format!("content exceeds {} characters", max_chars))
+            }
+            Check::JsonSchema(_) | Check::Moderation(_) | Check::PromptInjection(_) => None,
+        }
+    }
+
+    /// Longest text a deny list or PII check can match, at most `WINDOW`
+    fn held_length(&self) -> usize {
+        let patterns: Vec<&Regex> = match &self.check {
+            Check::DenyList(patterns) => patterns.iter().collect(),
+            Check::Pii(entities) => entities.iter().map(|entity| entity.pattern()).collect(),
+            _ => return 0,
+        };
+        patterns
+            .into_iter()
+            .map(|pattern| {
+                regex_syntax::parse(pattern.as_str())
+                    .ok()
+                    .and_then(|hir| hir.properties().maximum_len())
+                    .map_or(WINDOW, |length| length.min(WINDOW))
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Byte ranges of `text` a deny list or PII check would redact
+    fn match_ranges(&self, text: &str) -> Vec<Range<usize>> {
+        match &self.check {
+            Check::DenyList(patterns) => patterns
+                .iter()
+                .flat_map(|pattern| pattern.find_iter(text).map(|m| m.range()))
+                .collect(),
+            Check::Pii(entities) => pii::find(text, entities)
+                .into_iter()
+                .map(|(_, range)| range)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Choices, or the Anthropic content block, that a chunk finishes
+fn ended_choices(chunk: &Value) -> Vec<u64> {
+    if let Some(Value::Array(choices)) = chunk.get("choices") {
+        return choices
+            .iter()
+            .enumerate()
+            .filter(|(_, choice)| choice.get("finish_reason").is_some_and(|r| !r.is_null()))
+            .map(|(position, choice)| {
+                choice
+                    .get("index")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(position as u64)
+            })
+            .collect();
+    }
+    match chunk.get("type").and_then(Value::as_str) {
+        Some("content_block_stop") => {
+            vec![chunk
+                .get("index")
+                .and_then(Value::as_u64)
+                .unwrap_or_default()]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// End of the first complete SSE event in `buffer`, including its blank line
+fn event_end(buffer: &[u8]) -> Option<usize> {
+    buffer.iter().enumerate().find_map(|(i, &byte)| {
+        if byte != b'\n' {
+            return None;
+        }
+        match &buffer[i + 1..] {
+            [b'\n', ..] => Some(i + 2),
+            [b'\r', b'\n', ..] => Some(i + 3),
+            _ => None,
+        }
+    })
+}
+
+fn error_event(error: AppError) -> Vec<u8> {
+    format!("data: {}\n\n", error.body()).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GuardrailConfig;
+    use crate::metrics::Metrics;
+
+    fn guardrails(config: &str) -> Arc<Guardrails> {
+        let config: GuardrailConfig = toml::from_str(config).unwrap();
+        let metrics = Arc::new(Metrics::default());
+        Arc::new(Guardrails {
+            guardrails: vec![
+                Guardrail::new(&config, &reqwest::Client::new(), metrics.clone()).unwrap(),
+            ],
+            metrics,
+        })
+    }
+
+    fn secrets() -> Arc<Guardrails> {
+        guardrails(
+            r#"
+            name = "secrets"
+            check = "deny_list"
+            stage = "response"
+            action = "redact"
+            patterns = ["sk-[A-Za-z0-9]{20,}", "code-[0-9]{4}"]
+            "#,
+        )
+    }
+
+    fn chunk(text: &str) -> String {
+        let chunk = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "model": "tiny",
+            "choices": [{ "index": 0, "delta": { "content": text }, "finish_reason": null }],
+        });
+        format!("data: {}\n\n", chunk)
+    }
+
+    fn stop() -> String {
+        let chunk = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "model": "tiny",
+            "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }],
+        });
+        format!("data: {}\n\n", chunk)
+    }
+
+    /// Feed the events to the guard one upstream chunk at a time and collect the response
+    async fn run(guardrails: Arc<Guardrails>, events: Vec<String>) -> String {
+        let events = events
+            .into_iter()
+            .map(|event| Ok::<_, std::io::Error>(Bytes::from(event)));
+        let body = Body::from_stream(futures_util::stream::iter(events));
+        let body = check_stream(guardrails, "openai".to_string(), None, None, body);
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    /// JSON payloads of the `data:` lines, without `[DONE]`
+    fn chunks(body: &str) -> Vec<Value> {
+        body.lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    fn text(chunks: &[Value]) -> String {
+        chunks
+            .iter()
+            .filter_map(|chunk| chunk.pointer("/choices/0/delta/content"))
+            .filter_map(Value::as_str)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn redacts_matches_split_across_chunks() {
+        let body = run(
+            secrets(),
+            vec![
+                chunk("Your key is sk-abcdefghij"),
+                chunk("klmnopqrstuvwxyz, keep it"),
+                chunk(" safe."),
+                stop(),
+                "data: [DONE]\n\n".to_string(),
+            ],
+        )
+        .await;
+        assert!(!body.contains("sk-abc"), "{}", body);
+        let chunks = chunks(&body);
+        assert_eq!(text(&chunks), "Your key is [REDACTED], keep it safe.");
+        assert!(body.trim_end().ends_with("data: [DONE]"));
+    }
+
+    #[tokio::test]
+    async fn releases_text_beyond_the_longest_match() {
+        let guardrails = guardrails(
+            r#"
+            name = "codes"
+            check = "deny_list"
+            stage = "response"
+            action = "redact"
+            patterns = ["code-[0-9]{4}"]
+            "#,
+        );
+        let body = run(
+            guardrails,
+            vec![
+                chunk("The door opens with code-12"),
+                chunk("34 after dark."),
+                stop(),
+            ],
+        )
+        .await;
+        let chunks = chunks(&body);
+        let first = chunks[0]["choices"][0]["delta"]["content"]
+            .as_str()
+            .unwrap();
+        assert_eq!(first, "The door opens wit");
+        assert_eq!(text(&chunks), "The door opens with [REDACTED] after dark.");
+    }
+
+    #[tokio::test]
+    async fn held_text_keeps_the_chunk_fields() {
+        // The stream ends without a finish reason, so the held text goes out on its own chunk
+        let body = run(secrets(), vec![chunk("Nothing secret here.")]).await;
+        let chunks = chunks(&body);
+        assert_eq!(text(&chunks), "Nothing secret here.");
+        for chunk in &chunks {
+            assert_eq!(chunk["id"], "chatcmpl-1");
+            assert_eq!(chunk["object"], "chat.completion.chunk");
+            assert_eq!(chunk["model"], "tiny");
+        }
+    }
+
+    #[tokio::test]
+    async fn redacts_anthropic_text_deltas_before_the_block_stops() {
+        let delta = |text: &str| {
+            let event = json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "text_delta", "text": text },
+            });
+            format!("event: content_block_delta\ndata: {}\n\n", event)
+        };
+        let body = run(
+            secrets(),
+            vec![
+                delta("Use sk-ABCDEFGHIJKLMNOP"),
+                delta("QRSTUVWXYZ today."),
+                "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n"
+                    .to_string(),
+            ],
+        )
+        .await;
+        let chunks = chunks(&body);
+        let text: String = chunks
+            .iter()
+            .filter_map(|chunk| chunk.pointer("/delta/text"))
+            .filter_map(Value::as_str)
+            .collect();
+        assert_eq!(text, "Use [REDACTED] today.");
+        assert_eq!(chunks.last().unwrap()["type"], "content_block_stop");
+    }
+
+    #[tokio::test]
+    async fn redacts_pii_split_across_chunks() {
+        let guardrails = guardrails(
+            r#"
+            name = "pii"
+            check = "pii"
+            stage = "response"
+            action = "redact"
+            entities = ["email"]
+            "#,
+        );
+        let body = run(
+            guardrails,
+            vec![
+                chunk("Write to jane.doe@exa"),
+                chunk("mple.com for details."),
+                stop(),
+            ],
+        )
+        .await;
+        assert_eq!(text(&chunks(&body)), "Write to [EMAIL] for details.");
+    }
+}
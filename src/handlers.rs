@@ -1,35 +1,105 @@
-use crate::{config::AppConfig, proxy::proxy_request_to_provider};
+use crate::{
+    catalog,
+    client_ip::ClientIp,
+    error::AppError,
+    health,
+    providers::rerank::RerankRequest,
+    proxy::{
+        body::{self, RequestBody},
+        proxy_request_to_provider,
+    },
+    state::AppState,
+    usage::Tracker,
+};
 use axum::{
     body::Body,
-    extract::{ConnectInfo, State},
-    http::{HeaderMap, Request},
-    response::IntoResponse,
-    Json,
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderMap, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
 use serde_json::json;
-use std::{net::SocketAddr, sync::Arc};
 use tracing::{debug, error, Instrument};
 
+/// Gateway counters in the Prometheus text format
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
 pub async fn health_check() -> impl IntoResponse {
     debug!("Health check endpoint called");
     Json(json!({ "status": "healthy", "version": env!("CARGO_PKG_VERSION") }))
 }
 
+/// Liveness: the process is up and serving requests
+pub async fn livez() -> impl IntoResponse {
+    Json(json!({ "status": "alive", "version": env!("CARGO_PKG_VERSION") }))
+}
+
+/// Readiness under the `[health]` policy, `503` with the same details when not ready
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let (ready, body) = health::readiness(&state);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(body))
+}
+
+/// Serve the aggregated model catalog, or the named provider's own listing when `x-provider` is set
+pub async fn list_models(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    client: Extension<ClientIp>,
+    request: Request<Body>,
+) -> Response {
+    if headers.contains_key("x-provider") {
+        return proxy_request(State(state), headers, client, request)
+            .await
+            .into_response();
+    }
+
+    let config = &state.config;
+    let key = if config.keys.is_empty() {
+        None
+    } else {
+        match config.key_for_request(&request) {
+            Some(key) => Some(key.clone()),
+            None => {
+                error!("Model catalog requested without a configured key");
+                return AppError::MissingApiKey.into_response();
+            }
+        }
+    };
+
+    if let Some(key) = &key {
+        debug!("Listing model catalog for key: {}", key.name);
+    }
+    let data = catalog::models(&state, key.as_ref()).await;
+    Json(json!({ "object": "list", "data": data })).into_response()
+}
+
 pub async fn proxy_request(
-    State(config): State<Arc<AppConfig>>,
+    State(state): State<AppState>,
     headers: HeaderMap,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(client): Extension<ClientIp>,
     request: Request<Body>,
 ) -> impl IntoResponse {
     let provider = headers
         .get("x-provider")
         .and_then(|h| h.to_str().ok())
-        .unwrap_or("openai");
+        .unwrap_or("openai")
+        .to_lowercase();
+    let provider = provider.as_str();
 
     debug!(
         "Received request for provider: {}, client: {}, path: {}",
         provider,
-        addr,
+        client,
         request.uri().path()
     );
 
@@ -38,17 +108,72 @@ pub async fn proxy_request(
         provider = provider,
         method = %request.method(),
         path = %request.uri().path(),
-        client = %addr
+        client = %client
     );
 
     async move {
-        match proxy_request_to_provider(config, provider, request).await {
-            Ok(response) => response,
-            Err(e) => {
-                error!(error = %e, "Proxy request failed");
-                e.into_response()
+        let tracker = Tracker::start(&state, provider, &request);
+        let result = proxy_request_to_provider(&state, provider, request).await;
+        if let Err(e) = &result {
+            error!(error = %e, "Proxy request failed");
+        }
+        tracker.finish(result)
+    }
+    .instrument(span)
+    .await
+}
+
+/// Validate a normalized rerank request before handing it to the rerank provider
+pub async fn rerank(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Extension(client): Extension<ClientIp>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let provider = headers
+        .get("x-provider")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("cohere");
+
+    debug!(
+        "Received rerank request for provider: {}, client: {}",
+        provider, client
+    );
+
+    let span = tracing::info_span!(
+        "rerank",
+        provider = provider,
+        client = %client
+    );
+
+    async move {
+        let tracker = Tracker::start(&state, provider, &request);
+        let (parts, body) = request.into_parts();
+        let limit = state.config.max_body_size_for(parts.uri.path());
+        let normalized = async {
+            body::check_content_length(&parts.headers, limit)?;
+            let bytes = RequestBody::Streaming(body).buffer(limit).await?;
+            let rerank_request = RerankRequest::parse(&bytes)?;
+            Ok::<_, AppError>(serde_json::to_vec(&rerank_request)?)
+        }
+        .await;
+
+        let result = match normalized {
+            Ok(body) => {
+                proxy_request_to_provider(
+                    &state,
+                    provider,
+                    Request::from_parts(parts, Body::from(body)),
+                )
+                .await
             }
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = &result {
+            error!(error = %e, "Rerank request failed");
         }
+        tracker.finish(result)
     }
     .instrument(span)
     .await
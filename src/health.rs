@@ -0,0 +1,301 @@
+use crate::{
+    config::{AppConfig, ProbeConfig, ProbeKind},
+    context::RequestContext,
+    error::AppError,
+    providers::ProviderRegistry,
+    proxy::proxy_request_to_provider,
+    shutdown::Phase,
+    state::{AppState, Gateway},
+};
+use axum::{body::Body, http::HeaderMap};
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use parking_lot::Mutex;
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+const DEFAULT_INTERVAL: u64 = 30;
+const DEFAULT_TIMEOUT: u64 = 5;
+const DEFAULT_UNHEALTHY_THRESHOLD: u64 = 3;
+
+/// Health of provider targets, learned from proxied requests and active probes
+#[derive(Default)]
+pub struct ProviderHealth {
+    targets: Mutex<HashMap<String, TargetHealth>>,
+}
+
+/// Request and probe outcomes of one provider target
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TargetHealth {
+    pub requests: u64,
+    pub failures: u64,
+    /// Failed requests and probes since the last success
+    pub consecutive_failures: u64,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_probe: Option<ProbeResult>,
+}
+
+/// Outcome of the latest active probe of a target
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub time: DateTime<Utc>,
+    pub healthy: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Healthy,
+    Unhealthy,
+    /// Neither used nor probed yet
+    Unknown,
+}
+
+impl TargetHealth {
+    pub fn status(&self, unhealthy_threshold: u64) -> Status {
+        if self.consecutive_failures >= unhealthy_threshold {
+            Status::Unhealthy
+        } else if self.requests == 0 && self.last_probe.is_none() {
+            Status::Unknown
+        } else {
+            Status::Healthy
+        }
+    }
+
+    fn succeeded(&mut self) {
+        self.consecutive_failures = 0;
+        self.last_success = Some(Utc::now());
+    }
+
+    fn failed(&mut self, error: String) {
+        self.consecutive_failures += 1;
+        self.last_failure = Some(Utc::now());
+        self.last_error = Some(error);
+    }
+}
+
+impl ProviderHealth {
+    /// Record a request the target answered without a server error
+    pub fn record_success(&self, target: &str) {
+        let mut targets = self.targets.lock();
+        let health = targets.entry(target.to_string()).or_default();
+        health.requests += 1;
+        health.succeeded();
+    }
+
+    /// Record a request that failed to reach the target or got a server error
+    pub fn record_failure(&self, target: &str, error: String) {
+        let mut targets = self.targets.lock();
+        let health = targets.entry(target.to_string()).or_default();
+        health.requests += 1;
+        health.failures += 1;
+        health.failed(error);
+    }
+
+    /// Record an active probe, which counts towards the target's status but not its requests
+    pub fn record_probe(&self, target: &str, latency: Duration, result: Result<(), String>) {
+        let mut targets = self.targets.lock();
+        let health = targets.entry(target.to_string()).or_default();
+        health.last_probe = Some(ProbeResult {
+            time: Utc::now(),
+            healthy: result.is_ok(),
+            latency_ms: latency.as_millis() as u64,
+            error: result.as_ref().err().cloned(),
+        });
+        match result {
+            Ok(()) => health.succeeded(),
+            Err(error) => health.failed(error),
+        }
+    }
+
+    /// Outcomes recorded for a target, default when it has not been used yet
+    pub fn get(&self, target: &str) -> TargetHealth {
+        self.targets.lock().get(target).cloned().unwrap_or_default()
+    }
+
+    fn targets(&self) -> Vec<String> {
+        self.targets.lock().keys().cloned().collect()
+    }
+}
+
+/// Marks requests sent by active probes, which record their own outcome
+#[derive(Debug, Clone, Copy)]
+pub struct Probe;
+
+/// Targets serving a required model or provider name
+pub fn targets_for(config: &AppConfig, providers: &ProviderRegistry, name: &str) -> Vec<String> {
+    let mut targets: Vec<String> = config
+        .models
+        .iter()
+        .filter(|model| model.id == name)
+        .map(|model| model.provider.clone())
+        .collect();
+    let provider = name.to_lowercase();
+    if targets.is_empty() && providers.names().contains(&provider.as_str()) {
+        targets.push(provider);
+    }
+    targets
+}
+
+/// Readiness of the gateway under the `[health]` policy: every required model or provider
+/// needs a healthy target. Without required entries the gateway is ready when a probed
+/// target is healthy, or always when nothing is probed. Never ready once shutdown starts.
+pub fn readiness(state: &AppState) -> (bool, Value) {
+    let health = &state.config.health;
+    let threshold = health
+        .unhealthy_threshold
+        .unwrap_or(DEFAULT_UNHEALTHY_THRESHOLD);
+    let probed = |target: &str| health.probes.iter().any(|p| p.provider == target);
+    // Targets nobody has used or probed are assumed healthy until a probe says otherwise
+    let usable = |target: &str| match state.health.get(target).status(threshold) {
+        Status::Healthy => true,
+        Status::Unhealthy => false,
+        Status::Unknown => !probed(target),
+    };
+
+    let required: Vec<Value> = health
+        .required
+        .iter()
+        .map(|name| {
+            let targets = targets_for(&state.config, &state.providers, name);
+            json!({
+                "name": name,
+                "ready": targets.iter().any(|t| usable(t)),
+                "targets": targets,
+            })
+        })
+        .collect();
+    let draining = state.shutdown.phase() != Phase::Running;
+    let ready = if draining {
+        false
+    } else if health.required.is_empty() {
+        health.probes.is_empty() || health.probes.iter().any(|p| usable(&p.provider))
+    } else {
+        required.iter().all(|r| r["ready"] == json!(true))
+    };
+
+    let mut shown: BTreeSet<String> = state.health.targets().into_iter().collect();
+    shown.extend(health.probes.iter().map(|p| p.provider.clone()));
+    let targets: serde_json::Map<String, Value> = shown
+        .into_iter()
+        .map(|target| {
+            let detail = state.health.get(&target);
+            let mut view = serde_json::to_value(&detail).unwrap_or_default();
+            view["status"] = json!(detail.status(threshold));
+            view["probed"] = json!(probed(&target));
+            (target, view)
+        })
+        .collect();
+
+    let body = json!({
+        "status": match (ready, draining) {
+            (true, _) => "ready",
+            (false, true) => "shutting_down",
+            (false, false) => "not_ready",
+        },
+        "required": required,
+        "targets": targets,
+    });
+    (ready, body)
+}
+
+/// Run the configured probes for as long as the gateway runs. Probes are read from the
+/// current configuration on every round, so reloads add, change and remove them.
+pub async fn run_probes(gateway: Arc<Gateway>) {
+    let mut last_run: HashMap<String, Instant> = HashMap::new();
+    loop {
+        let state = gateway.state();
+        let health = &state.config.health;
+        let interval = health.interval.unwrap_or(DEFAULT_INTERVAL);
+        let timeout = Duration::from_secs(health.timeout.unwrap_or(DEFAULT_TIMEOUT));
+        let due: Vec<&ProbeConfig> = health
+            .probes
+            .iter()
+            .filter(|probe| {
+                let every = Duration::from_secs(probe.interval.unwrap_or(interval));
+                last_run
+                    .get(&probe.provider)
+                    .is_none_or(|last| last.elapsed() >= every)
+            })
+            .collect();
+        for probe in &due {
+            last_run.insert(probe.provider.clone(), Instant::now());
+        }
+        join_all(
+            due.into_iter()
+                .map(|probe| run_probe(&state, probe, timeout)),
+        )
+        .await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn run_probe(state: &AppState, probe: &ProbeConfig, timeout: Duration) {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, check(state, probe)).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("probe timed out after {}s", timeout.as_secs())),
+    };
+    match &result {
+        Ok(()) => debug!("Health probe of {} succeeded", probe.provider),
+        Err(e) => warn!("Health probe of {} failed: {}", probe.provider, e),
+    }
+    state
+        .health
+        .record_probe(&probe.provider, started.elapsed(), result);
+}
+
+async fn check(state: &AppState, probe: &ProbeConfig) -> Result<(), String> {
+    match probe.kind {
+        ProbeKind::Models => {
+            let mut request = axum::http::Request::builder()
+                .method(Method::GET)
+                .uri("/v1/models")
+                .body(Body::empty())
+                .map_err(|e| e.to_string())?;
+            request.extensions_mut().insert(Probe);
+            let response = proxy_request_to_provider(state, &probe.provider, request)
+                .await
+                .map_err(|e| e.to_string())?;
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(format!("model listing returned {}", response.status()))
+            }
+        }
+        ProbeKind::Tcp => {
+            let address = match &probe.address {
+                Some(address) => address.clone(),
+                None => provider_address(state, &probe.provider).map_err(|e| e.to_string())?,
+            };
+            tokio::net::TcpStream::connect(&address)
+                .await
+                .map(|_| ())
+                .map_err(|e| format!("connect to {}: {}", address, e))
+        }
+    }
+}
+
+/// `host:port` of a provider's base URL
+fn provider_address(state: &AppState, provider: &str) -> Result<String, AppError> {
+    let provider = state.providers.get(provider)?;
+    let base_url = provider.base_url(&RequestContext::new("/v1/models", HeaderMap::new()));
+    let url = reqwest::Url::parse(&base_url)
+        .map_err(|e| AppError::RequestError(format!("invalid base URL {}: {}", base_url, e)))?;
+    match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => Ok(format!("{}:{}", host, port)),
+        _ => Err(AppError::RequestError(format!(
+            "base URL {} has no host",
+            base_url
+        ))),
+    }
+}
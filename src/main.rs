@@ -1,21 +1,48 @@
 use axum::{
-    routing::{any, get},
+    middleware,
+    routing::{any, get, post},
     Router,
 };
+use std::io::Write;
 use std::sync::Arc;
 use std::time::Duration;
-use tower_http::cors::{Any, CorsLayer};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod admin;
+mod catalog;
+mod client_ip;
 mod config;
 mod context;
+mod cors;
 mod error;
+mod guardrails;
 mod handlers;
+mod health;
+mod metrics;
 mod providers;
 mod proxy;
+mod server;
+mod shutdown;
+mod state;
+mod usage;
 
-use crate::config::AppConfig;
+use crate::{
+    admin::{
+        store::{self, SqliteStore, Store},
+        Admin,
+    },
+    config::{AdminConfig, AppConfig, TlsConfig, UnixSocketConfig},
+    health::ProviderHealth,
+    metrics::Metrics,
+    server::{tls::Tls, Listener},
+    shutdown::{Phase, Shutdown},
+    state::{AppState, Gateway},
+    usage::Usage,
+};
+
+/// Time streams terminated at the drain deadline get to send their error event
+const TERMINATION_GRACE: Duration = Duration::from_secs(2);
 
 #[tokio::main]
 async fn main() {
@@ -28,9 +55,21 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer().compact())
         .init();
 
+    // Entries managed through the admin API extend the config file
+    let admin_config = AdminConfig::from_env();
+    let admin_store: Option<Arc<dyn Store>> = admin_config.as_ref().map(|admin| {
+        let store = SqliteStore::open(&admin.database).unwrap_or_else(|e| panic!("{}", e));
+        store::hash_keys(&store).unwrap_or_else(|e| panic!("{}", e));
+        Arc::new(store) as Arc<dyn Store>
+    });
+    let managed = admin_store
+        .as_deref()
+        .map(|store| store::managed(store).unwrap_or_else(|e| panic!("{}", e)))
+        .unwrap_or_default();
+
     // Load configuration
     info!("Loading application configuration");
-    let config = Arc::new(AppConfig::new());
+    let config = Arc::new(AppConfig::load(managed).unwrap_or_else(|e| panic!("{}", e)));
     debug!(
         "Configuration loaded: port={}, host={}",
         config.port, config.host
@@ -44,66 +83,123 @@ async fn main() {
     std::env::set_var("TOKIO_WORKER_THREADS", config.worker_threads.to_string());
     std::env::set_var("TOKIO_THREAD_STACK_SIZE", (2 * 1024 * 1024).to_string());
 
-    // Setup CORS
-    debug!("Setting up CORS layer with 1-hour max age");
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any)
-        .max_age(Duration::from_secs(3600));
+    let shutdown = Arc::new(Shutdown::new(
+        Duration::from_secs(config.shutdown_delay),
+        Duration::from_secs(config.shutdown_timeout),
+    ));
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { shutdown.run().await }
+    });
+
+    // Providers are built once and shared by all requests until the configuration is reloaded
+    let state = AppState::new(
+        config.clone(),
+        Arc::new(Metrics::default()),
+        Arc::new(ProviderHealth::default()),
+        Arc::new(Usage::default()),
+        shutdown.clone(),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+    let gateway = Arc::new(Gateway::new(state));
+    tokio::spawn(health::run_probes(gateway.clone()));
 
     // Create router with optimized settings
     let app = Router::new()
         .route("/health", get(handlers::health_check))
+        .route("/livez", get(handlers::livez))
+        .route("/readyz", get(handlers::readyz))
+        .route("/metrics", get(handlers::metrics))
+        .route("/v1/models", get(handlers::list_models))
+        .route("/v1/rerank", post(handlers::rerank))
         .route("/v1/*path", any(handlers::proxy_request))
-        .with_state(config.clone())
-        .layer(cors)
-        .into_make_service_with_connect_info::<std::net::SocketAddr>();
-
-    // Start server with optimized TCP settings
-    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], config.port));
-    info!("Setting up TCP listener with non-blocking mode");
-    let tcp_listener = std::net::TcpListener::bind(addr).expect("Failed to bind address");
-    tcp_listener
-        .set_nonblocking(true)
-        .expect("Failed to set non-blocking");
-
-    debug!("Converting to tokio TCP listener");
-    let listener = tokio::net::TcpListener::from_std(tcp_listener)
-        .expect("Failed to create Tokio TCP listener");
+        .with_state(gateway.clone())
+        .layer(middleware::from_fn_with_state(
+            gateway.clone(),
+            cors::handle,
+        ))
+        .layer(middleware::from_fn_with_state(
+            gateway.clone(),
+            client_ip::handle,
+        ));
 
+    // Listeners on every HOST address, terminating TLS when a certificate is configured,
+    // and on the Unix domain socket when one is configured
+    let tls_config = TlsConfig::from_env();
+    let tls = tls_config.clone().map(|tls_config| {
+        let tls = Arc::new(Tls::load(tls_config).unwrap_or_else(|e| panic!("{}", e)));
+        tokio::spawn(tls.clone().watch());
+        tls
+    });
+    let tls_port = tls_config.and_then(|tls_config| tls_config.port);
+    let mut listeners = Vec::new();
+    let plain_tls = tls.clone().filter(|_| tls_port.is_none());
+    for listener in server::bind(&config.host, config.port).expect("Failed to bind address") {
+        listeners.push(
+            Listener::new(listener, plain_tls.clone(), config.proxy_protocol)
+                .expect("Failed to bind address"),
+        );
+    }
+    if let (Some(tls), Some(port)) = (tls, tls_port) {
+        for listener in server::bind(&config.host, port).expect("Failed to bind TLS address") {
+            listeners.push(
+                Listener::new(listener, Some(tls.clone()), config.proxy_protocol)
+                    .expect("Failed to bind TLS address"),
+            );
+        }
+    }
+    if let Some(unix) = UnixSocketConfig::from_env() {
+        listeners.push(
+            Listener::unix(&unix.path, unix.mode)
+                .unwrap_or_else(|e| panic!("Failed to bind {}: {}", unix.path, e)),
+        );
+    }
+    if listeners.is_empty() {
+        panic!("No listener configured, set HOST or UNIX_SOCKET");
+    }
     info!(
-        "AI Gateway listening on {}:{} with {} worker threads",
-        config.host, config.port, config.worker_threads
+        "AI Gateway starting {} listeners with {} worker threads",
+        listeners.len(),
+        config.worker_threads
     );
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap_or_else(|e| {
-            error!("Server error: {}", e);
-            std::process::exit(1);
+    if let (Some(admin_config), Some(store)) = (admin_config, admin_store) {
+        let admin = Arc::new(Admin::new(gateway.clone(), store, admin_config.token));
+        let listener =
+            tokio::net::TcpListener::bind((admin_config.host.as_str(), admin_config.port))
+                .await
+                .expect("Failed to bind admin address");
+        info!(
+            "Admin API listening on {}:{}",
+            admin_config.host, admin_config.port
+        );
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            axum::serve(listener, admin::router(admin))
+                .with_graceful_shutdown(async move { shutdown.reached(Phase::Closing).await })
+                .await
+                .unwrap_or_else(|e| error!("Admin server error: {}", e));
         });
-}
-
-async fn shutdown_signal() {
-    info!("Registering shutdown signal handler");
-    let ctrl_c = async {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("Failed to install CTRL+C signal handler")
-    };
+    }
 
-    let terminate = async {
-        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-            .expect("Failed to install signal handler")
-            .recv()
-            .await;
+    let server = server::serve(listeners, app, shutdown.clone());
+    // Terminated streams get a moment to deliver their error event before the process exits
+    let deadline = async {
+        shutdown.reached(Phase::Terminating).await;
+        tokio::time::sleep(TERMINATION_GRACE).await;
     };
-
     tokio::select! {
-        _ = ctrl_c => {},
-        _ = terminate => {},
+        _ = server => {}
+        _ = deadline => warn!("Connections still open after the drain deadline, exiting"),
+    }
+
+    // Flush the final counters and any buffered log output
+    let state = gateway.state();
+    let metrics = state.metrics.render();
+    if !metrics.is_empty() {
+        info!(target: "metrics", "Final metrics:\n{}", metrics.trim_end());
     }
-    info!("Shutdown signal received, starting graceful shutdown");
+    info!("Shutdown complete");
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
 }
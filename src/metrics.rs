@@ -0,0 +1,51 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Counters exported on `GET /metrics` in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    /// Counter values by metric name and rendered label set
+    counters: Mutex<BTreeMap<&'static str, BTreeMap<String, u64>>>,
+}
+
+impl Metrics {
+    /// Add `value` to the counter `name` with the given labels
+    pub fn increment(&self, name: &'static str, labels: &[(&str, &str)], value: u64) {
+        let labels = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        *self
+            .counters
+            .lock()
+            .entry(name)
+            .or_default()
+            .entry(labels)
+            .or_default() += value;
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (name, series) in self.counters.lock().iter() {
+            let _ = writeln!(output, "# TYPE {} counter", name);
+            for (labels, value) in series {
+                let _ = if labels.is_empty() {
+                    writeln!(output, "{} {}", name, value)
+                } else {
+                    writeln!(output, "{}{{{}}} {}", name, labels, value)
+                };
+            }
+        }
+        output
+    }
+}
+
+/// Escape a label value for the text exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
@@ -1,5 +1,5 @@
 use super::Provider;
-use crate::error::AppError;
+use crate::{context::RequestContext, error::AppError};
 use async_trait::async_trait;
 use axum::http::HeaderMap;
 use tracing::{debug, error};
@@ -18,7 +18,7 @@ impl AnthropicProvider {
 
 #[async_trait]
 impl Provider for AnthropicProvider {
-    fn base_url(&self) -> String {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
         self.base_url.clone()
     }
 
@@ -26,15 +26,16 @@ impl Provider for AnthropicProvider {
         "anthropic"
     }
 
-    fn transform_path(&self, path: &str) -> String {
-        if path.contains("/chat/completions") {
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        if ctx.path.contains("/chat/completions") {
             "/v1/messages".to_string()
         } else {
-            path.to_string()
+            ctx.path.clone()
         }
     }
 
-    fn process_headers(&self, original_headers: &HeaderMap) -> Result<HeaderMap, AppError> {
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
         debug!("Processing Anthropic request headers");
         let mut headers = HeaderMap::new();
 
@@ -0,0 +1,41 @@
+use super::multipart;
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+use tracing::debug;
+
+/// Whisper-style audio endpoints taking `multipart/form-data` uploads
+const TRANSCRIPTION_PATHS: [&str; 2] = ["/v1/audio/transcriptions", "/v1/audio/translations"];
+
+/// Whether the path is a Whisper-style transcription or translation endpoint
+pub(super) fn is_transcription_path(path: &str) -> bool {
+    TRANSCRIPTION_PATHS.contains(&path)
+}
+
+/// A captured multipart transcription request
+#[derive(Debug, Clone)]
+pub(super) struct Transcription {
+    boundary: String,
+    pub model: String,
+}
+
+impl Transcription {
+    /// Capture the form boundary and model of a transcription upload
+    pub fn capture(path: &str, headers: &HeaderMap, body: &Bytes) -> Option<Self> {
+        if !is_transcription_path(path) {
+            return None;
+        }
+        let boundary = multipart::boundary(headers)?;
+        let model = multipart::field(body, &boundary, "model")?;
+        debug!("Captured transcription request for model {}", model);
+        Some(Self { boundary, model })
+    }
+
+    /// Rewrite the upload's `model` field when the provider names the model differently
+    pub fn with_model(&self, body: Bytes, model: &str) -> Bytes {
+        if model == self.model {
+            return body;
+        }
+        debug!("Mapping transcription model {} to {}", self.model, model);
+        multipart::replace_field(&body, &self.boundary, "model", model)
+    }
+}
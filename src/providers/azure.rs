@@ -0,0 +1,296 @@
+use super::{Provider, PATH_SEGMENT};
+use crate::{config::AppConfig, context::RequestContext, error::AppError};
+use async_trait::async_trait;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    http::{HeaderMap, HeaderValue, Response},
+};
+use percent_encoding::utf8_percent_encode;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::{debug, error};
+
+/// AzureProvider handles Azure OpenAI deployments
+pub struct AzureProvider {
+    endpoint: Option<String>,
+    /// Endpoints callers may select with `x-azure-endpoint`, besides `endpoint`
+    allowed_endpoints: Vec<String>,
+    api_version: String,
+    deployments: HashMap<String, String>,
+}
+
+/// Endpoint, API version and deployment resolved for a request
+#[derive(Debug, Clone)]
+struct AzureRequest {
+    endpoint: String,
+    api_version: String,
+    /// `None` for resource-level operations such as listing models
+    deployment: Option<String>,
+}
+
+impl AzureProvider {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            endpoint: config
+                .azure_endpoint
+                .as_deref()
+                .map(|e| e.trim_end_matches('/').to_string()),
+            allowed_endpoints: config.azure_allowed_endpoints.clone(),
+            api_version: config.azure_api_version.clone(),
+            deployments: config.azure_deployments.clone(),
+        }
+    }
+
+    /// Resolve the deployment for a model, falling back to the model name itself
+    fn resolve_deployment(&self, model: &str) -> String {
+        match self.deployments.get(model) {
+            Some(deployment) => {
+                debug!("Mapped model {} to Azure deployment {}", model, deployment);
+                deployment.clone()
+            }
+            None => {
+                debug!(
+                    "No deployment mapping for model {}, using model name",
+                    model
+                );
+                model.to_string()
+            }
+        }
+    }
+
+    /// Whether `endpoint` may be selected per request. The caller's key is sent to the
+    /// endpoint, so arbitrary hosts must never be reachable.
+    fn is_allowed_endpoint(&self, endpoint: &str) -> bool {
+        self.endpoint.as_deref() == Some(endpoint)
+            || self.allowed_endpoints.iter().any(|e| e == endpoint)
+    }
+
+    /// Resource-level operations that are not scoped to a deployment and carry no body
+    fn is_resource_path(path: &str) -> bool {
+        path == "/v1/models" || path.starts_with("/v1/models/")
+    }
+
+    /// Entra ID access tokens are JWTs, anything else is treated as an Azure API key
+    fn is_entra_token(token: &str) -> bool {
+        token.starts_with("eyJ") && token.split('.').count() == 3
+    }
+
+    /// Rewrite Azure content-filter errors into the gateway error format
+    fn normalize_error(body: &Value) -> Option<Value> {
+        let error = body.get("error")?;
+        let code = error
+            .get("code")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let inner_code = error
+            .pointer("/innererror/code")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        if code != "content_filter" && inner_code != "ResponsibleAIPolicyViolation" {
+            return None;
+        }
+
+        Some(json!({
+            "error": {
+                "message": error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("The request was blocked by the Azure content filter"),
+                "type": "content_filter",
+                "code": "content_filter",
+                "param": error.get("param").cloned().unwrap_or(Value::Null),
+                "provider": "azure",
+                "content_filter_result": error
+                    .pointer("/innererror/content_filter_result")
+                    .cloned()
+                    .unwrap_or(Value::Null),
+                "provider_error": body,
+            }
+        }))
+    }
+}
+
+#[async_trait]
+impl Provider for AzureProvider {
+    fn base_url(&self, ctx: &RequestContext) -> String {
+        ctx.get::<AzureRequest>()
+            .map(|r| r.endpoint.clone())
+            .unwrap_or_default()
+    }
+
+    fn name(&self) -> &str {
+        "azure"
+    }
+
+    fn requires_body(&self, path: &str) -> bool {
+        !Self::is_resource_path(path)
+    }
+
+    async fn before_request(
+        &self,
+        ctx: &mut RequestContext,
+        _body: &Bytes,
+    ) -> Result<(), AppError> {
+        let endpoint = match ctx.header("x-azure-endpoint") {
+            Some(endpoint) => {
+                let endpoint = endpoint.trim_end_matches('/');
+                if !self.is_allowed_endpoint(endpoint) {
+                    error!("Refusing Azure endpoint {} not in configuration", endpoint);
+                    return Err(AppError::RequestError(
+                        "x-azure-endpoint is not an allowed Azure endpoint".to_string(),
+                    ));
+                }
+                debug!("Using Azure endpoint from x-azure-endpoint: {}", endpoint);
+                endpoint.to_string()
+            }
+            None => self.endpoint.clone().ok_or_else(|| {
+                error!("No Azure endpoint configured");
+                AppError::RequestError("Azure endpoint is not configured".to_string())
+            })?,
+        };
+
+        let api_version = ctx
+            .header("x-azure-api-version")
+            .unwrap_or(&self.api_version)
+            .to_string();
+
+        // An explicit deployment header wins over the model mapping
+        let deployment = if Self::is_resource_path(&ctx.path) {
+            None
+        } else if let Some(deployment) = ctx.header("x-azure-deployment") {
+            Some(deployment.to_string())
+        } else {
+            let model = ctx.model.as_deref().ok_or_else(|| {
+                error!("Unable to resolve Azure deployment: no model in request body");
+                AppError::UnsupportedModel
+            })?;
+            Some(self.resolve_deployment(model))
+        };
+
+        debug!("Using Azure deployment: {:?}", deployment);
+        ctx.insert(AzureRequest {
+            endpoint,
+            api_version,
+            deployment,
+        });
+        Ok(())
+    }
+
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        let Some(request) = ctx.get::<AzureRequest>() else {
+            return ctx.path.clone();
+        };
+        let operation = ctx.path.strip_prefix("/v1").unwrap_or(&ctx.path);
+        let api_version = utf8_percent_encode(&request.api_version, PATH_SEGMENT);
+        match &request.deployment {
+            Some(deployment) => format!(
+                "/openai/deployments/{}{}?api-version={}",
+                utf8_percent_encode(deployment, PATH_SEGMENT),
+                operation,
+                api_version
+            ),
+            None => format!("/openai{}?api-version={}", operation, api_version),
+        }
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
+        debug!("Processing Azure OpenAI request headers");
+        let mut headers = HeaderMap::new();
+
+        // Add content type
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        // Process authentication
+        if let Some(api_key) = original_headers
+            .get("api-key")
+            .and_then(|h| h.to_str().ok())
+        {
+            debug!("Using provided api-key header for Azure");
+            headers.insert(
+                http::header::HeaderName::from_static("api-key"),
+                http::header::HeaderValue::from_str(api_key).map_err(|_| {
+                    error!("Failed to process Azure api-key header");
+                    AppError::InvalidHeader
+                })?,
+            );
+        } else if let Some(auth) = original_headers
+            .get(http::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+        {
+            let token = auth.trim_start_matches("Bearer ").trim();
+            if token.is_empty() {
+                error!("Empty Bearer token in Azure authorization header");
+                return Err(AppError::InvalidHeader);
+            }
+
+            if Self::is_entra_token(token) {
+                debug!("Using Entra ID bearer token for Azure");
+                headers.insert(
+                    http::header::AUTHORIZATION,
+                    http::header::HeaderValue::from_str(&format!("Bearer {}", token)).map_err(
+                        |_| {
+                            error!("Failed to process Azure Entra ID token");
+                            AppError::InvalidHeader
+                        },
+                    )?,
+                );
+            } else {
+                debug!("Converting Bearer token to api-key format");
+                headers.insert(
+                    http::header::HeaderName::from_static("api-key"),
+                    http::header::HeaderValue::from_str(token).map_err(|_| {
+                        error!("Failed to process Azure authorization header");
+                        AppError::InvalidHeader
+                    })?,
+                );
+            }
+        } else {
+            error!("No api-key or authorization header found for Azure request");
+            return Err(AppError::MissingApiKey);
+        }
+
+        Ok(headers)
+    }
+
+    async fn process_response(
+        &self,
+        _ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        let is_json = response
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.contains("application/json"));
+
+        if !response.status().is_client_error() || !is_json {
+            return Ok(response);
+        }
+
+        let (mut parts, body) = response.into_parts();
+        let bytes = to_bytes(body, usize::MAX).await?;
+
+        let normalized = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|body| Self::normalize_error(&body));
+
+        match normalized {
+            Some(error_body) => {
+                debug!("Normalizing Azure content filter error");
+                let bytes = Bytes::from(serde_json::to_vec(&error_body)?);
+                parts.headers.remove(http::header::CONTENT_LENGTH);
+                parts.headers.insert(
+                    http::header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                );
+                Ok(Response::from_parts(parts, Body::from(bytes)))
+            }
+            None => Ok(Response::from_parts(parts, Body::from(bytes))),
+        }
+    }
+}
@@ -1,53 +1,109 @@
+use super::event_stream::EventStreamDecoder;
+use super::rerank::{self, RerankRequest};
 use super::Provider;
-use crate::error::AppError;
+use crate::{
+    context::RequestContext,
+    error::{provider_error, AppError},
+};
 use async_trait::async_trait;
-use aws_event_stream_parser::{parse_message, Message};
+use aws_event_stream_parser::Message;
 use axum::{
     body::{Body, Bytes},
-    http::{HeaderMap, HeaderValue, Response, StatusCode},
+    http::{HeaderMap, Response, StatusCode},
 };
 use futures_util::StreamExt;
-use parking_lot::RwLock;
 use serde_json::{json, Value};
-use std::sync::Arc;
-use tracing::{debug, error, warn};
+use tracing::{debug, error};
 
 /// Constants for default values
 const DEFAULT_REGION: &str = "us-east-1";
 const DEFAULT_MODEL: &str = "amazon.titan-text-premier-v1:0";
-const DEFAULT_FALLBACK_MODEL: &str = "mistral.mistral-7b-instruct-v0:2";
 const DEFAULT_MAX_TOKENS: u64 = 1000;
 const DEFAULT_TEMPERATURE: f64 = 0.7;
 const DEFAULT_TOP_P: f64 = 1.0;
 
 /// BedrockProvider handles AWS Bedrock API integration
-#[derive(Clone)]
-pub struct BedrockProvider {
-    base_url: Arc<RwLock<String>>,
-    region: Arc<RwLock<String>>,
-    current_model: Arc<RwLock<String>>,
+pub struct BedrockProvider;
+
+/// Region, model and rerank request resolved for a Bedrock request
+#[derive(Debug, Clone)]
+struct BedrockRequest {
+    region: String,
+    model: String,
+    rerank: Option<RerankRequest>,
+}
+
+impl BedrockRequest {
+    fn from_context(ctx: &RequestContext) -> Self {
+        Self {
+            region: ctx
+                .header("x-aws-region")
+                .unwrap_or(DEFAULT_REGION)
+                .to_string(),
+            model: ctx
+                .model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            rerank: None,
+        }
+    }
+}
+
+/// Translates a Bedrock event stream into OpenAI SSE chunks
+struct BedrockStream {
+    model: String,
+    decoder: EventStreamDecoder,
 }
 
 impl BedrockProvider {
     pub fn new() -> Self {
-        let region = DEFAULT_REGION.to_string();
-        debug!("Initializing BedrockProvider with region: {}", region);
+        debug!("Initializing BedrockProvider");
+        Self
+    }
 
-        Self {
-            base_url: Arc::new(RwLock::new(format!(
-                "https://bedrock-runtime.{}.amazonaws.com",
-                region
-            ))),
-            region: Arc::new(RwLock::new(region)),
-            current_model: Arc::new(RwLock::new(DEFAULT_MODEL.to_string())),
-        }
+    /// Request state stored by `before_request`, or defaults from the context
+    fn request(ctx: &RequestContext) -> BedrockRequest {
+        ctx.get::<BedrockRequest>()
+            .cloned()
+            .unwrap_or_else(|| BedrockRequest::from_context(ctx))
     }
 
-    fn get_model_name(&self, path: &str) -> String {
-        path.split('/')
-            .last()
-            .map(ToString::to_string)
-            .unwrap_or_else(|| DEFAULT_FALLBACK_MODEL.to_string())
+    /// Build a Bedrock Agent Runtime `Rerank` request from a normalized rerank request
+    fn transform_rerank_body(&self, region: &str, request: &RerankRequest) -> Value {
+        let model_arn = if request.model.starts_with("arn:") {
+            request.model.clone()
+        } else {
+            format!(
+                "arn:aws:bedrock:{}::foundation-model/{}",
+                region, request.model
+            )
+        };
+
+        let sources = request
+            .texts()
+            .into_iter()
+            .map(|text| {
+                json!({
+                    "type": "INLINE",
+                    "inlineDocumentSource": {
+                        "type": "TEXT",
+                        "textDocument": { "text": text }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "queries": [{ "type": "TEXT", "textQuery": { "text": request.query } }],
+            "sources": sources,
+            "rerankingConfiguration": {
+                "type": "BEDROCK_RERANKING_MODEL",
+                "bedrockRerankingConfiguration": {
+                    "numberOfResults": request.top_n.unwrap_or(request.documents.len()),
+                    "modelConfiguration": { "modelArn": model_arn }
+                }
+            }
+        })
     }
 
     fn transform_request_body(&self, body: Value) -> Result<Value, AppError> {
@@ -95,61 +151,84 @@ impl BedrockProvider {
         debug!("Transformed body: {:#?}", transformed);
         Ok(transformed)
     }
+}
 
-    fn transform_bedrock_chunk(&self, chunk: Bytes) -> Result<Bytes, AppError> {
+impl BedrockStream {
+    fn transform_bedrock_chunk(&mut self, chunk: Bytes) -> Result<Bytes, AppError> {
         debug!("Processing chunk of size: {}", chunk.len());
-        let mut remaining = chunk.as_ref();
         let mut response_events = Vec::new();
-
-        while !remaining.is_empty() {
-            match self.process_message(remaining) {
-                Ok((rest, events)) => {
-                    remaining = rest;
-                    response_events.extend(events);
-                }
-                Err(e) => {
-                    debug!("Failed to parse message: {:?}", e);
-                    break;
-                }
-            }
+        for message in self.decoder.push(&chunk)? {
+            response_events.extend(self.process_message(&message)?);
         }
-
         Ok(Bytes::from(response_events.join("")))
     }
 
-    fn process_message<'a>(&self, data: &'a [u8]) -> Result<(&'a [u8], Vec<String>), AppError> {
-        let (rest, message) =
-            parse_message(data).map_err(|e| AppError::EventStreamError(e.to_string()))?;
+    /// Fail when the upstream stream ended partway through a message
+    fn finish(&self) -> Result<(), AppError> {
+        match self.decoder.remaining() {
+            0 => Ok(()),
+            remaining => Err(AppError::EventStreamError(format!(
+                "stream ended inside a message, {} bytes left",
+                remaining
+            ))),
+        }
+    }
+
+    fn process_message(&self, message: &Message) -> Result<Vec<String>, AppError> {
+        if let Some(event) = self.exception_event(message) {
+            return Ok(vec![event]);
+        }
 
-        let event_type = self.get_event_type(&message);
+        let event_type = self.get_event_type(message);
         let events = match event_type.as_deref() {
-            Some("contentBlockDelta") => self.handle_content_block(&message)?,
-            Some("metadata") => self.handle_metadata(&message)?,
+            Some("contentBlockDelta") => self.handle_content_block(message)?,
+            Some("metadata") => self.handle_metadata(message)?,
             _ => {
                 debug!("Skipping event type: {:?}", event_type);
                 vec![]
             }
         };
 
-        if !message.valid() {
-            warn!("Invalid message checksum detected");
-        }
-
-        Ok((rest, events))
+        Ok(events)
     }
 
     fn get_event_type(&self, message: &Message) -> Option<String> {
+        Self::header(message, ":event-type")
+    }
+
+    fn header(message: &Message, name: &str) -> Option<String> {
         message
             .headers
             .headers
             .iter()
-            .find(|h| h.key == ":event-type")
+            .find(|h| h.key == name)
             .and_then(|h| match &h.value {
                 aws_event_stream_parser::HeaderValue::String(s) => Some(s.to_string()),
                 _ => None,
             })
     }
 
+    /// SSE error event for an exception message, e.g. `modelStreamErrorException` or `throttlingException`
+    fn exception_event(&self, message: &Message) -> Option<String> {
+        let kind = match Self::header(message, ":message-type").as_deref() {
+            Some("exception") => Self::header(message, ":exception-type")?,
+            Some("error") => Self::header(message, ":error-code")?,
+            _ => return None,
+        };
+        let body = match Self::header(message, ":error-message") {
+            Some(error_message) => json!({ "message": error_message }).to_string().into_bytes(),
+            None => message.body.to_vec(),
+        };
+
+        error!(
+            "Bedrock stream exception {}: {}",
+            kind,
+            String::from_utf8_lossy(&body)
+        );
+        let error_body = provider_error("bedrock", StatusCode::BAD_GATEWAY, &body, Some(&kind));
+        Some(format!("data: {}\n\n", error_body))
+    }
+
     fn handle_content_block(&self, message: &Message) -> Result<Vec<String>, AppError> {
         let body_str = String::from_utf8(message.body.to_vec())?;
         let json: Value = serde_json::from_str(&body_str)?;
@@ -186,7 +265,7 @@ impl BedrockProvider {
             "id": "chatcmpl-bedrock",
             "object": "chat.completion.chunk",
             "created": chrono::Utc::now().timestamp(),
-            "model": self.current_model.read().as_str(),
+            "model": self.model,
             "choices": [{
                 "index": 0,
                 "delta": {
@@ -202,7 +281,7 @@ impl BedrockProvider {
             "id": "chatcmpl-bedrock",
             "object": "chat.completion.chunk",
             "created": chrono::Utc::now().timestamp(),
-            "model": self.current_model.read().as_str(),
+            "model": self.model,
             "choices": [{
                 "index": 0,
                 "delta": {},
@@ -215,46 +294,63 @@ impl BedrockProvider {
 
 #[async_trait]
 impl Provider for BedrockProvider {
-    fn base_url(&self) -> String {
-        self.base_url.read().clone()
+    fn base_url(&self, ctx: &RequestContext) -> String {
+        let request = Self::request(ctx);
+        // Rerank is served by the Agent Runtime rather than the model runtime
+        let service = if request.rerank.is_some() {
+            "bedrock-agent-runtime"
+        } else {
+            "bedrock-runtime"
+        };
+        format!("https://{}.{}.amazonaws.com", service, request.region)
     }
 
     fn name(&self) -> &str {
         "bedrock"
     }
 
-    async fn before_request(&self, headers: &HeaderMap, body: &Bytes) -> Result<(), AppError> {
-        // Extract and set the model from the request body before any other processing
-        if let Ok(request_body) = serde_json::from_slice::<Value>(body) {
-            if let Some(model) = request_body["model"].as_str() {
-                debug!("Setting model from before_request: {}", model);
-                *self.current_model.write() = model.to_string();
-            }
-        }
-
-        // Extract and set the region from the request headers
-        if let Some(region) = headers.get("x-aws-region").and_then(|h| h.to_str().ok()) {
-            debug!("Setting region from before_request: {}", region);
-            *self.region.write() = region.to_string();
-            *self.base_url.write() = format!("https://bedrock-runtime.{}.amazonaws.com", region);
-        }
+    fn requires_body(&self, _path: &str) -> bool {
+        true
+    }
 
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        let mut request = BedrockRequest::from_context(ctx);
+        request.rerank = rerank::capture(&ctx.path, body)?;
+        debug!(
+            "Bedrock request for model {} in region {}",
+            request.model, request.region
+        );
+        ctx.insert(request);
         Ok(())
     }
 
-    fn transform_path(&self, path: &str) -> String {
-        let model = self.current_model.read();
-        debug!("Transforming path with model: {}", *model);
-        format!("/model/{}/converse-stream", *model)
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        let request = Self::request(ctx);
+        if request.rerank.is_some() {
+            return "/rerank".to_string();
+        }
+        debug!("Transforming path with model: {}", request.model);
+        format!("/model/{}/converse-stream", request.model)
     }
 
-    async fn prepare_request_body(&self, body: Bytes) -> Result<Bytes, AppError> {
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        let request = Self::request(ctx);
+        if let Some(rerank) = &request.rerank {
+            return Ok(Bytes::from(serde_json::to_vec(
+                &self.transform_rerank_body(&request.region, rerank),
+            )?));
+        }
         let request_body: Value = serde_json::from_slice(&body)?;
         let transformed_body = self.transform_request_body(request_body)?;
         Ok(Bytes::from(serde_json::to_vec(&transformed_body)?))
     }
 
-    fn process_headers(&self, headers: &HeaderMap) -> Result<HeaderMap, AppError> {
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let headers = &ctx.headers;
         let mut final_headers = HeaderMap::new();
 
         // Add standard headers
@@ -277,49 +373,65 @@ impl Provider for BedrockProvider {
         true
     }
 
-    fn get_signing_credentials(&self, headers: &HeaderMap) -> Option<(String, String, String)> {
-        let access_key = headers.get("x-aws-access-key-id")?.to_str().ok()?;
-        let secret_key = headers.get("x-aws-secret-access-key")?.to_str().ok()?;
-        let region = headers
-            .get("x-aws-region")
-            .and_then(|h| h.to_str().ok())
-            .map(String::from)
-            .unwrap_or_else(|| self.region.read().clone());
+    fn get_signing_credentials(&self, ctx: &RequestContext) -> Option<(String, String, String)> {
+        let access_key = ctx.header("x-aws-access-key-id")?;
+        let secret_key = ctx.header("x-aws-secret-access-key")?;
+        let region = Self::request(ctx).region;
 
         Some((access_key.to_string(), secret_key.to_string(), region))
     }
 
-    fn get_signing_host(&self) -> String {
-        let region = self.region.read().clone();
-        format!("bedrock-runtime.{}.amazonaws.com", region)
-    }
+    async fn process_response(
+        &self,
+        ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        let request = Self::request(ctx);
+        if let Some(rerank) = request.rerank {
+            return rerank::process_response(response, |body| {
+                rerank.response(
+                    None,
+                    rerank::results(body.get("results"), "relevanceScore"),
+                    None,
+                )
+            })
+            .await;
+        }
 
-    async fn process_response(&self, response: Response<Body>) -> Result<Response<Body>, AppError> {
         if response
             .headers()
             .get(http::header::CONTENT_TYPE)
             .and_then(|v| v.to_str().ok())
-            .map_or(false, |ct| {
-                ct.contains("application/vnd.amazon.eventstream")
-            })
+            .is_some_and(|ct| ct.contains("application/vnd.amazon.eventstream"))
         {
             debug!("Processing Bedrock event stream response");
 
             // Create transformed stream
-            let provider = self.clone();
-            let stream = response
-                .into_body()
-                .into_data_stream()
-                .map(move |chunk| match chunk {
-                    Ok(bytes) => match provider.transform_bedrock_chunk(bytes) {
-                        Ok(transformed) => Ok(transformed),
+            let provider = BedrockStream {
+                model: request.model,
+                decoder: EventStreamDecoder::default(),
+            };
+            let stream = futures_util::stream::unfold(
+                Some((response.into_body().into_data_stream(), provider)),
+                |state| async move {
+                    let (mut upstream, mut provider) = state?;
+                    let transformed = match upstream.next().await {
+                        Some(Ok(bytes)) => provider.transform_bedrock_chunk(bytes),
+                        Some(Err(e)) => Err(AppError::AxumError(e)),
+                        None => match provider.finish() {
+                            Ok(()) => return None,
+                            Err(e) => Err(e),
+                        },
+                    };
+                    match transformed {
+                        Ok(bytes) => Some((Ok(bytes), Some((upstream, provider)))),
                         Err(e) => {
                             error!("Error transforming chunk: {}", e);
-                            Err(std::io::Error::new(std::io::ErrorKind::Other, e))
+                            Some((Err(std::io::Error::other(e)), None))
                         }
-                    },
-                    Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
-                });
+                    }
+                },
+            );
 
             // Build response with transformed stream and all necessary headers
             Ok(Response::builder()
@@ -329,31 +441,12 @@ impl Provider for BedrockProvider {
                 .header("cache-control", "no-cache")
                 .header("connection", "keep-alive")
                 .header("transfer-encoding", "chunked")
-                // CORS headers
-                .header("access-control-allow-origin", "*")
-                .header("access-control-allow-methods", "POST, OPTIONS")
-                .header("access-control-allow-headers", "content-type, x-provider, x-aws-access-key-id, x-aws-secret-access-key, x-aws-region")
-                .header("access-control-expose-headers", "*")
                 // SSE specific headers for better client compatibility
                 .header("x-accel-buffering", "no")
                 .header("keep-alive", "timeout=600")
                 .body(Body::from_stream(stream))
                 .unwrap())
         } else {
-            // For non-streaming responses, still add CORS headers
-            let mut response = response;
-            let headers = response.headers_mut();
-            headers.insert("access-control-allow-origin", HeaderValue::from_static("*"));
-            headers.insert(
-                "access-control-allow-methods",
-                HeaderValue::from_static("POST, OPTIONS"),
-            );
-            headers.insert("access-control-allow-headers",
-                           HeaderValue::from_static("content-type, x-provider, x-aws-access-key-id, x-aws-secret-access-key, x-aws-region"));
-            headers.insert(
-                "access-control-expose-headers",
-                HeaderValue::from_static("*"),
-            );
             Ok(response)
         }
     }
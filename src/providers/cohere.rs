@@ -0,0 +1,440 @@
+use super::rerank::{self, RerankRequest};
+use super::stream::{sse_event, transform_lines, LineTransform, SSE_DONE};
+use super::Provider;
+use crate::{context::RequestContext, error::AppError};
+use async_trait::async_trait;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    http::{HeaderMap, HeaderValue, Response},
+};
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+/// CohereProvider handles Cohere Chat v2 and Rerank
+pub struct CohereProvider {
+    base_url: String,
+}
+
+/// Endpoint and options captured for a Cohere request
+#[derive(Debug, Clone, Default)]
+struct CohereRequest {
+    is_chat: bool,
+    stream: bool,
+    rerank: Option<RerankRequest>,
+}
+
+impl CohereProvider {
+    pub fn new() -> Self {
+        Self {
+            base_url: "https://api.cohere.com".to_string(),
+        }
+    }
+
+    fn request(ctx: &RequestContext) -> CohereRequest {
+        ctx.get::<CohereRequest>().cloned().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Provider for CohereProvider {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
+        self.base_url.clone()
+    }
+
+    fn name(&self) -> &str {
+        "cohere"
+    }
+
+    fn requires_body(&self, _path: &str) -> bool {
+        true
+    }
+
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        let request = CohereRequest {
+            is_chat: ctx.path.ends_with("/chat/completions"),
+            stream: serde_json::from_slice::<Value>(body)
+                .ok()
+                .and_then(|b| b["stream"].as_bool())
+                .unwrap_or(false),
+            rerank: rerank::capture(&ctx.path, body)?,
+        };
+        ctx.insert(request);
+        Ok(())
+    }
+
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        let request = Self::request(ctx);
+        if request.is_chat {
+            "/v2/chat".to_string()
+        } else if request.rerank.is_some() {
+            "/v2/rerank".to_string()
+        } else {
+            ctx.path.clone()
+        }
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
+        debug!("Processing Cohere request headers");
+        let mut headers = HeaderMap::new();
+
+        // Add content type
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        // Process authentication
+        if let Some(auth) = original_headers
+            .get(http::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+        {
+            debug!("Using provided authorization header for Cohere");
+            headers.insert(
+                http::header::AUTHORIZATION,
+                http::header::HeaderValue::from_str(auth).map_err(|_| {
+                    error!("Failed to process Cohere authorization header");
+                    AppError::InvalidHeader
+                })?,
+            );
+        } else {
+            error!("No authorization header found for Cohere request");
+            return Err(AppError::MissingApiKey);
+        }
+
+        Ok(headers)
+    }
+
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        let request = Self::request(ctx);
+        let transformed = if let Some(request) = &request.rerank {
+            let mut transformed = json!({
+                "model": request.model,
+                "query": request.query,
+                "documents": request.texts(),
+            });
+            if let Some(top_n) = request.top_n {
+                transformed["top_n"] = json!(top_n);
+            }
+            transformed
+        } else if request.is_chat {
+            to_cohere_chat(&serde_json::from_slice(&body)?)?
+        } else {
+            return Ok(body);
+        };
+        debug!("Transformed Cohere body: {:#?}", transformed);
+        Ok(Bytes::from(serde_json::to_vec(&transformed)?))
+    }
+
+    async fn process_response(
+        &self,
+        ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        let request = Self::request(ctx);
+        if let Some(rerank) = request.rerank {
+            return rerank::process_response(response, |body| {
+                rerank.response(
+                    body["id"].as_str().map(String::from),
+                    rerank::results(body.get("results"), "relevance_score"),
+                    body.pointer("/meta/billed_units").cloned(),
+                )
+            })
+            .await;
+        }
+
+        if !request.is_chat || !response.status().is_success() {
+            return Ok(response);
+        }
+
+        let model = ctx.model.clone().unwrap_or_default();
+        let (mut parts, body) = response.into_parts();
+        parts.headers.remove(http::header::CONTENT_LENGTH);
+
+        if request.stream {
+            debug!("Processing Cohere event stream response");
+            let body = transform_lines(body, CohereStreamTransform::new(model));
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        let bytes = to_bytes(body, usize::MAX).await?;
+        let cohere_response: Value = serde_json::from_slice(&bytes)?;
+        let transformed = from_cohere_chat(&cohere_response, &model);
+
+        parts.headers.insert(
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        Ok(Response::from_parts(
+            parts,
+            Body::from(serde_json::to_vec(&transformed)?),
+        ))
+    }
+}
+
+/// Translate an OpenAI chat completion request into a Cohere Chat v2 request
+fn to_cohere_chat(body: &Value) -> Result<Value, AppError> {
+    let messages = body
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| {
+            error!("Invalid request format: messages array not found");
+            AppError::InvalidRequestFormat
+        })?;
+
+    let messages = messages
+        .iter()
+        .map(|msg| {
+            let role = match msg["role"].as_str().unwrap_or("user") {
+                "developer" => "system",
+                role => role,
+            };
+            let mut message = json!({ "role": role });
+            if !msg["content"].is_null() {
+                message["content"] = msg["content"].clone();
+            }
+            for field in ["tool_calls", "tool_call_id"] {
+                if let Some(value) = msg.get(field).filter(|v| !v.is_null()) {
+                    message[field] = value.clone();
+                }
+            }
+            message
+        })
+        .collect::<Vec<_>>();
+
+    let mut request = json!({
+        "model": body["model"],
+        "messages": messages,
+        "stream": body["stream"].as_bool().unwrap_or(false),
+    });
+
+    let mappings = [
+        ("tools", "tools"),
+        ("temperature", "temperature"),
+        ("top_p", "p"),
+        ("top_k", "k"),
+        ("seed", "seed"),
+        ("frequency_penalty", "frequency_penalty"),
+        ("presence_penalty", "presence_penalty"),
+        ("max_tokens", "max_tokens"),
+        ("max_completion_tokens", "max_tokens"),
+    ];
+    for (openai_key, cohere_key) in mappings {
+        if let Some(value) = body.get(openai_key).filter(|v| !v.is_null()) {
+            request[cohere_key] = value.clone();
+        }
+    }
+
+    match body.get("stop") {
+        Some(Value::String(stop)) => request["stop_sequences"] = json!([stop]),
+        Some(Value::Array(stop)) => request["stop_sequences"] = json!(stop),
+        _ => {}
+    }
+
+    match body.get("tool_choice").and_then(Value::as_str) {
+        Some("required") => request["tool_choice"] = json!("REQUIRED"),
+        Some("none") => request["tool_choice"] = json!("NONE"),
+        _ => {}
+    }
+
+    match body
+        .pointer("/response_format/type")
+        .and_then(Value::as_str)
+    {
+        Some("json_object") => request["response_format"] = json!({ "type": "json_object" }),
+        Some("json_schema") => {
+            request["response_format"] = json!({
+                "type": "json_object",
+                "json_schema": body
+                    .pointer("/response_format/json_schema/schema")
+                    .cloned()
+                    .unwrap_or(Value::Null),
+            })
+        }
+        _ => {}
+    }
+
+    Ok(request)
+}
+
+fn finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        "TOOL_CALL" => "tool_calls",
+        "ERROR_TOXIC" => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn usage(usage: &Value) -> Value {
+    let tokens = usage
+        .get("tokens")
+        .or_else(|| usage.get("billed_units"))
+        .unwrap_or(&Value::Null);
+    let prompt_tokens = tokens["input_tokens"].as_u64().unwrap_or(0);
+    let completion_tokens = tokens["output_tokens"].as_u64().unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+fn from_cohere_chat(response: &Value, model: &str) -> Value {
+    let text = response
+        .pointer("/message/content")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|part| part["text"].as_str())
+        .collect::<Vec<_>>()
+        .join("");
+
+    let mut message = json!({ "role": "assistant", "content": text });
+    if let Some(tool_calls) = response
+        .pointer("/message/tool_calls")
+        .filter(|calls| calls.as_array().is_some_and(|c| !c.is_empty()))
+    {
+        message["tool_calls"] = tool_calls.clone();
+        if text.is_empty() {
+            message["content"] = Value::Null;
+        }
+    }
+
+    json!({
+        "id": response["id"].as_str().unwrap_or("chatcmpl-cohere"),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(response["finish_reason"].as_str().unwrap_or("COMPLETE")),
+        }],
+        "usage": usage(&response["usage"]),
+    })
+}
+
+/// Translates Cohere Chat v2 stream events into OpenAI chat completion chunks
+struct CohereStreamTransform {
+    id: String,
+    model: String,
+    created: i64,
+}
+
+impl CohereStreamTransform {
+    fn new(model: String) -> Self {
+        Self {
+            id: "chatcmpl-cohere".to_string(),
+            model,
+            created: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason,
+            }],
+        })
+    }
+}
+
+impl LineTransform for CohereStreamTransform {
+    fn line(&mut self, line: &str) -> Vec<String> {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return vec![];
+        };
+        let event: Value = match serde_json::from_str(data) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Failed to parse Cohere stream event: {}", e);
+                return vec![];
+            }
+        };
+
+        let index = event["index"].as_u64().unwrap_or(0);
+        let chunk = match event["type"].as_str() {
+            Some("message-start") => {
+                if let Some(id) = event["id"].as_str() {
+                    self.id = id.to_string();
+                }
+                self.chunk(json!({ "role": "assistant", "content": "" }), None)
+            }
+            Some("content-delta") => {
+                let Some(text) = event
+                    .pointer("/delta/message/content/text")
+                    .and_then(Value::as_str)
+                else {
+                    return vec![];
+                };
+                self.chunk(json!({ "content": text }), None)
+            }
+            Some("tool-call-start") => {
+                let call = event
+                    .pointer("/delta/message/tool_calls")
+                    .cloned()
+                    .unwrap_or_default();
+                self.chunk(
+                    json!({
+                        "tool_calls": [{
+                            "index": index,
+                            "id": call["id"],
+                            "type": "function",
+                            "function": {
+                                "name": call.pointer("/function/name").cloned().unwrap_or(Value::Null),
+                                "arguments": call.pointer("/function/arguments").cloned().unwrap_or(json!("")),
+                            }
+                        }]
+                    }),
+                    None,
+                )
+            }
+            Some("tool-call-delta") => {
+                let arguments = event
+                    .pointer("/delta/message/tool_calls/function/arguments")
+                    .cloned()
+                    .unwrap_or(json!(""));
+                self.chunk(
+                    json!({
+                        "tool_calls": [{ "index": index, "function": { "arguments": arguments } }]
+                    }),
+                    None,
+                )
+            }
+            Some("message-end") => {
+                let reason = finish_reason(
+                    event
+                        .pointer("/delta/finish_reason")
+                        .and_then(Value::as_str)
+                        .unwrap_or("COMPLETE"),
+                );
+                let mut chunk = self.chunk(json!({}), Some(reason));
+                if let Some(usage_value) = event.pointer("/delta/usage") {
+                    chunk["usage"] = usage(usage_value);
+                }
+                chunk
+            }
+            other => {
+                debug!("Skipping Cohere event type: {:?}", other);
+                return vec![];
+            }
+        };
+
+        vec![sse_event(&chunk)]
+    }
+
+    fn finish(&mut self) -> Vec<String> {
+        vec![SSE_DONE.to_string()]
+    }
+}
@@ -0,0 +1,181 @@
+use crate::error::AppError;
+use aws_event_stream_parser::{parse_message, Message};
+use bytes::BytesMut;
+use tracing::debug;
+
+/// Total length, headers length and prelude CRC
+const PRELUDE_LENGTH: usize = 12;
+/// Trailing message CRC
+const CHECKSUM_LENGTH: usize = 4;
+/// Largest message the AWS event stream encoding allows
+const MAX_MESSAGE_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Decodes `application/vnd.amazon.eventstream` messages from a byte stream,
+/// buffering frames that straddle chunk boundaries and validating both CRCs
+#[derive(Default)]
+pub(super) struct EventStreamDecoder {
+    buffer: BytesMut,
+}
+
+impl EventStreamDecoder {
+    /// Add a chunk and return every message it completes
+    pub(super) fn push(&mut self, chunk: &[u8]) -> Result<Vec<Message>, AppError> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut messages = Vec::new();
+        while let Some(frame) = self.next_frame()? {
+            let (_, message) = parse_message(&frame).map_err(|e| {
+                debug!("Failed to parse event stream message: {:?}", e);
+                AppError::EventStreamError("malformed message headers".to_string())
+            })?;
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    /// Bytes of an incomplete message left when the stream ended
+    pub(super) fn remaining(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn next_frame(&mut self) -> Result<Option<BytesMut>, AppError> {
+        if self.buffer.len() < PRELUDE_LENGTH {
+            return Ok(None);
+        }
+
+        let prelude = &self.buffer[..PRELUDE_LENGTH];
+        let total_length = be_u32(&prelude[0..4]) as usize;
+        let headers_length = be_u32(&prelude[4..8]) as usize;
+        if crc32fast::hash(&prelude[..8]) != be_u32(&prelude[8..12]) {
+            return Err(AppError::EventStreamError(
+                "prelude checksum mismatch".to_string(),
+            ));
+        }
+        if total_length > MAX_MESSAGE_LENGTH
+            || total_length < PRELUDE_LENGTH + headers_length + CHECKSUM_LENGTH
+        {
+            return Err(AppError::EventStreamError(format!(
+                "invalid message length {}",
+                total_length
+            )));
+        }
+        if self.buffer.len() < total_length {
+            return Ok(None);
+        }
+
+        let frame = self.buffer.split_to(total_length);
+        let (content, checksum) = frame.split_at(total_length - CHECKSUM_LENGTH);
+        if crc32fast::hash(content) != be_u32(checksum) {
+            return Err(AppError::EventStreamError(
+                "message checksum mismatch".to_string(),
+            ));
+        }
+        Ok(Some(frame))
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_event_stream_parser::HeaderValue;
+
+    /// A Bedrock `ConverseStream` response: message start, two text deltas with multi-byte
+    /// characters, block and message stop, and usage metadata
+    const RECORDED: &[u8] = include_bytes!("testdata/bedrock-converse-stream.bin");
+
+    const EVENT_TYPES: [&str; 6] = [
+        "messageStart",
+        "contentBlockDelta",
+        "contentBlockDelta",
+        "contentBlockStop",
+        "messageStop",
+        "metadata",
+    ];
+
+    /// Event type and body of each message
+    fn summary(messages: &[Message]) -> Vec<(String, String)> {
+        messages
+            .iter()
+            .map(|message| {
+                let event_type = message
+                    .headers
+                    .headers
+                    .iter()
+                    .find(|h| h.key == ":event-type")
+                    .and_then(|h| match &h.value {
+                        HeaderValue::String(s) => Some(s.to_string()),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                (
+                    event_type,
+                    String::from_utf8(message.body.to_vec()).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn decode(chunks: &[&[u8]]) -> (Vec<(String, String)>, usize) {
+        let mut decoder = EventStreamDecoder::default();
+        let mut messages = Vec::new();
+        for chunk in chunks {
+            messages.extend(decoder.push(chunk).unwrap());
+        }
+        (summary(&messages), decoder.remaining())
+    }
+
+    #[test]
+    fn decodes_recorded_stream() {
+        let (messages, remaining) = decode(&[RECORDED]);
+        assert_eq!(remaining, 0);
+        let event_types: Vec<&str> = messages.iter().map(|(e, _)| e.as_str()).collect();
+        assert_eq!(event_types, EVENT_TYPES);
+        assert!(messages[1].1.contains(r#""text":"Héllo""#));
+        assert!(messages[2].1.contains(r#""text":" wörld 👋""#));
+        assert!(messages[5].1.contains(r#""totalTokens":17"#));
+    }
+
+    #[test]
+    fn split_at_every_offset() {
+        let (expected, _) = decode(&[RECORDED]);
+        for offset in 0..=RECORDED.len() {
+            let (head, tail) = RECORDED.split_at(offset);
+            let (messages, remaining) = decode(&[head, tail]);
+            assert_eq!(messages, expected, "split at byte {}", offset);
+            assert_eq!(remaining, 0, "split at byte {}", offset);
+        }
+    }
+
+    #[test]
+    fn one_byte_at_a_time() {
+        let (expected, _) = decode(&[RECORDED]);
+        let chunks: Vec<&[u8]> = RECORDED.chunks(1).collect();
+        let (messages, remaining) = decode(&chunks);
+        assert_eq!(messages, expected);
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn truncated_stream_leaves_remaining_bytes() {
+        let (messages, remaining) = decode(&[&RECORDED[..RECORDED.len() - 1]]);
+        assert_eq!(messages.len(), EVENT_TYPES.len() - 1);
+        assert!(remaining > 0);
+    }
+
+    #[test]
+    fn rejects_corrupted_frames() {
+        let mut prelude = RECORDED.to_vec();
+        prelude[9] ^= 0xff;
+        let mut decoder = EventStreamDecoder::default();
+        assert!(decoder.push(&prelude).is_err());
+
+        let mut payload = RECORDED.to_vec();
+        payload[40] ^= 0xff;
+        let mut decoder = EventStreamDecoder::default();
+        assert!(decoder.push(&payload).is_err());
+    }
+}
@@ -1,9 +1,14 @@
+use super::audio::{self, Transcription};
 use super::Provider;
-use crate::error::AppError;
+use crate::{context::RequestContext, error::AppError};
 use async_trait::async_trait;
-use axum::http::HeaderMap;
+use axum::{body::Bytes, http::HeaderMap};
 use tracing::{debug, error};
 
+/// Fireworks serves Whisper models from dedicated audio hosts
+const AUDIO_BASE_URL: &str = "https://audio-prod.api.fireworks.ai/v1";
+const AUDIO_TURBO_BASE_URL: &str = "https://audio-turbo.api.fireworks.ai/v1";
+
 pub struct FireworksProvider {
     base_url: String,
 }
@@ -14,19 +19,58 @@ impl FireworksProvider {
             base_url: "https://api.fireworks.ai/inference/v1".to_string(),
         }
     }
+
+    /// Fireworks serves Whisper under its own model names
+    fn whisper_model(model: &str) -> &str {
+        match model {
+            "whisper-1" => "whisper-v3",
+            other => other,
+        }
+    }
 }
 
 #[async_trait]
 impl Provider for FireworksProvider {
-    fn base_url(&self) -> String {
-        self.base_url.clone()
+    fn base_url(&self, ctx: &RequestContext) -> String {
+        match ctx.get::<Transcription>() {
+            Some(t) if Self::whisper_model(&t.model) == "whisper-v3-turbo" => {
+                AUDIO_TURBO_BASE_URL.to_string()
+            }
+            Some(_) => AUDIO_BASE_URL.to_string(),
+            None => self.base_url.clone(),
+        }
     }
 
     fn name(&self) -> &str {
         "fireworks"
     }
 
-    fn process_headers(&self, original_headers: &HeaderMap) -> Result<HeaderMap, AppError> {
+    fn requires_body(&self, path: &str) -> bool {
+        audio::is_transcription_path(path)
+    }
+
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        if let Some(transcription) = Transcription::capture(&ctx.path, &ctx.headers, body) {
+            ctx.insert(transcription);
+        }
+        Ok(())
+    }
+
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        Ok(match ctx.get::<Transcription>() {
+            Some(transcription) => {
+                transcription.with_model(body, Self::whisper_model(&transcription.model))
+            }
+            None => body,
+        })
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
         debug!("Processing Fireworks request headers");
         let mut headers = HeaderMap::new();
 
@@ -80,7 +124,8 @@ impl Provider for FireworksProvider {
         Ok(headers)
     }
 
-    fn transform_path(&self, path: &str) -> String {
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        let path = &ctx.path;
         // The incoming path is /v1/chat/completions
         // We want to strip the /v1 prefix since it's already in the base_url
         if path.starts_with("/v1/") {
@@ -0,0 +1,662 @@
+use super::stream::{sse_event, transform_lines, LineTransform, SSE_DONE};
+use super::{Provider, PATH_SEGMENT};
+use crate::{
+    context::RequestContext,
+    error::{provider_error, AppError},
+};
+use async_trait::async_trait;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
+};
+use percent_encoding::utf8_percent_encode;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use tracing::{debug, error};
+
+/// GeminiProvider handles the Google AI Studio Gemini API
+pub struct GeminiProvider {
+    base_url: String,
+}
+
+impl GeminiProvider {
+    pub fn new() -> Self {
+        Self {
+            base_url: "https://generativelanguage.googleapis.com".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for GeminiProvider {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
+        self.base_url.clone()
+    }
+
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    fn requires_body(&self, _path: &str) -> bool {
+        true
+    }
+
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        ctx.insert(GeminiRequest::capture(body));
+        Ok(())
+    }
+
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        if !ctx.path.contains("/chat/completions") {
+            return ctx.path.clone();
+        }
+        format!("/v1beta/models/{}", GeminiRequest::get(ctx).model_method())
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
+        debug!("Processing Gemini request headers");
+        let mut headers = HeaderMap::new();
+
+        // Add content type
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        // Process authentication
+        let api_key = original_headers
+            .get("x-goog-api-key")
+            .and_then(|h| h.to_str().ok())
+            .or_else(|| {
+                original_headers
+                    .get(http::header::AUTHORIZATION)
+                    .and_then(|h| h.to_str().ok())
+                    .map(|auth| auth.trim_start_matches("Bearer ").trim())
+            })
+            .filter(|key| !key.is_empty());
+
+        match api_key {
+            Some(api_key) => {
+                debug!("Converting Bearer token to x-goog-api-key format");
+                headers.insert(
+                    http::header::HeaderName::from_static("x-goog-api-key"),
+                    http::header::HeaderValue::from_str(api_key).map_err(|_| {
+                        error!("Failed to process Gemini authorization header");
+                        AppError::InvalidHeader
+                    })?,
+                );
+            }
+            None => {
+                error!("No authorization header found for Gemini request");
+                return Err(AppError::MissingApiKey);
+            }
+        }
+
+        Ok(headers)
+    }
+
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        GeminiRequest::get(ctx).prepare_request_body(body)
+    }
+
+    async fn process_response(
+        &self,
+        ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        GeminiRequest::get(ctx)
+            .process_response("gemini", response)
+            .await
+    }
+}
+
+/// Request state shared by the Gemini and Vertex AI providers
+#[derive(Debug, Clone, Default)]
+pub(super) struct GeminiRequest {
+    pub(super) model: String,
+    stream: bool,
+    is_chat: bool,
+}
+
+impl GeminiRequest {
+    /// Capture the model and streaming mode of an OpenAI chat request
+    pub(super) fn capture(body: &Bytes) -> Self {
+        let Ok(request_body) = serde_json::from_slice::<Value>(body) else {
+            return Self::default();
+        };
+
+        let model = request_body["model"]
+            .as_str()
+            .unwrap_or_default()
+            .trim_start_matches("models/")
+            .to_string();
+        debug!("Captured Gemini request for model: {}", model);
+        Self {
+            model,
+            stream: request_body["stream"].as_bool().unwrap_or(false),
+            is_chat: request_body.get("messages").is_some(),
+        }
+    }
+
+    /// The state stored by `before_request`
+    pub(super) fn get(ctx: &RequestContext) -> Self {
+        ctx.get::<Self>().cloned().unwrap_or_default()
+    }
+
+    /// Build the `{model}:{method}` path segment for the current request
+    pub(super) fn model_method(&self) -> String {
+        // The model comes from the caller, encoded so it cannot add path segments or a query
+        let model = utf8_percent_encode(&self.model, PATH_SEGMENT);
+        if self.stream {
+            format!("{}:streamGenerateContent?alt=sse", model)
+        } else {
+            format!("{}:generateContent", model)
+        }
+    }
+
+    pub(super) fn prepare_request_body(&self, body: Bytes) -> Result<Bytes, AppError> {
+        if !self.is_chat {
+            return Ok(body);
+        }
+        let request_body: Value = serde_json::from_slice(&body)?;
+        let transformed = to_gemini_request(&request_body)?;
+        debug!("Transformed Gemini body: {:#?}", transformed);
+        Ok(Bytes::from(serde_json::to_vec(&transformed)?))
+    }
+
+    pub(super) async fn process_response(
+        &self,
+        provider: &'static str,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        if !self.is_chat || !response.status().is_success() {
+            return Ok(response);
+        }
+
+        let model = self.model.clone();
+        let (mut parts, body) = response.into_parts();
+        parts.headers.remove(http::header::CONTENT_LENGTH);
+
+        if self.stream {
+            debug!("Processing Gemini event stream response");
+            let body = transform_lines(body, GeminiStreamTransform::new(provider, model));
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        let bytes = to_bytes(body, usize::MAX).await?;
+        let gemini_response: Value = serde_json::from_slice(&bytes)?;
+        let transformed = from_gemini_response(&gemini_response, &model);
+
+        parts.headers.insert(
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        Ok(Response::from_parts(
+            parts,
+            Body::from(serde_json::to_vec(&transformed)?),
+        ))
+    }
+}
+
+/// Translate an OpenAI chat completion request into a Gemini `generateContent` request
+pub(super) fn to_gemini_request(body: &Value) -> Result<Value, AppError> {
+    let messages = body
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| {
+            error!("Invalid request format: messages array not found");
+            AppError::InvalidRequestFormat
+        })?;
+
+    // Tool results reference calls by id, Gemini references them by function name
+    let tool_names: HashMap<&str, &str> = messages
+        .iter()
+        .filter_map(|msg| msg.get("tool_calls").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|call| {
+            Some((
+                call.get("id")?.as_str()?,
+                call.pointer("/function/name")?.as_str()?,
+            ))
+        })
+        .collect();
+
+    let mut system_parts = Vec::new();
+    let mut contents: Vec<Value> = Vec::new();
+
+    for msg in messages {
+        let role = msg["role"].as_str().unwrap_or("user");
+        let (gemini_role, parts) = match role {
+            "system" | "developer" => {
+                system_parts.extend(content_parts(&msg["content"]));
+                continue;
+            }
+            "assistant" => {
+                let mut parts = content_parts(&msg["content"]);
+                for call in msg["tool_calls"].as_array().into_iter().flatten() {
+                    let arguments = call
+                        .pointer("/function/arguments")
+                        .and_then(Value::as_str)
+                        .unwrap_or("{}");
+                    parts.push(json!({
+                        "functionCall": {
+                            "name": call.pointer("/function/name").cloned().unwrap_or(Value::Null),
+                            "args": serde_json::from_str::<Value>(arguments).unwrap_or_else(|_| json!({})),
+                        }
+                    }));
+                }
+                ("model", parts)
+            }
+            "tool" | "function" => {
+                let name = msg["name"].as_str().unwrap_or_else(|| {
+                    msg["tool_call_id"]
+                        .as_str()
+                        .and_then(|id| tool_names.get(id).copied())
+                        .unwrap_or_default()
+                });
+                let content = text_content(&msg["content"]);
+                let response = match serde_json::from_str::<Value>(&content) {
+                    Ok(Value::Object(object)) => Value::Object(object),
+                    _ => json!({ "content": content }),
+                };
+                (
+                    "user",
+                    vec![json!({ "functionResponse": { "name": name, "response": response } })],
+                )
+            }
+            _ => ("user", content_parts(&msg["content"])),
+        };
+
+        if parts.is_empty() {
+            continue;
+        }
+
+        // Gemini expects alternating turns, so merge consecutive messages of the same role
+        match contents.last_mut() {
+            Some(last) if last["role"] == gemini_role => {
+                if let Some(existing) = last["parts"].as_array_mut() {
+                    existing.extend(parts);
+                }
+            }
+            _ => contents.push(json!({ "role": gemini_role, "parts": parts })),
+        }
+    }
+
+    let mut request = json!({ "contents": contents });
+
+    if !system_parts.is_empty() {
+        request["systemInstruction"] = json!({ "parts": system_parts });
+    }
+
+    if let Some(tools) = body.get("tools").and_then(Value::as_array) {
+        let declarations = tools
+            .iter()
+            .filter_map(|tool| tool.get("function"))
+            .map(|function| {
+                let mut declaration = json!({
+                    "name": function["name"],
+                    "description": function.get("description").cloned().unwrap_or(json!("")),
+                });
+                if let Some(parameters) = function.get("parameters") {
+                    declaration["parameters"] = clean_schema(parameters);
+                }
+                declaration
+            })
+            .collect::<Vec<_>>();
+        if !declarations.is_empty() {
+            request["tools"] = json!([{ "functionDeclarations": declarations }]);
+        }
+    }
+
+    if let Some(tool_choice) = body.get("tool_choice") {
+        let config = match tool_choice {
+            Value::String(choice) => match choice.as_str() {
+                "none" => json!({ "mode": "NONE" }),
+                "required" => json!({ "mode": "ANY" }),
+                _ => json!({ "mode": "AUTO" }),
+            },
+            choice => json!({
+                "mode": "ANY",
+                "allowedFunctionNames": [choice.pointer("/function/name").cloned().unwrap_or(Value::Null)],
+            }),
+        };
+        request["toolConfig"] = json!({ "functionCallingConfig": config });
+    }
+
+    let generation_config = generation_config(body);
+    if !generation_config.is_empty() {
+        request["generationConfig"] = Value::Object(generation_config);
+    }
+
+    Ok(request)
+}
+
+fn generation_config(body: &Value) -> Map<String, Value> {
+    let mut config = Map::new();
+
+    let mappings = [
+        ("temperature", "temperature"),
+        ("top_p", "topP"),
+        ("top_k", "topK"),
+        ("n", "candidateCount"),
+        ("presence_penalty", "presencePenalty"),
+        ("frequency_penalty", "frequencyPenalty"),
+        ("seed", "seed"),
+        ("max_tokens", "maxOutputTokens"),
+        ("max_completion_tokens", "maxOutputTokens"),
+    ];
+    for (openai_key, gemini_key) in mappings {
+        if let Some(value) = body.get(openai_key).filter(|v| !v.is_null()) {
+            config.insert(gemini_key.to_string(), value.clone());
+        }
+    }
+
+    match body.get("stop") {
+        Some(Value::String(stop)) => {
+            config.insert("stopSequences".to_string(), json!([stop]));
+        }
+        Some(Value::Array(stop)) => {
+            config.insert("stopSequences".to_string(), json!(stop));
+        }
+        _ => {}
+    }
+
+    match body
+        .pointer("/response_format/type")
+        .and_then(Value::as_str)
+    {
+        Some("json_object") => {
+            config.insert("responseMimeType".to_string(), json!("application/json"));
+        }
+        Some("json_schema") => {
+            config.insert("responseMimeType".to_string(), json!("application/json"));
+            if let Some(schema) = body.pointer("/response_format/json_schema/schema") {
+                config.insert("responseSchema".to_string(), clean_schema(schema));
+            }
+        }
+        _ => {}
+    }
+
+    config
+}
+
+/// Convert OpenAI message content (a string or an array of parts) into Gemini parts
+fn content_parts(content: &Value) -> Vec<Value> {
+    match content {
+        Value::String(text) if !text.is_empty() => vec![json!({ "text": text })],
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part["type"].as_str() {
+                Some("text") => Some(json!({ "text": part["text"] })),
+                Some("image_url") => {
+                    let url = part
+                        .pointer("/image_url/url")
+                        .or_else(|| part.get("image_url"))
+                        .and_then(Value::as_str)?;
+                    Some(image_part(url))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn text_content(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join(""),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Inline base64 data URLs, reference everything else by URI
+fn image_part(url: &str) -> Value {
+    if let Some((meta, data)) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+    {
+        let mime_type = meta.trim_end_matches(";base64");
+        return json!({ "inlineData": { "mimeType": mime_type, "data": data } });
+    }
+
+    let extension = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('.').next())
+        .unwrap_or_default()
+        .to_lowercase();
+    let mime_type = match extension.as_str() {
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "heic" => "image/heic",
+        "pdf" => "application/pdf",
+        _ => "image/jpeg",
+    };
+    json!({ "fileData": { "mimeType": mime_type, "fileUri": url } })
+}
+
+/// Remove JSON schema keywords that Gemini's OpenAPI subset rejects
+fn clean_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .filter(|(key, _)| !matches!(key.as_str(), "additionalProperties" | "$schema"))
+                .map(|(key, value)| (key.clone(), clean_schema(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(clean_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+fn finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn usage(metadata: &Value) -> Value {
+    let prompt_tokens = metadata["promptTokenCount"].as_u64().unwrap_or(0);
+    let completion_tokens = metadata["candidatesTokenCount"].as_u64().unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": metadata["totalTokenCount"]
+            .as_u64()
+            .unwrap_or(prompt_tokens + completion_tokens),
+    })
+}
+
+/// Split a Gemini candidate into its text and OpenAI-formatted tool calls
+fn candidate_content(candidate: &Value, call_offset: usize) -> (String, Vec<Value>) {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+
+    for part in candidate
+        .pointer("/content/parts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(part_text) = part["text"].as_str() {
+            text.push_str(part_text);
+        } else if let Some(call) = part.get("functionCall") {
+            let index = call_offset + tool_calls.len();
+            tool_calls.push(json!({
+                "index": index,
+                "id": format!("call_{}", index),
+                "type": "function",
+                "function": {
+                    "name": call["name"],
+                    "arguments": call.get("args").unwrap_or(&json!({})).to_string(),
+                }
+            }));
+        }
+    }
+
+    (text, tool_calls)
+}
+
+/// Translate a Gemini `generateContent` response into an OpenAI chat completion
+pub(super) fn from_gemini_response(response: &Value, model: &str) -> Value {
+    let choices = response["candidates"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(position, candidate)| {
+            let (text, tool_calls) = candidate_content(candidate, 0);
+            let mut message = json!({
+                "role": "assistant",
+                "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+            });
+            let reason = if tool_calls.is_empty() {
+                finish_reason(candidate["finishReason"].as_str().unwrap_or("STOP"))
+            } else {
+                message["tool_calls"] = json!(tool_calls
+                    .into_iter()
+                    .map(|mut call| {
+                        call.as_object_mut().map(|c| c.remove("index"));
+                        call
+                    })
+                    .collect::<Vec<_>>());
+                "tool_calls"
+            };
+            json!({
+                "index": candidate["index"].as_u64().unwrap_or(position as u64),
+                "message": message,
+                "finish_reason": reason,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "id": response["responseId"].as_str().map(|id| format!("chatcmpl-{}", id)).unwrap_or_else(|| "chatcmpl-gemini".to_string()),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": response["modelVersion"].as_str().unwrap_or(model),
+        "choices": choices,
+        "usage": usage(&response["usageMetadata"]),
+    })
+}
+
+/// Translates Gemini `alt=sse` events into OpenAI chat completion chunks
+struct GeminiStreamTransform {
+    provider: &'static str,
+    model: String,
+    created: i64,
+    sent_role: bool,
+    tool_calls: usize,
+}
+
+impl GeminiStreamTransform {
+    fn new(provider: &'static str, model: String) -> Self {
+        Self {
+            provider,
+            model,
+            created: chrono::Utc::now().timestamp(),
+            sent_role: false,
+            tool_calls: 0,
+        }
+    }
+
+    fn chunk(&self, choices: Vec<Value>) -> Value {
+        json!({
+            "id": "chatcmpl-gemini",
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        })
+    }
+}
+
+impl LineTransform for GeminiStreamTransform {
+    fn line(&mut self, line: &str) -> Vec<String> {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return vec![];
+        };
+        let event: Value = match serde_json::from_str(data) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Failed to parse Gemini stream event: {}", e);
+                return vec![];
+            }
+        };
+
+        if event.get("error").is_some() {
+            error!("{} stream error: {}", self.provider, data);
+            return vec![sse_event(&provider_error(
+                self.provider,
+                StatusCode::BAD_GATEWAY,
+                data.as_bytes(),
+                None,
+            ))];
+        }
+
+        let mut events = Vec::new();
+        for (position, candidate) in event["candidates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let index = candidate["index"].as_u64().unwrap_or(position as u64);
+            let (text, tool_calls) = candidate_content(candidate, self.tool_calls);
+            self.tool_calls += tool_calls.len();
+
+            let mut delta = json!({});
+            if !self.sent_role {
+                delta["role"] = json!("assistant");
+                self.sent_role = true;
+            }
+            if !text.is_empty() {
+                delta["content"] = json!(text);
+            }
+            if !tool_calls.is_empty() {
+                delta["tool_calls"] = json!(tool_calls);
+            }
+            if delta.as_object().is_some_and(|d| !d.is_empty()) {
+                events.push(sse_event(&self.chunk(vec![
+                    json!({ "index": index, "delta": delta, "finish_reason": null }),
+                ])));
+            }
+
+            if let Some(reason) = candidate["finishReason"].as_str() {
+                let reason = if self.tool_calls > 0 {
+                    "tool_calls"
+                } else {
+                    finish_reason(reason)
+                };
+                let mut chunk = self.chunk(vec![
+                    json!({ "index": index, "delta": {}, "finish_reason": reason }),
+                ]);
+                if let Some(metadata) = event.get("usageMetadata") {
+                    chunk["usage"] = usage(metadata);
+                }
+                events.push(sse_event(&chunk));
+            }
+        }
+
+        events
+    }
+
+    fn finish(&mut self) -> Vec<String> {
+        vec![SSE_DONE.to_string()]
+    }
+}
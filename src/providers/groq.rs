@@ -1,7 +1,8 @@
+use super::audio::{self, Transcription};
 use super::Provider;
-use crate::error::AppError;
+use crate::{context::RequestContext, error::AppError};
 use async_trait::async_trait;
-use axum::http::HeaderMap;
+use axum::{body::Bytes, http::HeaderMap};
 use tracing::{debug, error};
 
 pub struct GroqProvider {
@@ -14,11 +15,19 @@ impl GroqProvider {
             base_url: "https://api.groq.com/openai".to_string(),
         }
     }
+
+    /// Groq serves Whisper under its own model names
+    fn whisper_model(model: &str) -> &str {
+        match model {
+            "whisper-1" => "whisper-large-v3",
+            other => other,
+        }
+    }
 }
 
 #[async_trait]
 impl Provider for GroqProvider {
-    fn base_url(&self) -> String {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
         self.base_url.clone()
     }
 
@@ -26,7 +35,19 @@ impl Provider for GroqProvider {
         "groq"
     }
 
-    fn process_headers(&self, original_headers: &HeaderMap) -> Result<HeaderMap, AppError> {
+    fn requires_body(&self, path: &str) -> bool {
+        audio::is_transcription_path(path)
+    }
+
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        if let Some(transcription) = Transcription::capture(&ctx.path, &ctx.headers, body) {
+            ctx.insert(transcription);
+        }
+        Ok(())
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
         debug!("Processing Groq request headers");
         let mut headers = HeaderMap::new();
 
@@ -56,4 +77,17 @@ impl Provider for GroqProvider {
 
         Ok(headers)
     }
+
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        Ok(match ctx.get::<Transcription>() {
+            Some(transcription) => {
+                transcription.with_model(body, Self::whisper_model(&transcription.model))
+            }
+            None => body,
+        })
+    }
 }
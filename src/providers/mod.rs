@@ -1,50 +1,81 @@
-use crate::error::AppError;
+use crate::{
+    config::AppConfig, context::RequestContext, error::AppError, proxy::client::UpstreamClient,
+};
 use async_trait::async_trait;
 use axum::{
     body::{Body, Bytes},
     http::{HeaderMap, Response},
 };
-use tracing::error;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, error, warn};
+
+/// Characters encoded in caller-supplied values placed into a URL path segment or query
+pub(crate) const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Whether a passthrough path stays where the gateway put it: no `.` or `..` segments,
+/// also not percent-encoded ones, which URL normalization would resolve
+pub(crate) fn is_plain_path(path: &str) -> bool {
+    path.split('/').all(|segment| {
+        let decoded = percent_encoding::percent_decode_str(segment).decode_utf8_lossy();
+        decoded != "." && decoded != ".." && !decoded.contains(['/', '\\', '?', '#'])
+    })
+}
 
+/// A provider is built once at startup and shared by all requests.
+/// Request-specific state belongs in the `RequestContext`, never on the provider.
 #[async_trait]
 pub trait Provider: Send + Sync {
     /// Get the base URL for the provider's API
-    fn base_url(&self) -> String;
+    fn base_url(&self, ctx: &RequestContext) -> String;
 
     /// Get the provider's name for logging and identification
     fn name(&self) -> &str;
 
     /// Transform the request path if needed
-    fn transform_path(&self, path: &str) -> String {
-        path.to_string()
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        ctx.path.clone()
     }
 
     /// Process and validate headers before sending request
-    fn process_headers(&self, headers: &HeaderMap) -> Result<HeaderMap, AppError>;
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError>;
 
     /// Transform request body if needed
-    async fn prepare_request_body(&self, body: Bytes) -> Result<Bytes, AppError> {
+    async fn prepare_request_body(
+        &self,
+        _ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
         Ok(body)
     }
 
     /// Process response before returning to client
-    async fn process_response(&self, response: Response<Body>) -> Result<Response<Body>, AppError> {
+    async fn process_response(
+        &self,
+        _ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
         Ok(response)
     }
 
-    /// Sign the final request if needed
-    async fn sign_request(
-        &self,
-        method: &str,
-        url: &str,
-        headers: &HeaderMap,
-        body: &[u8],
-    ) -> Result<HeaderMap, AppError> {
-        Ok(headers.clone())
+    /// Whether the provider reads or rewrites the request body for this path.
+    /// Bodies are streamed to the provider unbuffered when this is false.
+    fn requires_body(&self, _path: &str) -> bool {
+        false
     }
 
-    /// Process any operations needed before the request is sent
-    async fn before_request(&self, headers: &HeaderMap, body: &Bytes) -> Result<(), AppError> {
+    /// Resolve per-request state into the context before the request is sent.
+    /// `body` is empty when the request body is streamed.
+    async fn before_request(
+        &self,
+        _ctx: &mut RequestContext,
+        _body: &Bytes,
+    ) -> Result<(), AppError> {
         Ok(())
     }
 
@@ -54,44 +85,151 @@ pub trait Provider: Send + Sync {
     }
 
     /// Get AWS signing credentials if available
-    fn get_signing_credentials(&self, _headers: &HeaderMap) -> Option<(String, String, String)> {
+    fn get_signing_credentials(&self, _ctx: &RequestContext) -> Option<(String, String, String)> {
         None
     }
-
-    /// Get the signing host for the provider
-    fn get_signing_host(&self) -> String {
-        self.base_url()
-            .replace("https://", "")
-            .replace("http://", "")
-    }
 }
 
 mod anthropic;
+mod audio;
+mod azure;
 mod bedrock;
+mod cohere;
+mod event_stream;
 mod fireworks;
+mod gemini;
 mod groq;
+mod jina;
+mod multipart;
+mod ollama;
 mod openai;
+mod openai_compatible;
+pub mod rerank;
+mod stream;
 mod together;
+mod vertex;
+mod voyage;
 
 pub use anthropic::AnthropicProvider;
+pub use azure::AzureProvider;
 pub use bedrock::BedrockProvider;
+pub use cohere::CohereProvider;
 pub use fireworks::FireworksProvider;
+pub use gemini::GeminiProvider;
 pub use groq::GroqProvider;
+pub use jina::JinaProvider;
+pub use ollama::OllamaProvider;
 pub use openai::OpenAIProvider;
+pub use openai_compatible::OpenAICompatibleProvider;
 pub use together::TogetherProvider;
+pub use vertex::VertexProvider;
+pub use voyage::VoyageProvider;
 
-/// Factory function to create provider instances
-pub fn create_provider(provider_name: &str) -> Result<Box<dyn Provider>, AppError> {
-    match provider_name.to_lowercase().as_str() {
-        "openai" => Ok(Box::new(OpenAIProvider::new())),
-        "anthropic" => Ok(Box::new(AnthropicProvider::new())),
-        "groq" => Ok(Box::new(GroqProvider::new())),
-        "fireworks" => Ok(Box::new(FireworksProvider::new())),
-        "together" => Ok(Box::new(TogetherProvider::new())),
-        "bedrock" => Ok(Box::new(BedrockProvider::new())),
-        unknown => {
-            error!("Attempted to use unsupported provider: {}", unknown);
-            Err(AppError::UnsupportedProvider)
+/// Model named in a JSON or `multipart/form-data` request body
+pub fn request_model(headers: &HeaderMap, body: &Bytes) -> Option<String> {
+    match multipart::boundary(headers) {
+        Some(boundary) => multipart::field(body, &boundary, "model"),
+        None => serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|b| b["model"].as_str().map(String::from)),
+    }
+}
+
+/// Providers built once at startup from configuration, keyed by `x-provider` name
+pub struct ProviderRegistry {
+    providers: HashMap<String, Arc<dyn Provider>>,
+    /// Clients for providers with their own `[clients.<name>]` settings
+    clients: HashMap<String, Arc<UpstreamClient>>,
+    shared_client: Arc<UpstreamClient>,
+}
+
+impl ProviderRegistry {
+    pub fn new(config: &AppConfig) -> Result<Self, String> {
+        let clients: HashMap<String, Arc<UpstreamClient>> = config
+            .clients
+            .keys()
+            .map(|name| {
+                let settings = config.client_config(name).unwrap_or_default();
+                let client = UpstreamClient::new(config, &settings)
+                    .map_err(|e| format!("Invalid client settings for provider {}: {}", name, e))?;
+                Ok((name.clone(), Arc::new(client)))
+            })
+            .collect::<Result<_, String>>()?;
+        let shared_client = Arc::new(UpstreamClient::shared(config));
+        let vertex_client = clients.get("vertex").unwrap_or(&shared_client).clone();
+
+        let mut providers: HashMap<String, Arc<dyn Provider>> = HashMap::from([
+            (
+                "openai".to_string(),
+                Arc::new(OpenAIProvider::new()) as Arc<dyn Provider>,
+            ),
+            ("anthropic".to_string(), Arc::new(AnthropicProvider::new())),
+            ("groq".to_string(), Arc::new(GroqProvider::new())),
+            ("fireworks".to_string(), Arc::new(FireworksProvider::new())),
+            ("together".to_string(), Arc::new(TogetherProvider::new())),
+            ("bedrock".to_string(), Arc::new(BedrockProvider::new())),
+            ("azure".to_string(), Arc::new(AzureProvider::new(config))),
+            ("gemini".to_string(), Arc::new(GeminiProvider::new())),
+            (
+                "vertex".to_string(),
+                Arc::new(VertexProvider::new(config, vertex_client)?),
+            ),
+            ("ollama".to_string(), Arc::new(OllamaProvider::new(config))),
+            ("cohere".to_string(), Arc::new(CohereProvider::new())),
+            ("jina".to_string(), Arc::new(JinaProvider::new())),
+            ("voyage".to_string(), Arc::new(VoyageProvider::new())),
+        ]);
+
+        // Providers defined in the config file take precedence over the built-in ones
+        for provider_config in &config.providers {
+            providers.insert(
+                provider_config.name.clone(),
+                Arc::new(OpenAICompatibleProvider::new(provider_config.clone())),
+            );
         }
+
+        for name in clients.keys() {
+            if !providers.contains_key(name) {
+                warn!("Client settings configured for unknown provider: {}", name);
+            }
+        }
+
+        debug!("Provider registry built with {} providers", providers.len());
+        Ok(Self {
+            providers,
+            clients,
+            shared_client,
+        })
+    }
+
+    /// Names of all registered providers, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.providers.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// HTTP client used for a provider's upstream requests
+    pub fn client(&self, provider_name: &str) -> Arc<UpstreamClient> {
+        self.clients
+            .get(&provider_name.to_lowercase())
+            .unwrap_or(&self.shared_client)
+            .clone()
+    }
+
+    /// HTTP client for requests that belong to no provider, with the `UPSTREAM_*` settings
+    pub fn shared_client(&self) -> Arc<UpstreamClient> {
+        self.shared_client.clone()
+    }
+
+    /// Look up a provider by its `x-provider` name
+    pub fn get(&self, provider_name: &str) -> Result<Arc<dyn Provider>, AppError> {
+        self.providers
+            .get(&provider_name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| {
+                error!("Attempted to use unsupported provider: {}", provider_name);
+                AppError::UnsupportedProvider
+            })
     }
 }
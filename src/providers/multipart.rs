@@ -0,0 +1,103 @@
+use axum::body::Bytes;
+use axum::http::{header::CONTENT_TYPE, HeaderMap};
+use std::ops::Range;
+
+/// Boundary of a `multipart/form-data` request, if that is the content type
+pub(super) fn boundary(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    if !content_type
+        .to_ascii_lowercase()
+        .starts_with("multipart/form-data")
+    {
+        return None;
+    }
+    content_type.split(';').find_map(|param| {
+        let (key, value) = param.trim().split_once('=')?;
+        key.eq_ignore_ascii_case("boundary")
+            .then(|| value.trim_matches('"').to_string())
+    })
+}
+
+/// Text value of the form field `name`
+pub(super) fn field(body: &[u8], boundary: &str, name: &str) -> Option<String> {
+    let part = parts(body, boundary)
+        .into_iter()
+        .find(|part| is_field(&body[part.headers.clone()], name))?;
+    String::from_utf8(body[part.content].to_vec()).ok()
+}
+
+/// Replace the value of the form field `name`, leaving every other part untouched
+pub(super) fn replace_field(body: &Bytes, boundary: &str, name: &str, value: &str) -> Bytes {
+    let Some(part) = parts(body, boundary)
+        .into_iter()
+        .find(|part| is_field(&body[part.headers.clone()], name))
+    else {
+        return body.clone();
+    };
+
+    let mut replaced = Vec::with_capacity(body.len() + value.len());
+    replaced.extend_from_slice(&body[..part.content.start]);
+    replaced.extend_from_slice(value.as_bytes());
+    replaced.extend_from_slice(&body[part.content.end..]);
+    Bytes::from(replaced)
+}
+
+/// Byte ranges of a part's headers and content
+struct Part {
+    headers: Range<usize>,
+    content: Range<usize>,
+}
+
+fn parts(body: &[u8], boundary: &str) -> Vec<Part> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+
+    // Delimiters start the body or follow a CRLF and end the line, anything else is part content
+    let positions: Vec<usize> = find_all(body, delimiter)
+        .into_iter()
+        .filter(|&pos| pos == 0 || body[..pos].ends_with(b"\r\n"))
+        .filter(|&pos| {
+            let rest = &body[pos + delimiter.len()..];
+            rest.starts_with(b"\r\n") || rest.starts_with(b"--")
+        })
+        .collect();
+
+    positions
+        .windows(2)
+        .filter_map(|window| {
+            let start = window[0] + delimiter.len() + 2;
+            let end = window[1].checked_sub(2)?;
+            let section = body.get(start..end)?;
+            let split = find(section, b"\r\n\r\n")?;
+            Some(Part {
+                headers: start..start + split,
+                content: start + split + 4..end,
+            })
+        })
+        .collect()
+}
+
+/// Whether part headers describe the form field `name` (and not a file named `name`)
+fn is_field(headers: &[u8], name: &str) -> bool {
+    let headers = String::from_utf8_lossy(headers).to_ascii_lowercase();
+    let needle = format!("name=\"{}\"", name.to_ascii_lowercase());
+    headers.match_indices(&needle).any(|(pos, _)| {
+        matches!(
+            headers.as_bytes().get(pos.wrapping_sub(1)),
+            Some(b' ' | b';')
+        )
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn find_all(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(|(_, w)| *w == needle)
+        .map(|(i, _)| i)
+        .collect()
+}
@@ -0,0 +1,590 @@
+use super::stream::{sse_event, transform_lines, LineTransform, SSE_DONE};
+use super::{is_plain_path, Provider};
+use crate::{
+    config::AppConfig,
+    context::RequestContext,
+    error::{provider_error, AppError},
+};
+use async_trait::async_trait;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
+};
+use serde_json::{json, Map, Value};
+use tracing::{debug, error, warn};
+
+/// Native endpoints reachable through `/v1/api/*`. Others, such as `pull`, `delete` or
+/// `create`, manage the models on the server and need `OLLAMA_ADMIN_API`.
+const NATIVE_ENDPOINTS: [&str; 5] = ["chat", "generate", "embed", "tags", "show"];
+
+/// The Ollama API a gateway request is translated to
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum OllamaEndpoint {
+    /// `/v1/chat/completions` translated to `/api/chat`
+    Chat,
+    /// `/v1/embeddings` translated to `/api/embed`
+    Embeddings,
+    /// `/v1/models` translated to `/api/tags`
+    Models,
+    /// `/v1/api/*` forwarded verbatim to the native `/api/*` endpoints (chat, show, tags, ...)
+    Native,
+    /// Anything else is forwarded to Ollama's own OpenAI-compatible endpoints
+    #[default]
+    Passthrough,
+}
+
+/// OllamaProvider talks to Ollama's native API
+pub struct OllamaProvider {
+    base_url: String,
+    /// Whether model management endpoints are reachable through the native passthrough
+    admin_api: bool,
+}
+
+/// Endpoint and streaming mode captured for an Ollama request
+#[derive(Clone, Copy, Debug, Default)]
+struct OllamaRequest {
+    endpoint: OllamaEndpoint,
+    stream: bool,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            base_url: config.ollama_base_url.clone(),
+            admin_api: config.ollama_admin_api,
+        }
+    }
+
+    fn request(ctx: &RequestContext) -> OllamaRequest {
+        ctx.get::<OllamaRequest>().copied().unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Provider for OllamaProvider {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
+        self.base_url.clone()
+    }
+
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn requires_body(&self, _path: &str) -> bool {
+        true
+    }
+
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        let path = ctx.path.as_str();
+        let endpoint = if path.ends_with("/chat/completions") {
+            OllamaEndpoint::Chat
+        } else if path.ends_with("/embeddings") {
+            OllamaEndpoint::Embeddings
+        } else if path == "/v1/models" {
+            OllamaEndpoint::Models
+        } else if path.starts_with("/v1/api/") {
+            OllamaEndpoint::Native
+        } else {
+            OllamaEndpoint::Passthrough
+        };
+        debug!("Using Ollama endpoint: {:?}", endpoint);
+
+        // Paths are forwarded as they are, so they must not climb out to other endpoints
+        if !is_plain_path(path) {
+            error!("Refusing Ollama path with dot segments: {}", path);
+            return Err(AppError::RequestError("Invalid request path".to_string()));
+        }
+        if endpoint == OllamaEndpoint::Native
+            && !self.admin_api
+            && !NATIVE_ENDPOINTS.contains(&path.trim_start_matches("/v1/api/"))
+        {
+            warn!(
+                "Refusing Ollama endpoint {}, set OLLAMA_ADMIN_API=true to allow it",
+                path
+            );
+            return Err(AppError::NotFound(format!("Ollama endpoint {}", path)));
+        }
+
+        let stream = serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|b| b["stream"].as_bool())
+            .unwrap_or(false);
+        ctx.insert(OllamaRequest { endpoint, stream });
+        Ok(())
+    }
+
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        let path = ctx.path.as_str();
+        match Self::request(ctx).endpoint {
+            OllamaEndpoint::Chat => "/api/chat".to_string(),
+            OllamaEndpoint::Embeddings => "/api/embed".to_string(),
+            OllamaEndpoint::Models => "/api/tags".to_string(),
+            OllamaEndpoint::Native => path.trim_start_matches("/v1").to_string(),
+            OllamaEndpoint::Passthrough => path.to_string(),
+        }
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
+        debug!("Processing Ollama request headers");
+        let mut headers = HeaderMap::new();
+
+        // Add content type
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        // Ollama itself is unauthenticated, but forward credentials for auth proxies in front of it
+        if let Some(auth) = original_headers.get(http::header::AUTHORIZATION) {
+            debug!("Forwarding authorization header to Ollama");
+            headers.insert(http::header::AUTHORIZATION, auth.clone());
+        }
+
+        Ok(headers)
+    }
+
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        let transformed = match Self::request(ctx).endpoint {
+            OllamaEndpoint::Chat => to_ollama_chat(&serde_json::from_slice(&body)?)?,
+            OllamaEndpoint::Embeddings => to_ollama_embed(&serde_json::from_slice(&body)?),
+            _ => return Ok(body),
+        };
+        debug!("Transformed Ollama body: {:#?}", transformed);
+        Ok(Bytes::from(serde_json::to_vec(&transformed)?))
+    }
+
+    async fn process_response(
+        &self,
+        ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        let OllamaRequest { endpoint, stream } = Self::request(ctx);
+        if !response.status().is_success()
+            || matches!(
+                endpoint,
+                OllamaEndpoint::Native | OllamaEndpoint::Passthrough
+            )
+        {
+            return Ok(response);
+        }
+
+        let model = ctx.model.clone().unwrap_or_default();
+        let (mut parts, body) = response.into_parts();
+        parts.headers.remove(http::header::CONTENT_LENGTH);
+
+        if endpoint == OllamaEndpoint::Chat && stream {
+            debug!("Translating Ollama NDJSON stream to SSE");
+            parts.headers.insert(
+                http::header::CONTENT_TYPE,
+                HeaderValue::from_static("text/event-stream"),
+            );
+            let body = transform_lines(body, OllamaStreamTransform::new(model));
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        let bytes = to_bytes(body, usize::MAX).await?;
+        let ollama_response: Value = serde_json::from_slice(&bytes)?;
+        let transformed = match endpoint {
+            OllamaEndpoint::Chat => from_ollama_chat(&ollama_response, &model),
+            OllamaEndpoint::Embeddings => from_ollama_embed(&ollama_response, &model),
+            _ => from_ollama_tags(&ollama_response),
+        };
+
+        parts.headers.insert(
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        Ok(Response::from_parts(
+            parts,
+            Body::from(serde_json::to_vec(&transformed)?),
+        ))
+    }
+}
+
+/// Translate an OpenAI chat completion request into an Ollama `/api/chat` request
+fn to_ollama_chat(body: &Value) -> Result<Value, AppError> {
+    let messages = body
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| {
+            error!("Invalid request format: messages array not found");
+            AppError::InvalidRequestFormat
+        })?;
+
+    let messages = messages
+        .iter()
+        .map(|msg| {
+            let mut message = json!({
+                "role": msg["role"].as_str().unwrap_or("user"),
+                "content": text_content(&msg["content"]),
+            });
+
+            let images = image_content(&msg["content"]);
+            if !images.is_empty() {
+                message["images"] = json!(images);
+            }
+
+            if let Some(tool_calls) = msg["tool_calls"].as_array() {
+                message["tool_calls"] = tool_calls
+                    .iter()
+                    .map(|call| {
+                        let arguments = call
+                            .pointer("/function/arguments")
+                            .and_then(Value::as_str)
+                            .and_then(|args| serde_json::from_str::<Value>(args).ok())
+                            .unwrap_or_else(|| json!({}));
+                        json!({
+                            "function": {
+                                "name": call.pointer("/function/name").cloned().unwrap_or(Value::Null),
+                                "arguments": arguments,
+                            }
+                        })
+                    })
+                    .collect();
+            }
+
+            if let Some(name) = msg["name"].as_str() {
+                message["tool_name"] = json!(name);
+            }
+
+            message
+        })
+        .collect::<Vec<_>>();
+
+    let mut request = json!({
+        "model": body["model"],
+        "messages": messages,
+        "stream": body["stream"].as_bool().unwrap_or(false),
+    });
+
+    if let Some(tools) = body.get("tools") {
+        request["tools"] = tools.clone();
+    }
+    if let Some(keep_alive) = body.get("keep_alive") {
+        request["keep_alive"] = keep_alive.clone();
+    }
+
+    match body
+        .pointer("/response_format/type")
+        .and_then(Value::as_str)
+    {
+        Some("json_object") => request["format"] = json!("json"),
+        Some("json_schema") => {
+            if let Some(schema) = body.pointer("/response_format/json_schema/schema") {
+                request["format"] = schema.clone();
+            }
+        }
+        _ => {}
+    }
+
+    let options = options(body);
+    if !options.is_empty() {
+        request["options"] = Value::Object(options);
+    }
+
+    Ok(request)
+}
+
+/// Map OpenAI sampling parameters and Ollama extensions like `num_ctx` into `options`
+fn options(body: &Value) -> Map<String, Value> {
+    let mut options = Map::new();
+
+    let mappings = [
+        ("temperature", "temperature"),
+        ("top_p", "top_p"),
+        ("top_k", "top_k"),
+        ("seed", "seed"),
+        ("frequency_penalty", "frequency_penalty"),
+        ("presence_penalty", "presence_penalty"),
+        ("max_tokens", "num_predict"),
+        ("max_completion_tokens", "num_predict"),
+        ("num_ctx", "num_ctx"),
+    ];
+    for (openai_key, ollama_key) in mappings {
+        if let Some(value) = body.get(openai_key).filter(|v| !v.is_null()) {
+            options.insert(ollama_key.to_string(), value.clone());
+        }
+    }
+
+    match body.get("stop") {
+        Some(Value::String(stop)) => {
+            options.insert("stop".to_string(), json!([stop]));
+        }
+        Some(Value::Array(stop)) => {
+            options.insert("stop".to_string(), json!(stop));
+        }
+        _ => {}
+    }
+
+    // Explicit Ollama options win over translated ones
+    if let Some(explicit) = body.get("options").and_then(Value::as_object) {
+        options.extend(explicit.clone());
+    }
+
+    options
+}
+
+fn text_content(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join(""),
+        _ => String::new(),
+    }
+}
+
+/// Ollama only accepts base64 images, so only data URLs can be forwarded
+fn image_content(content: &Value) -> Vec<String> {
+    content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| {
+            part.pointer("/image_url/url")
+                .and_then(Value::as_str)?
+                .strip_prefix("data:")?
+                .split_once(',')
+                .map(|(_, data)| data.to_string())
+        })
+        .collect()
+}
+
+fn to_ollama_embed(body: &Value) -> Value {
+    let mut request = json!({
+        "model": body["model"],
+        "input": body["input"],
+    });
+    if let Some(dimensions) = body.get("dimensions") {
+        request["dimensions"] = dimensions.clone();
+    }
+    if let Some(options) = body.get("options") {
+        request["options"] = options.clone();
+    }
+    request
+}
+
+fn finish_reason(done_reason: Option<&str>, has_tool_calls: bool) -> &'static str {
+    if has_tool_calls {
+        return "tool_calls";
+    }
+    match done_reason {
+        Some("length") => "length",
+        _ => "stop",
+    }
+}
+
+fn usage(response: &Value) -> Value {
+    let prompt_tokens = response["prompt_eval_count"].as_u64().unwrap_or(0);
+    let completion_tokens = response["eval_count"].as_u64().unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+fn tool_calls(message: &Value, offset: usize) -> Vec<Value> {
+    message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, call)| {
+            let index = offset + i;
+            json!({
+                "index": index,
+                "id": format!("call_{}", index),
+                "type": "function",
+                "function": {
+                    "name": call.pointer("/function/name").cloned().unwrap_or(Value::Null),
+                    "arguments": call
+                        .pointer("/function/arguments")
+                        .map(Value::to_string)
+                        .unwrap_or_else(|| "{}".to_string()),
+                }
+            })
+        })
+        .collect()
+}
+
+fn from_ollama_chat(response: &Value, model: &str) -> Value {
+    let tool_calls = tool_calls(&response["message"], 0);
+    let mut message = json!({
+        "role": "assistant",
+        "content": response.pointer("/message/content").cloned().unwrap_or(json!("")),
+    });
+    let reason = finish_reason(response["done_reason"].as_str(), !tool_calls.is_empty());
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = json!(tool_calls
+            .into_iter()
+            .map(|mut call| {
+                call.as_object_mut().map(|c| c.remove("index"));
+                call
+            })
+            .collect::<Vec<_>>());
+    }
+
+    json!({
+        "id": "chatcmpl-ollama",
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": response["model"].as_str().unwrap_or(model),
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": reason,
+        }],
+        "usage": usage(response),
+    })
+}
+
+fn from_ollama_embed(response: &Value, model: &str) -> Value {
+    let data = response["embeddings"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, embedding)| {
+            json!({
+                "object": "embedding",
+                "index": index,
+                "embedding": embedding,
+            })
+        })
+        .collect::<Vec<_>>();
+    let prompt_tokens = response["prompt_eval_count"].as_u64().unwrap_or(0);
+
+    json!({
+        "object": "list",
+        "data": data,
+        "model": response["model"].as_str().unwrap_or(model),
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "total_tokens": prompt_tokens,
+        },
+    })
+}
+
+fn from_ollama_tags(response: &Value) -> Value {
+    let data = response["models"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|model| {
+            let created = model["modified_at"]
+                .as_str()
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t.timestamp())
+                .unwrap_or(0);
+            json!({
+                "id": model["name"],
+                "object": "model",
+                "created": created,
+                "owned_by": "ollama",
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "object": "list", "data": data })
+}
+
+/// Translates Ollama NDJSON chat chunks into OpenAI SSE chunks
+struct OllamaStreamTransform {
+    model: String,
+    created: i64,
+    sent_role: bool,
+    tool_calls: usize,
+}
+
+impl OllamaStreamTransform {
+    fn new(model: String) -> Self {
+        Self {
+            model,
+            created: chrono::Utc::now().timestamp(),
+            sent_role: false,
+            tool_calls: 0,
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": "chatcmpl-ollama",
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason,
+            }],
+        })
+    }
+}
+
+impl LineTransform for OllamaStreamTransform {
+    fn line(&mut self, line: &str) -> Vec<String> {
+        if line.trim().is_empty() {
+            return vec![];
+        }
+        let event: Value = match serde_json::from_str(line) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Failed to parse Ollama stream chunk: {}", e);
+                return vec![];
+            }
+        };
+
+        if let Some(message) = event["error"].as_str() {
+            error!("Ollama stream error: {}", message);
+            return vec![sse_event(&provider_error(
+                "ollama",
+                StatusCode::BAD_GATEWAY,
+                line.as_bytes(),
+                None,
+            ))];
+        }
+
+        let mut events = Vec::new();
+        let mut delta = json!({});
+        if !self.sent_role {
+            delta["role"] = json!("assistant");
+            self.sent_role = true;
+        }
+        if let Some(content) = event
+            .pointer("/message/content")
+            .and_then(Value::as_str)
+            .filter(|c| !c.is_empty())
+        {
+            delta["content"] = json!(content);
+        }
+        let tool_calls = tool_calls(&event["message"], self.tool_calls);
+        if !tool_calls.is_empty() {
+            self.tool_calls += tool_calls.len();
+            delta["tool_calls"] = json!(tool_calls);
+        }
+        if delta.as_object().is_some_and(|d| !d.is_empty()) {
+            events.push(sse_event(&self.chunk(delta, None)));
+        }
+
+        if event["done"].as_bool().unwrap_or(false) {
+            let reason = finish_reason(event["done_reason"].as_str(), self.tool_calls > 0);
+            let mut chunk = self.chunk(json!({}), Some(reason));
+            chunk["usage"] = usage(&event);
+            events.push(sse_event(&chunk));
+        }
+
+        events
+    }
+
+    fn finish(&mut self) -> Vec<String> {
+        vec![SSE_DONE.to_string()]
+    }
+}
@@ -0,0 +1,147 @@
+use super::Provider;
+use crate::{config::ProviderConfig, context::RequestContext, error::AppError};
+use async_trait::async_trait;
+use axum::{body::Bytes, http::HeaderMap};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{debug, error};
+
+/// OpenAICompatibleProvider handles any OpenAI-compatible API defined in the config file
+pub struct OpenAICompatibleProvider {
+    config: Arc<ProviderConfig>,
+}
+
+impl OpenAICompatibleProvider {
+    pub fn new(config: Arc<ProviderConfig>) -> Self {
+        Self { config }
+    }
+
+    /// Use the configured key when present, otherwise forward the caller's key
+    fn api_key(&self, original_headers: &HeaderMap) -> Option<String> {
+        self.config.static_api_key().or_else(|| {
+            original_headers
+                .get(http::header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .map(|auth| auth.trim_start_matches("Bearer ").trim().to_string())
+                .filter(|key| !key.is_empty())
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAICompatibleProvider {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
+        self.config.base_url.clone()
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        let path = ctx.path.as_str();
+        let path = self
+            .config
+            .strip_path_prefix
+            .as_deref()
+            .and_then(|prefix| path.strip_prefix(prefix))
+            .unwrap_or(path);
+        format!(
+            "{}{}",
+            self.config.path_prefix.as_deref().unwrap_or_default(),
+            path
+        )
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
+        debug!("Processing {} request headers", self.config.name);
+        let mut headers = HeaderMap::new();
+
+        // Add content type
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        // Add configured static headers
+        for (name, value) in &self.config.headers {
+            headers.insert(
+                http::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                    error!(
+                        "Invalid header name in {} config: {}",
+                        self.config.name, name
+                    );
+                    AppError::InvalidHeader
+                })?,
+                http::header::HeaderValue::from_str(value)?,
+            );
+        }
+
+        // Process authentication
+        match self.api_key(original_headers) {
+            Some(api_key) => {
+                debug!(
+                    "Using {} header for {}",
+                    self.config.auth_header, self.config.name
+                );
+                headers.insert(
+                    http::header::HeaderName::from_bytes(self.config.auth_header.as_bytes())
+                        .map_err(|_| {
+                            error!("Invalid auth header name in {} config", self.config.name);
+                            AppError::InvalidHeader
+                        })?,
+                    http::header::HeaderValue::from_str(
+                        &self.config.auth_format.replace("{key}", &api_key),
+                    )
+                    .map_err(|_| {
+                        error!(
+                            "Failed to process {} authorization header",
+                            self.config.name
+                        );
+                        AppError::InvalidHeader
+                    })?,
+                );
+            }
+            None if self.config.auth_required => {
+                error!(
+                    "No authorization header found for {} request",
+                    self.config.name
+                );
+                return Err(AppError::MissingApiKey);
+            }
+            None => debug!("Sending unauthenticated request to {}", self.config.name),
+        }
+
+        Ok(headers)
+    }
+
+    fn requires_body(&self, _path: &str) -> bool {
+        self.config.strip_model_prefix.is_some()
+    }
+
+    async fn prepare_request_body(
+        &self,
+        _ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        let Some(prefix) = self.config.strip_model_prefix.as_deref() else {
+            return Ok(body);
+        };
+        let Ok(mut request_body) = serde_json::from_slice::<Value>(&body) else {
+            return Ok(body);
+        };
+
+        match request_body["model"]
+            .as_str()
+            .and_then(|m| m.strip_prefix(prefix))
+        {
+            Some(model) => {
+                debug!("Stripped model prefix {} for {}", prefix, self.config.name);
+                request_body["model"] = Value::String(model.to_string());
+                Ok(Bytes::from(serde_json::to_vec(&request_body)?))
+            }
+            None => Ok(body),
+        }
+    }
+}
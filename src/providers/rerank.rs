@@ -0,0 +1,160 @@
+use crate::error::AppError;
+use axum::body::{to_bytes, Body, Bytes};
+use axum::http::{HeaderValue, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, error};
+
+/// Gateway path of the normalized rerank endpoint
+pub const RERANK_PATH: &str = "/v1/rerank";
+
+/// Normalized rerank request accepted on `/v1/rerank`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<RerankDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<usize>,
+    #[serde(default)]
+    pub return_documents: bool,
+}
+
+/// A document to rerank, either plain text or an object with a `text` field
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RerankDocument {
+    Text(String),
+    Object { text: String },
+}
+
+impl RerankDocument {
+    pub fn text(&self) -> &str {
+        match self {
+            RerankDocument::Text(text) => text,
+            RerankDocument::Object { text } => text,
+        }
+    }
+}
+
+/// Normalized rerank response returned from `/v1/rerank`
+#[derive(Debug, Serialize)]
+pub struct RerankResponse {
+    pub object: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub model: String,
+    pub results: Vec<RerankResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RerankResult {
+    pub index: usize,
+    pub relevance_score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<RerankDocument>,
+}
+
+impl RerankRequest {
+    /// Parse and validate a normalized rerank request body
+    pub fn parse(body: &[u8]) -> Result<Self, AppError> {
+        let request: RerankRequest = serde_json::from_slice(body)?;
+        if request.query.trim().is_empty() || request.documents.is_empty() {
+            error!("Invalid rerank request: query and documents are required");
+            return Err(AppError::InvalidRequestFormat);
+        }
+        Ok(request)
+    }
+
+    /// Document texts in request order
+    pub fn texts(&self) -> Vec<&str> {
+        self.documents.iter().map(RerankDocument::text).collect()
+    }
+
+    /// Build a normalized response, filling in documents the backend did not return
+    pub fn response(
+        &self,
+        id: Option<String>,
+        mut results: Vec<RerankResult>,
+        usage: Option<Value>,
+    ) -> RerankResponse {
+        results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+        if let Some(top_n) = self.top_n {
+            results.truncate(top_n);
+        }
+        for result in &mut results {
+            result.document = if self.return_documents {
+                self.documents
+                    .get(result.index)
+                    .map(|d| RerankDocument::Object {
+                        text: d.text().to_string(),
+                    })
+            } else {
+                None
+            };
+        }
+
+        RerankResponse {
+            object: "list",
+            id,
+            model: self.model.clone(),
+            results,
+            usage,
+        }
+    }
+}
+
+/// Parse the normalized request when the gateway path is the rerank endpoint
+pub(super) fn capture(path: &str, body: &Bytes) -> Result<Option<RerankRequest>, AppError> {
+    if path != RERANK_PATH {
+        return Ok(None);
+    }
+    debug!("Capturing normalized rerank request");
+    RerankRequest::parse(body).map(Some)
+}
+
+/// Read a successful backend rerank response and rewrite it with `translate`
+pub(super) async fn process_response<F>(
+    response: Response<Body>,
+    translate: F,
+) -> Result<Response<Body>, AppError>
+where
+    F: FnOnce(&Value) -> RerankResponse,
+{
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = to_bytes(body, usize::MAX).await?;
+    let backend_response: Value = serde_json::from_slice(&bytes)?;
+    let normalized = translate(&backend_response);
+
+    parts.headers.remove(http::header::CONTENT_LENGTH);
+    parts.headers.insert(
+        http::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Ok(Response::from_parts(
+        parts,
+        Body::from(serde_json::to_vec(&normalized)?),
+    ))
+}
+
+/// Collect `{index, <score_field>}` results from a backend result array
+pub(super) fn results(items: Option<&Value>, score_field: &str) -> Vec<RerankResult> {
+    items
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            Some(RerankResult {
+                index: item["index"].as_u64()? as usize,
+                relevance_score: item[score_field].as_f64()?,
+                document: None,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+use axum::body::{Body, Bytes};
+use futures_util::StreamExt;
+
+/// Line-oriented transformation applied to a streaming response body
+pub(super) trait LineTransform: Send + 'static {
+    /// Transform a single complete line (without its trailing newline)
+    fn line(&mut self, line: &str) -> Vec<String>;
+
+    /// Emit any trailing events once the upstream stream has ended
+    fn finish(&mut self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Buffers bytes until complete lines are available, so lines split across
+/// network chunks are never handed to a transform half-finished
+#[derive(Default)]
+struct LineBuffer {
+    buffer: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            lines.push(Self::decode(&line));
+        }
+        lines
+    }
+
+    fn flush(&mut self) -> Option<String> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let line = Self::decode(&self.buffer);
+        self.buffer.clear();
+        Some(line)
+    }
+
+    fn decode(line: &[u8]) -> String {
+        String::from_utf8_lossy(line)
+            .trim_end_matches(['\n', '\r'])
+            .to_string()
+    }
+}
+
+/// Apply a line transform to a streaming body, producing a new streaming body
+pub(super) fn transform_lines<T: LineTransform>(body: Body, transform: T) -> Body {
+    let stream = futures_util::stream::unfold(
+        (
+            body.into_data_stream(),
+            LineBuffer::default(),
+            transform,
+            false,
+        ),
+        |(mut upstream, mut lines, mut transform, done)| async move {
+            if done {
+                return None;
+            }
+
+            let (events, done) = match upstream.next().await {
+                Some(Ok(chunk)) => {
+                    let events = lines
+                        .push(&chunk)
+                        .iter()
+                        .flat_map(|line| transform.line(line))
+                        .collect::<Vec<_>>();
+                    (Ok(events), false)
+                }
+                Some(Err(e)) => (Err(std::io::Error::other(e)), true),
+                None => {
+                    let mut events = lines
+                        .flush()
+                        .map(|line| transform.line(&line))
+                        .unwrap_or_default();
+                    events.extend(transform.finish());
+                    (Ok(events), true)
+                }
+            };
+
+            let item = events.map(|events| Bytes::from(events.concat()));
+            Some((item, (upstream, lines, transform, done)))
+        },
+    );
+
+    Body::from_stream(stream)
+}
+
+/// Format a JSON value as an OpenAI-style SSE data event
+pub(super) fn sse_event(value: &serde_json::Value) -> String {
+    format!("data: {}\n\n", value)
+}
+
+/// The terminal SSE event expected by OpenAI clients
+pub(super) const SSE_DONE: &str = "data: [DONE]\n\n";
@@ -1,5 +1,5 @@
 use super::Provider;
-use crate::error::AppError;
+use crate::{context::RequestContext, error::AppError};
 use async_trait::async_trait;
 use axum::http::HeaderMap;
 use tracing::{debug, error};
@@ -18,7 +18,7 @@ impl TogetherProvider {
 
 #[async_trait]
 impl Provider for TogetherProvider {
-    fn base_url(&self) -> String {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
         self.base_url.clone()
     }
 
@@ -26,7 +26,8 @@ impl Provider for TogetherProvider {
         "together"
     }
 
-    fn process_headers(&self, original_headers: &HeaderMap) -> Result<HeaderMap, AppError> {
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
         debug!("Processing Together request headers");
         let mut headers = HeaderMap::new();
 
@@ -0,0 +1,346 @@
+use super::gemini::GeminiRequest;
+use super::{is_plain_path, Provider};
+use crate::{
+    config::AppConfig, context::RequestContext, error::AppError, proxy::client::UpstreamClient,
+};
+use async_trait::async_trait;
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, Response},
+};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{debug, error};
+
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const JWT_BEARER_GRANT: &str = "urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer";
+/// Refresh access tokens this many seconds before they expire
+const TOKEN_REFRESH_MARGIN: i64 = 60;
+
+#[derive(Clone)]
+struct CachedToken {
+    access_token: String,
+    expires_at: i64,
+}
+
+#[derive(Deserialize)]
+struct ServiceAccountFile {
+    client_email: String,
+    private_key: String,
+    private_key_id: Option<String>,
+    project_id: Option<String>,
+    token_uri: Option<String>,
+}
+
+/// Service account credentials, parsed once when the provider is created
+struct ServiceAccount {
+    client_email: String,
+    key: EncodingKey,
+    key_id: Option<String>,
+    project_id: Option<String>,
+    token_uri: String,
+}
+
+impl ServiceAccount {
+    fn parse(credentials: &str, token_uri: Option<&str>) -> Result<Self, String> {
+        let file: ServiceAccountFile = serde_json::from_str(credentials)
+            .map_err(|e| format!("Invalid Google service account credentials: {}", e))?;
+        let key = EncodingKey::from_rsa_pem(file.private_key.as_bytes())
+            .map_err(|e| format!("Invalid service account private key: {}", e))?;
+        Ok(Self {
+            client_email: file.client_email,
+            key,
+            key_id: file.private_key_id,
+            project_id: file.project_id,
+            token_uri: token_uri
+                .map(String::from)
+                .or(file.token_uri)
+                .unwrap_or_else(|| DEFAULT_TOKEN_URI.to_string()),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+/// VertexProvider handles Gemini models served through Google Cloud Vertex AI
+pub struct VertexProvider {
+    project: Option<String>,
+    location: String,
+    account: Option<ServiceAccount>,
+    /// Access token of the service account, reused until shortly before it expires
+    token: Mutex<Option<CachedToken>>,
+    /// Client for token exchanges, with the provider's proxy, CA bundle and timeouts
+    client: Arc<UpstreamClient>,
+}
+
+/// Project, location and access token resolved for a Vertex AI request
+#[derive(Debug, Clone, Default)]
+struct VertexRequest {
+    project: String,
+    location: String,
+    access_token: String,
+}
+
+impl VertexProvider {
+    pub fn new(config: &AppConfig, client: Arc<UpstreamClient>) -> Result<Self, String> {
+        let account = config
+            .google_credentials
+            .as_deref()
+            .map(|credentials| {
+                ServiceAccount::parse(credentials, config.vertex_token_uri.as_deref())
+            })
+            .transpose()?;
+        Ok(Self {
+            project: config.vertex_project.clone(),
+            location: config.vertex_location.clone(),
+            account,
+            token: Mutex::new(None),
+            client,
+        })
+    }
+
+    fn request(ctx: &RequestContext) -> VertexRequest {
+        ctx.get::<VertexRequest>().cloned().unwrap_or_default()
+    }
+
+    /// Exchange a signed service-account JWT for an OAuth access token, reusing the cached token
+    async fn service_account_token(&self, account: &ServiceAccount) -> Result<String, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        if let Some(token) = self.token.lock().as_ref() {
+            if token.expires_at - TOKEN_REFRESH_MARGIN > now {
+                debug!("Using cached Vertex AI access token");
+                return Ok(token.access_token.clone());
+            }
+        }
+
+        debug!(
+            "Exchanging service account JWT for access token at {}",
+            account.token_uri
+        );
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = account.key_id.clone();
+        let claims = Claims {
+            iss: &account.client_email,
+            scope: CLOUD_PLATFORM_SCOPE,
+            aud: &account.token_uri,
+            iat: now,
+            exp: now + 3600,
+        };
+        let assertion = encode(&header, &claims, &account.key).map_err(|e| {
+            error!("Failed to sign service account JWT: {}", e);
+            AppError::RequestError("Failed to sign service account JWT".to_string())
+        })?;
+
+        let response = self
+            .client
+            .client
+            .post(&account.token_uri)
+            .timeout(self.client.timeout)
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(format!(
+                "grant_type={}&assertion={}",
+                JWT_BEARER_GRANT, assertion
+            ))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            error!("Token exchange failed with status {}: {}", status, body);
+            return Err(AppError::UpstreamUnavailable(format!(
+                "Vertex AI token exchange failed with status {}",
+                status
+            )));
+        }
+
+        let token: TokenResponse = response.json().await?;
+        *self.token.lock() = Some(CachedToken {
+            access_token: token.access_token.clone(),
+            expires_at: now + token.expires_in.unwrap_or(3600),
+        });
+        Ok(token.access_token)
+    }
+}
+
+/// Project IDs and locations such as `my-project` or `us-central1`
+fn is_resource_id(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+#[async_trait]
+impl Provider for VertexProvider {
+    fn base_url(&self, ctx: &RequestContext) -> String {
+        let request = Self::request(ctx);
+        if request.location == "global" {
+            "https://aiplatform.googleapis.com".to_string()
+        } else {
+            format!("https://{}-aiplatform.googleapis.com", request.location)
+        }
+    }
+
+    fn name(&self) -> &str {
+        "vertex"
+    }
+
+    fn requires_body(&self, _path: &str) -> bool {
+        true
+    }
+
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        ctx.insert(GeminiRequest::capture(body));
+
+        // Prefer the configured service account, otherwise forward a caller supplied token
+        let (access_token, account_project) = match &self.account {
+            Some(account) => (
+                self.service_account_token(account).await?,
+                account.project_id.clone(),
+            ),
+            None => {
+                let token = ctx
+                    .header(http::header::AUTHORIZATION.as_str())
+                    .map(|auth| auth.trim_start_matches("Bearer ").trim().to_string())
+                    .filter(|token| !token.is_empty())
+                    .ok_or_else(|| {
+                        error!(
+                            "No service account configured and no bearer token provided for Vertex AI"
+                        );
+                        AppError::MissingApiKey
+                    })?;
+                (token, None)
+            }
+        };
+
+        // The gateway's own credentials only ever reach the configured project and location,
+        // callers choose them only when they bring their own token
+        let (project, location) = if self.account.is_some() {
+            (
+                self.project.clone().or(account_project),
+                self.location.clone(),
+            )
+        } else {
+            (
+                ctx.header("x-vertex-project")
+                    .map(String::from)
+                    .or_else(|| self.project.clone()),
+                ctx.header("x-vertex-location")
+                    .unwrap_or(&self.location)
+                    .to_string(),
+            )
+        };
+        let project = project.ok_or_else(|| {
+            error!("No Vertex AI project configured or provided in x-vertex-project");
+            AppError::RequestError("Vertex AI project is not configured".to_string())
+        })?;
+        // Both end up in the host name and path of the upstream URL
+        if !is_resource_id(&project) || !is_resource_id(&location) {
+            error!(
+                "Invalid Vertex AI project or location: {} / {}",
+                project, location
+            );
+            return Err(AppError::RequestError(
+                "Invalid Vertex AI project or location".to_string(),
+            ));
+        }
+        if !ctx.path.contains("/chat/completions") && !is_plain_path(&ctx.path) {
+            error!("Refusing Vertex AI path with dot segments: {}", ctx.path);
+            return Err(AppError::RequestError("Invalid request path".to_string()));
+        }
+
+        ctx.insert(VertexRequest {
+            project,
+            location,
+            access_token,
+        });
+        Ok(())
+    }
+
+    fn transform_path(&self, ctx: &RequestContext) -> String {
+        let request = Self::request(ctx);
+        let prefix = format!(
+            "/v1/projects/{}/locations/{}",
+            request.project, request.location
+        );
+
+        if ctx.path.contains("/chat/completions") {
+            format!(
+                "{}/publishers/google/models/{}",
+                prefix,
+                GeminiRequest::get(ctx).model_method()
+            )
+        } else {
+            format!(
+                "{}{}",
+                prefix,
+                ctx.path.strip_prefix("/v1").unwrap_or(&ctx.path)
+            )
+        }
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        debug!("Processing Vertex AI request headers");
+        let mut headers = HeaderMap::new();
+
+        // Add content type
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        // Process authentication
+        let token = Self::request(ctx).access_token;
+        if token.is_empty() {
+            error!("No access token available for Vertex AI request");
+            return Err(AppError::MissingApiKey);
+        }
+        headers.insert(
+            http::header::AUTHORIZATION,
+            http::header::HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|_| {
+                error!("Failed to create Vertex AI authorization header");
+                AppError::InvalidHeader
+            })?,
+        );
+
+        Ok(headers)
+    }
+
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        GeminiRequest::get(ctx).prepare_request_body(body)
+    }
+
+    async fn process_response(
+        &self,
+        ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        GeminiRequest::get(ctx)
+            .process_response("vertex", response)
+            .await
+    }
+}
@@ -0,0 +1,115 @@
+use super::rerank::{self, RerankRequest};
+use super::Provider;
+use crate::{context::RequestContext, error::AppError};
+use async_trait::async_trait;
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, Response},
+};
+use serde_json::json;
+use tracing::{debug, error};
+
+/// VoyageProvider handles Voyage AI embeddings and rerank
+pub struct VoyageProvider {
+    base_url: String,
+}
+
+impl VoyageProvider {
+    pub fn new() -> Self {
+        Self {
+            base_url: "https://api.voyageai.com".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for VoyageProvider {
+    fn base_url(&self, _ctx: &RequestContext) -> String {
+        self.base_url.clone()
+    }
+
+    fn name(&self) -> &str {
+        "voyage"
+    }
+
+    fn requires_body(&self, path: &str) -> bool {
+        path == rerank::RERANK_PATH
+    }
+
+    async fn before_request(&self, ctx: &mut RequestContext, body: &Bytes) -> Result<(), AppError> {
+        if let Some(request) = rerank::capture(&ctx.path, body)? {
+            ctx.insert(request);
+        }
+        Ok(())
+    }
+
+    fn process_headers(&self, ctx: &RequestContext) -> Result<HeaderMap, AppError> {
+        let original_headers = &ctx.headers;
+        debug!("Processing Voyage request headers");
+        let mut headers = HeaderMap::new();
+
+        // Add content type
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::header::HeaderValue::from_static("application/json"),
+        );
+
+        // Process authentication
+        if let Some(auth) = original_headers
+            .get(http::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+        {
+            debug!("Using provided authorization header for Voyage");
+            headers.insert(
+                http::header::AUTHORIZATION,
+                http::header::HeaderValue::from_str(auth).map_err(|_| {
+                    error!("Failed to process Voyage authorization header");
+                    AppError::InvalidHeader
+                })?,
+            );
+        } else {
+            error!("No authorization header found for Voyage request");
+            return Err(AppError::MissingApiKey);
+        }
+
+        Ok(headers)
+    }
+
+    async fn prepare_request_body(
+        &self,
+        ctx: &RequestContext,
+        body: Bytes,
+    ) -> Result<Bytes, AppError> {
+        let Some(request) = ctx.get::<RerankRequest>() else {
+            return Ok(body);
+        };
+        let mut transformed = json!({
+            "model": request.model,
+            "query": request.query,
+            "documents": request.texts(),
+            "return_documents": false,
+        });
+        if let Some(top_n) = request.top_n {
+            transformed["top_k"] = json!(top_n);
+        }
+        Ok(Bytes::from(serde_json::to_vec(&transformed)?))
+    }
+
+    async fn process_response(
+        &self,
+        ctx: &RequestContext,
+        response: Response<Body>,
+    ) -> Result<Response<Body>, AppError> {
+        let Some(request) = ctx.get::<RerankRequest>() else {
+            return Ok(response);
+        };
+        rerank::process_response(response, |body| {
+            request.response(
+                None,
+                rerank::results(body.get("data"), "relevance_score"),
+                body.get("usage").cloned(),
+            )
+        })
+        .await
+    }
+}
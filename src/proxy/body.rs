@@ -0,0 +1,83 @@
+use crate::error::AppError;
+use axum::body::{Body, Bytes};
+use axum::http::{header::CONTENT_LENGTH, HeaderMap};
+use futures_util::{Stream, StreamExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{debug, error};
+
+/// An incoming request body, streamed through unless a provider needs the bytes
+pub enum RequestBody {
+    Buffered(Bytes),
+    Streaming(Body),
+}
+
+impl RequestBody {
+    /// Read the whole body into memory, rejecting bodies larger than `limit`
+    pub async fn buffer(self, limit: usize) -> Result<Bytes, AppError> {
+        let body = match self {
+            RequestBody::Buffered(bytes) => return Ok(bytes),
+            RequestBody::Streaming(body) => body,
+        };
+
+        let mut stream = std::pin::pin!(limited(body, limit));
+        let mut buffer = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            buffer.extend_from_slice(&chunk?);
+        }
+        debug!("Buffered request body of {} bytes", buffer.len());
+        Ok(Bytes::from(buffer))
+    }
+
+    /// The buffered bytes, if the body has been read
+    pub fn bytes(&self) -> Option<&Bytes> {
+        match self {
+            RequestBody::Buffered(bytes) => Some(bytes),
+            RequestBody::Streaming(_) => None,
+        }
+    }
+}
+
+/// Reject requests whose declared `Content-Length` is over `limit` before reading anything
+pub fn check_content_length(headers: &HeaderMap, limit: usize) -> Result<(), AppError> {
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    match length {
+        Some(length) if length > limit => {
+            error!(
+                "Request body of {} bytes exceeds the {} byte limit",
+                length, limit
+            );
+            Err(AppError::PayloadTooLarge(limit))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Stream a body to the provider, failing once more than `limit` bytes have been read.
+/// The returned flag is set when the stream was cut off by the limit.
+pub fn streaming(body: Body, limit: usize) -> (reqwest::Body, Arc<AtomicBool>) {
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let flag = exceeded.clone();
+    let stream = limited(body, limit).inspect(move |chunk| {
+        if matches!(chunk, Err(AppError::PayloadTooLarge(_))) {
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+    (reqwest::Body::wrap_stream(stream), exceeded)
+}
+
+fn limited(body: Body, limit: usize) -> impl Stream<Item = Result<Bytes, AppError>> {
+    let mut read = 0;
+    body.into_data_stream().map(move |chunk| {
+        let chunk = chunk?;
+        read += chunk.len();
+        if read > limit {
+            error!("Request body exceeds the {} byte limit", limit);
+            return Err(AppError::PayloadTooLarge(limit));
+        }
+        Ok(chunk)
+    })
+}
@@ -1,31 +1,119 @@
-use crate::config::AppConfig;
-use once_cell::sync::Lazy;
+use crate::config::{AppConfig, ClientConfig, HttpVersion};
 use std::time::Duration;
 use tracing::info;
 
-pub fn create_client(config: &AppConfig) -> reqwest::Client {
-    info!("Creating HTTP client with optimized settings");
-
-    reqwest::Client::builder()
-        .pool_max_idle_per_host(config.max_connections)
-        .pool_idle_timeout(Duration::from_secs(30))
-        .http2_prior_knowledge()
-        .http2_keep_alive_interval(Duration::from_secs(5))
-        .http2_keep_alive_timeout(Duration::from_secs(10))
-        .http2_adaptive_window(true)
-        .tcp_keepalive(Duration::from_secs(5))
-        .tcp_nodelay(true)
-        .use_rustls_tls()
-        .timeout(Duration::from_secs(30))
-        .connect_timeout(Duration::from_secs(3))
-        .pool_max_idle_per_host(32)
-        .gzip(true)
-        .brotli(true)
-        .build()
-        .expect("Failed to create HTTP client")
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_READ_TIMEOUT: u64 = 300;
+const DEFAULT_TIMEOUT: u64 = 600;
+const DEFAULT_STREAM_FIRST_BYTE_TIMEOUT: u64 = 300;
+const DEFAULT_STREAM_IDLE_TIMEOUT: u64 = 120;
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 32;
+/// Matches the largest default request limit, for file downloads
+const DEFAULT_MAX_RESPONSE_SIZE: usize = 512 * 1024 * 1024;
+
+/// HTTP client and timeouts used for a provider's upstream requests
+pub struct UpstreamClient {
+    pub client: reqwest::Client,
+    /// Time allowed until the response headers arrive
+    pub read_timeout: Duration,
+    /// Time allowed for a complete non-streaming response
+    pub timeout: Duration,
+    /// Time allowed until the first chunk of a streamed response
+    pub stream_first_byte_timeout: Duration,
+    /// Time allowed between chunks of a streamed response
+    pub stream_idle_timeout: Duration,
+    /// Upstream silence after which SSE keep-alive comments are sent, disabled when unset or zero
+    pub stream_keepalive_interval: Option<Duration>,
+    /// Largest non-streaming response body read into memory
+    pub max_response_size: usize,
 }
 
-pub static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
-    let config = AppConfig::new();
-    create_client(&config)
-});
+impl UpstreamClient {
+    pub fn new(config: &AppConfig, settings: &ClientConfig) -> Result<Self, String> {
+        let mut builder = reqwest::Client::builder()
+            .pool_max_idle_per_host(
+                settings
+                    .pool_max_idle_per_host
+                    .unwrap_or(DEFAULT_POOL_MAX_IDLE_PER_HOST),
+            )
+            .pool_idle_timeout(Duration::from_secs(30))
+            .http2_keep_alive_interval(Duration::from_secs(5))
+            .http2_keep_alive_timeout(Duration::from_secs(10))
+            .http2_adaptive_window(true)
+            .tcp_keepalive(Duration::from_secs(config.tcp_keepalive_interval))
+            .tcp_nodelay(config.tcp_nodelay)
+            .use_rustls_tls()
+            .connect_timeout(Duration::from_secs(
+                settings.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+            ))
+            .gzip(true)
+            .brotli(true);
+
+        builder = match settings.http_version.unwrap_or_default() {
+            // HTTP/2 is negotiated through ALPN, plaintext upstreams use HTTP/1.1
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+
+        if let Some(proxy) = &settings.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| format!("invalid proxy {}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = &settings.ca_bundle {
+            let pem = std::fs::read(path)
+                .map_err(|e| format!("failed to read CA bundle {}: {}", path, e))?;
+            let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("invalid CA bundle {}: {}", path, e))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        if let Some(path) = &settings.client_cert {
+            let pem = std::fs::read(path)
+                .map_err(|e| format!("failed to read client certificate {}: {}", path, e))?;
+            let identity = reqwest::Identity::from_pem(&pem)
+                .map_err(|e| format!("invalid client certificate {}: {}", path, e))?;
+            builder = builder.identity(identity);
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| format!("failed to create HTTP client: {}", e))?;
+
+        Ok(Self {
+            client,
+            read_timeout: Duration::from_secs(
+                settings.read_timeout.unwrap_or(DEFAULT_READ_TIMEOUT),
+            ),
+            timeout: Duration::from_secs(settings.timeout.unwrap_or(DEFAULT_TIMEOUT)),
+            stream_first_byte_timeout: Duration::from_secs(
+                settings
+                    .stream_first_byte_timeout
+                    .unwrap_or(DEFAULT_STREAM_FIRST_BYTE_TIMEOUT),
+            ),
+            stream_idle_timeout: Duration::from_secs(
+                settings
+                    .stream_idle_timeout
+                    .unwrap_or(DEFAULT_STREAM_IDLE_TIMEOUT),
+            ),
+            stream_keepalive_interval: settings
+                .stream_keepalive_interval
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs),
+            max_response_size: settings
+                .max_response_size
+                .unwrap_or(DEFAULT_MAX_RESPONSE_SIZE),
+        })
+    }
+
+    /// Client for providers without their own `[clients.<name>]` settings
+    pub fn shared(config: &AppConfig) -> Self {
+        info!("Creating shared upstream HTTP client");
+        Self::new(config, &config.client)
+            .unwrap_or_else(|e| panic!("Invalid UPSTREAM_* client settings: {}", e))
+    }
+}
@@ -1,103 +1,402 @@
-use crate::providers::Provider;
-use axum::body::to_bytes;
+use crate::config::GuardrailStage;
+use crate::guardrails::{self, Finding};
+use crate::health::Probe;
+use crate::providers::{self, Provider};
+use crate::usage::Routed;
 use axum::{
-    body::{Body, Bytes},
-    http::{HeaderMap, HeaderValue, Request, Response, StatusCode},
+    body::{to_bytes, Body, Bytes},
+    http::{
+        header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderMap, HeaderValue, Request, Response, StatusCode,
+    },
 };
-use futures_util::StreamExt;
+use mime::Mime;
 use reqwest::Method;
-use std::sync::Arc;
-use tracing::{debug, error};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tracing::{debug, error, warn};
 
-use crate::{config::AppConfig, error::AppError, providers::create_provider};
+use crate::{config::AppConfig, context::RequestContext, error::AppError, state::AppState};
+use client::UpstreamClient;
 
-mod client;
-pub use client::CLIENT;
+pub(crate) mod body;
+pub mod client;
 mod signing;
+mod stream;
+
+use body::RequestBody;
+
+const JSON_ROUTES: [&str; 4] = ["/completions", "/embeddings", "/messages", "/rerank"];
 
 pub async fn proxy_request_to_provider(
-    config: Arc<AppConfig>,
+    state: &AppState,
     provider_name: &str,
     mut original_request: Request<Body>,
 ) -> Result<Response<Body>, AppError> {
-    let provider = create_provider(provider_name)?;
+    let config = &state.config;
+    let limit = config.max_body_size_for(original_request.uri().path());
+    body::check_content_length(original_request.headers(), limit)?;
+    let mut body = RequestBody::Streaming(std::mem::replace(
+        original_request.body_mut(),
+        Body::empty(),
+    ));
 
-    // Extract body bytes
-    let body = std::mem::replace(original_request.body_mut(), Body::empty());
-    let body_bytes = to_bytes(body, usize::MAX)
-        .await
-        .map_err(|e| AppError::AxumError(e.into()))?;
+    // Configured model aliases select their own provider and upstream model, and keys are
+    // checked against the model as requested
+    let key = config.key_for_request(&original_request);
+    // Provider names are matched in lower case, like the registry and guardrail scopes
+    let mut provider_name = provider_name.to_lowercase();
+    let mut requested_model = None;
+    if (has_aliases(config) || key.is_some()) && is_json(original_request.headers()) {
+        let body_bytes = body.buffer(limit).await?;
+        requested_model = providers::request_model(original_request.headers(), &body_bytes);
+        body = match resolve_alias(config, &body_bytes)? {
+            Some((alias_provider, aliased_body)) => {
+                provider_name = alias_provider;
+                RequestBody::Buffered(aliased_body)
+            }
+            None => RequestBody::Buffered(body_bytes),
+        };
+    }
+    if let Some(key) = key {
+        let allowed = match &requested_model {
+            Some(model) => config.allows(key, &provider_name, model),
+            None => config.allows_provider(key, &provider_name),
+        };
+        if !allowed {
+            let denied = requested_model.unwrap_or_else(|| provider_name.clone());
+            warn!("Key {} is not allowed to use {}", key.name, denied);
+            return Err(AppError::NotAllowed(denied));
+        }
+    }
+    let provider = state.providers.get(&provider_name)?;
+    let client = state.providers.client(&provider_name);
+
+    // Request guardrails see the body after alias resolution, before provider transforms
+    let pii_policy = key.and_then(|key| key.pii);
+    let mut findings = Vec::new();
+    let mut pii_vault = None;
+    // Any body that is not an upload may be read as JSON upstream, whatever its content type
+    // says, and uploads to JSON routes would slip past the checks
+    let check_request = state
+        .guardrails
+        .applies(GuardrailStage::Request, &provider_name);
+    let upload = is_upload(original_request.headers());
+    if check_request && upload && is_json_route(original_request.uri().path()) {
+        return Err(AppError::RequestError(
+            "Request body must be JSON".to_string(),
+        ));
+    }
+    if check_request && !upload {
+        let (checked_body, request_findings, vault) = state
+            .guardrails
+            .check_request(&provider_name, pii_policy, body.buffer(limit).await?)
+            .await?;
+        body = RequestBody::Buffered(checked_body);
+        findings = request_findings;
+        pii_vault = vault;
+    }
+
+    // Only buffer the body when the provider transforms it or it has to be signed
+    let path = original_request.uri().path();
+    if provider.requires_body(path) || provider.requires_signing() {
+        body = RequestBody::Buffered(body.buffer(limit).await?);
+    }
+
+    // A gateway key in the Authorization header is the gateway's credential, never the provider's
+    let mut request_headers = original_request.headers().clone();
+    if config.key_for_bearer(&original_request).is_some() {
+        request_headers.remove(AUTHORIZATION);
+    }
+    let mut ctx = RequestContext::new(path, request_headers);
+    if let Some(body_bytes) = body.bytes() {
+        ctx.model = providers::request_model(&ctx.headers, body_bytes);
+    }
 
-    // Call before_request first to set up any provider state
+    // Call before_request first to resolve per-request provider state
     provider
-        .before_request(original_request.headers(), &body_bytes)
+        .before_request(&mut ctx, body.bytes().unwrap_or(&Bytes::new()))
         .await?;
 
     // Process headers and transform path
-    let headers = provider.process_headers(original_request.headers())?;
-    let path = original_request.uri().path();
-    let modified_path = provider.transform_path(path);
+    let mut headers = provider.process_headers(&ctx)?;
+    let modified_path = provider.transform_path(&ctx);
 
-    // Prepare request body
-    let prepared_body = provider.prepare_request_body(body_bytes).await?;
+    // Providers default to JSON, multipart and binary uploads keep the caller's content type
+    if !is_json(original_request.headers()) {
+        if let Some(content_type) = original_request.headers().get(CONTENT_TYPE) {
+            debug!("Forwarding content type: {:?}", content_type);
+            headers.insert(CONTENT_TYPE, content_type.clone());
+        }
+    }
 
-    // Construct final URL
+    // Construct final URL, providers may already carry query parameters in the path
+    let separator = if modified_path.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
     let query = original_request
         .uri()
         .query()
-        .map(|q| format!("?{}", q))
+        .map(|q| format!("{}{}", separator, q))
         .unwrap_or_default();
-    let url = format!("{}{}{}", provider.base_url(), modified_path, query);
+    let url = format!("{}{}{}", provider.base_url(&ctx), modified_path, query);
     debug!("Using URL: {}", url);
 
-    // Handle AWS signing if required
-    let final_headers = if provider.requires_signing() {
-        if let Some((access_key, secret_key, region)) = provider.get_signing_credentials(&headers) {
-            signing::sign_aws_request(
-                original_request.method().as_str(),
-                &url,
-                &prepared_body,
-                &access_key,
-                &secret_key,
-                &region,
-                "bedrock",
-            )
-            .await?
-        } else {
-            headers
+    let mut body_limit_exceeded = None;
+    let upstream_body = match body {
+        RequestBody::Buffered(body_bytes) => {
+            // Prepare request body
+            let prepared_body = provider.prepare_request_body(&ctx, body_bytes).await?;
+
+            // Handle AWS signing if required
+            if provider.requires_signing() {
+                if let Some((access_key, secret_key, region)) =
+                    provider.get_signing_credentials(&ctx)
+                {
+                    headers = signing::sign_aws_request(
+                        original_request.method().as_str(),
+                        &url,
+                        &prepared_body,
+                        &access_key,
+                        &secret_key,
+                        &region,
+                        "bedrock",
+                    )
+                    .await?;
+                }
+            }
+            reqwest::Body::from(prepared_body)
+        }
+        RequestBody::Streaming(stream) => {
+            debug!("Streaming request body to {}", provider.name());
+            // Keep the declared length so the upstream request is not chunked
+            if let Some(length) = original_request.headers().get(CONTENT_LENGTH) {
+                headers.insert(CONTENT_LENGTH, length.clone());
+            }
+            let (upstream_body, exceeded) = body::streaming(stream, limit);
+            body_limit_exceeded = Some(exceeded);
+            upstream_body
         }
-    } else {
-        headers
     };
 
-    debug!(
-        "Final headers in proxy_request_to_provider: {:?}",
-        final_headers
-    );
+    debug!("Final headers in proxy_request_to_provider: {:?}", headers);
 
     // Send the request with signed headers
     let response = send_provider_request(
         original_request.method().clone(),
         url,
-        final_headers,
-        prepared_body,
-        &provider,
-        config,
+        headers,
+        upstream_body,
+        provider.as_ref(),
+        &client,
     )
-    .await?;
+    .await
+    .map_err(|e| match body_limit_exceeded {
+        Some(exceeded) if exceeded.load(Ordering::Relaxed) => AppError::PayloadTooLarge(limit),
+        _ => e,
+    });
+    let probe = original_request.extensions().get::<Probe>().is_some();
+    match &response {
+        _ if probe => {}
+        Ok(response) if response.status().is_server_error() => state
+            .health
+            .record_failure(provider.name(), format!("HTTP {}", response.status())),
+        Ok(_) => state.health.record_success(provider.name()),
+        Err(AppError::PayloadTooLarge(_)) => {}
+        Err(e) => state.health.record_failure(provider.name(), e.to_string()),
+    }
+    let response = response?;
+
+    let response = provider.process_response(&ctx, response).await?;
+    let response = normalize_error(provider.name(), response).await?;
+    let routed = Routed {
+        provider: provider_name.clone(),
+        model: ctx.model.clone(),
+    };
+    let check_response = response.status().is_success()
+        && state
+            .guardrails
+            .applies(GuardrailStage::Response, &provider_name);
+
+    // Redacted PII goes back into successful responses after the response guardrails ran
+    let restore = response.status().is_success() && pii_vault.is_some();
+
+    if !is_event_stream(response.headers()) {
+        let (mut parts, body) = response.into_parts();
+        let body = if (check_response || restore) && is_json(&parts.headers) {
+            let mut bytes = to_bytes(body, client.max_response_size)
+                .await
+                .map_err(|_| AppError::ResponseTooLarge(client.max_response_size))?;
+            if check_response {
+                let response_findings;
+                (bytes, response_findings) = state
+                    .guardrails
+                    .check_response(&provider_name, pii_policy, bytes)
+                    .await?;
+                findings.extend(response_findings);
+            }
+            if let Some(vault) = pii_vault.as_ref().filter(|_| restore) {
+                bytes = state.guardrails.restore_response(bytes, vault)?;
+            }
+            parts.headers.remove(CONTENT_LENGTH);
+            Body::from(bytes)
+        } else {
+            body
+        };
+        add_guardrails_header(&mut parts.headers, &findings);
+        parts.extensions.insert(routed);
+        return Ok(Response::from_parts(parts, body));
+    }
+
+    // Error events and heartbeats go out after the provider's own stream translation
+    let (mut parts, mut body) = response.into_parts();
+    add_guardrails_header(&mut parts.headers, &findings);
+    parts.extensions.insert(routed);
+    if check_response || restore {
+        body = guardrails::stream::check_stream(
+            state.guardrails.clone(),
+            provider_name.clone(),
+            pii_policy,
+            pii_vault.filter(|_| restore),
+            body,
+        );
+    }
+    body = stream::with_error_events(body, provider.name().to_string(), state.shutdown.clone());
+    if let Some(interval) = client.stream_keepalive_interval {
+        body = stream::with_heartbeats(body, interval);
+    }
+    Ok(Response::from_parts(parts, body))
+}
+
+/// Report guardrails that flagged or redacted content
+fn add_guardrails_header(headers: &mut HeaderMap, findings: &[Finding]) {
+    if findings.is_empty() {
+        return;
+    }
+    if let Some(value) = Finding::header_value(findings) {
+        headers.insert(guardrails::GUARDRAILS_HEADER, value);
+    }
+}
+
+fn has_aliases(config: &AppConfig) -> bool {
+    config.models.iter().any(|m| m.model.is_some())
+}
+
+/// Parsed `Content-Type`, `None` when it is missing or malformed
+fn media_type(headers: &HeaderMap) -> Option<Mime> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|ct| ct.parse().ok())
+}
+
+/// JSON bodies, including `application/*+json` and requests without a content type
+fn is_json(headers: &HeaderMap) -> bool {
+    if !headers.contains_key(CONTENT_TYPE) {
+        return true;
+    }
+    media_type(headers).is_some_and(|mime| {
+        mime.essence_str() == mime::APPLICATION_JSON.essence_str()
+            || mime.suffix() == Some(mime::JSON)
+    })
+}
+
+/// Routes whose bodies are always JSON, such as chat completions and embeddings
+fn is_json_route(path: &str) -> bool {
+    JSON_ROUTES.iter().any(|route| path.ends_with(route))
+}
 
-    provider.process_response(response).await
+/// Multipart forms and binary uploads, which guardrails cannot inspect
+fn is_upload(headers: &HeaderMap) -> bool {
+    media_type(headers).is_some_and(|mime| {
+        matches!(
+            mime.type_(),
+            mime::MULTIPART | mime::AUDIO | mime::VIDEO | mime::IMAGE
+        ) || mime.essence_str() == mime::APPLICATION_OCTET_STREAM.essence_str()
+    })
+}
+
+/// Rewrite provider error responses into the gateway's OpenAI-compatible error schema
+async fn normalize_error(
+    provider: &str,
+    response: Response<Body>,
+) -> Result<Response<Body>, AppError> {
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error())
+        || is_event_stream(response.headers())
+    {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = to_bytes(body, usize::MAX).await?;
+
+    // Providers may already have normalized their own errors
+    let normalized = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .is_ok_and(|body| body.pointer("/error/provider").is_some());
+    if normalized {
+        return Ok(Response::from_parts(parts, Body::from(bytes)));
+    }
+
+    // AWS services name the exception in a header, e.g. `ValidationException:http://...`
+    let kind = parts
+        .headers
+        .get("x-amzn-errortype")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(':').next());
+    debug!("Normalizing {} error response from {}", status, provider);
+    let error_body = crate::error::provider_error(provider, status, &bytes, kind);
+
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(Response::from_parts(
+        parts,
+        Body::from(serde_json::to_vec(&error_body)?),
+    ))
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    media_type(headers).is_some_and(|mime| mime.essence_str() == mime::TEXT_EVENT_STREAM)
+}
+
+/// Rewrite the request model when it names a configured alias
+fn resolve_alias(config: &AppConfig, body: &Bytes) -> Result<Option<(String, Bytes)>, AppError> {
+    let Ok(mut request_body) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return Ok(None);
+    };
+    let Some(alias) = request_body["model"]
+        .as_str()
+        .and_then(|model| config.model_alias(model))
+    else {
+        return Ok(None);
+    };
+
+    debug!(
+        "Resolved model alias {} to {} on {}",
+        alias.id,
+        alias.upstream_model(),
+        alias.provider
+    );
+    request_body["model"] = serde_json::Value::String(alias.upstream_model().to_string());
+    Ok(Some((
+        alias.provider.clone(),
+        Bytes::from(serde_json::to_vec(&request_body)?),
+    )))
 }
 
 pub async fn send_provider_request(
     method: Method,
     url: String,
     headers: HeaderMap,
-    body: Bytes,
-    provider: &Box<dyn Provider>,
-    config: Arc<AppConfig>,
+    body: reqwest::Body,
+    provider: &dyn Provider,
+    client: &UpstreamClient,
 ) -> Result<Response<Body>, AppError> {
-    let client = &*CLIENT;
+    debug!("Sending request to provider: {}", provider.name());
 
     let reqwest_headers = headers
         .iter()
@@ -118,19 +417,29 @@ pub async fn send_provider_request(
         reqwest_headers
     );
 
-    let response = client
+    let started = tokio::time::Instant::now();
+    let request = client
+        .client
         .request(method, url)
         .headers(reqwest_headers)
         .body(body)
-        .send()
-        .await?;
+        .send();
+    let response = tokio::time::timeout(client.read_timeout, request)
+        .await
+        .map_err(|_| {
+            error!("No response headers from {} in time", provider.name());
+            AppError::UpstreamTimeout(client.read_timeout.as_secs())
+        })??;
 
-    process_response(response, config).await
+    // The total timeout covers non-streaming bodies, streams are bounded by the idle timeout
+    let remaining = client.timeout.saturating_sub(started.elapsed());
+    process_response(response, client, remaining).await
 }
 
 async fn process_response(
     response: reqwest::Response,
-    config: Arc<AppConfig>,
+    client: &UpstreamClient,
+    remaining: Duration,
 ) -> Result<Response<Body>, AppError> {
     let status = StatusCode::from_u16(response.status().as_u16())?;
     let mut response_builder = Response::builder().status(status);
@@ -142,37 +451,188 @@ async fn process_response(
         }
     }
 
-    // Fast path for non-streaming responses
-    if !response
+    let content_type = response
         .headers()
         .get(reqwest::header::CONTENT_TYPE)
         .and_then(|v| v.to_str().ok())
-        .map_or(false, |ct| {
-            ct.contains("application/vnd.amazon.eventstream") || ct.contains("text/event-stream")
-        })
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let ndjson = content_type.contains("application/x-ndjson");
+
+    // Fast path for non-streaming responses
+    if !ndjson
+        && !content_type.contains("application/vnd.amazon.eventstream")
+        && !content_type.contains("text/event-stream")
     {
-        let body = response.bytes().await?;
+        let body =
+            tokio::time::timeout(remaining, read_response(response, client.max_response_size))
+                .await
+                .map_err(|_| {
+                    error!("Provider response body not received in time");
+                    AppError::UpstreamTimeout(client.timeout.as_secs())
+                })??;
         return Ok(response_builder.body(Body::from(body)).unwrap());
     }
 
     // Optimized streaming response handling
     debug!("Processing streaming response");
 
-    let stream = response.bytes_stream().map(|result| match result {
-        Ok(bytes) => Ok(bytes),
-        Err(e) => {
-            error!("Stream error: {}", e);
-            Err(std::io::Error::new(std::io::ErrorKind::Other, e))
+    let stream = stream::with_timeouts(
+        response.bytes_stream(),
+        client.stream_first_byte_timeout,
+        client.stream_idle_timeout,
+    );
+
+    // Streaming headers replace the upstream ones. NDJSON passed through keeps its media
+    // type, event streams are sent on as SSE.
+    let mut response = response_builder.body(Body::from_stream(stream)).unwrap();
+    let headers = response.headers_mut();
+    if !ndjson {
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    }
+    for (name, value) in [
+        ("cache-control", "no-cache"),
+        ("connection", "keep-alive"),
+        ("transfer-encoding", "chunked"),
+        ("x-accel-buffering", "no"),
+    ] {
+        headers.insert(name, HeaderValue::from_static(value));
+    }
+    Ok(response)
+}
+
+/// Read a non-streaming response body, failing once it exceeds `limit` bytes
+async fn read_response(mut response: reqwest::Response, limit: usize) -> Result<Bytes, AppError> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        error!("Provider response exceeds the {} byte limit", limit);
+        return Err(AppError::ResponseTooLarge(limit));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            error!("Provider response exceeds the {} byte limit", limit);
+            return Err(AppError::ResponseTooLarge(limit));
         }
-    });
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FileConfig;
+    use crate::health::ProviderHealth;
+    use crate::metrics::Metrics;
+    use crate::shutdown::Shutdown;
+    use crate::usage::Usage;
+    use std::sync::Arc;
+
+    /// Gateway state with the guardrails and keys of a config file
+    fn state(file: &str) -> AppState {
+        let file: FileConfig = toml::from_str(file).unwrap();
+        let mut config = AppConfig::load(FileConfig::default()).unwrap();
+        config.guardrails = file.guardrails;
+        config.keys = file.keys;
+        AppState::new(
+            Arc::new(config),
+            Arc::new(Metrics::default()),
+            Arc::new(ProviderHealth::default()),
+            Arc::new(Usage::default()),
+            Arc::new(Shutdown::new(Duration::ZERO, Duration::ZERO)),
+        )
+        .unwrap()
+    }
+
+    fn chat(content: &str) -> Request<Body> {
+        let body = serde_json::json!({
+            "model": "gpt-4o-mini",
+            "messages": [{ "role": "user", "content": content }],
+        });
+        Request::post("/v1/chat/completions")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
 
-    // Add streaming headers once
-    response_builder = response_builder
-        .header("content-type", "text/event-stream")
-        .header("cache-control", "no-cache")
-        .header("connection", "keep-alive")
-        .header("transfer-encoding", "chunked")
-        .header("x-accel-buffering", "no");
+    #[tokio::test]
+    async fn provider_scoped_guardrails_ignore_header_case() {
+        let state = state(
+            r#"
+            [[guardrails]]
+            name = "nope"
+            check = "deny_list"
+            action = "block"
+            providers = ["openai"]
+            patterns = ["forbidden"]
+            "#,
+        );
+        for provider in ["openai", "OpenAI", "OPENAI"] {
+            let result =
+                proxy_request_to_provider(&state, provider, chat("a forbidden word")).await;
+            assert!(
+                matches!(result, Err(AppError::GuardrailBlocked(ref name)) if name == "nope"),
+                "{} was not blocked",
+                provider
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn provider_keys_keep_certificate_restrictions() {
+        let state = state(
+            r#"
+            [[keys]]
+            name = "billing"
+            client_certs = ["billing.internal"]
+            providers = ["anthropic"]
+            "#,
+        );
+        let mut request = chat("hello");
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Bearer sk-upstream-provider-key"),
+        );
+        request
+            .extensions_mut()
+            .insert(crate::server::tls::ClientCertificate {
+                subject: "CN=billing.internal".to_string(),
+                names: vec!["billing.internal".to_string()],
+            });
+        let result = proxy_request_to_provider(&state, "openai", request).await;
+        assert!(matches!(result, Err(AppError::NotAllowed(_))));
+    }
 
-    Ok(response_builder.body(Body::from_stream(stream)).unwrap())
+    async fn streamed(content_type: &str) -> Response<Body> {
+        let upstream = http::Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .header("cache-control", "private")
+            .body("{\"done\":true}\n")
+            .unwrap();
+        let client = UpstreamClient::shared(&AppConfig::load(FileConfig::default()).unwrap());
+        process_response(upstream.into(), &client, Duration::from_secs(5))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn ndjson_streams_keep_their_content_type() {
+        let response = streamed("application/x-ndjson").await;
+        let types: Vec<_> = response.headers().get_all(CONTENT_TYPE).iter().collect();
+        assert_eq!(types, ["application/x-ndjson"]);
+        let cache: Vec<_> = response.headers().get_all("cache-control").iter().collect();
+        assert_eq!(cache, ["no-cache"]);
+    }
+
+    #[tokio::test]
+    async fn event_streams_are_labelled_once() {
+        for content_type in ["text/event-stream", "application/vnd.amazon.eventstream"] {
+            let response = streamed(content_type).await;
+            let types: Vec<_> = response.headers().get_all(CONTENT_TYPE).iter().collect();
+            assert_eq!(types, ["text/event-stream"]);
+        }
+    }
 }
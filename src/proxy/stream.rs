@@ -0,0 +1,153 @@
+use crate::error::error_body;
+use crate::shutdown::{Phase, Shutdown};
+use axum::body::{Body, Bytes};
+use futures_util::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, warn};
+
+/// SSE comment sent to keep idle connections open, ignored by SSE clients
+const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+
+/// Close a streamed response when the first chunk or any later chunk takes too long
+pub(super) fn with_timeouts<S, E>(
+    upstream: S,
+    first_byte_timeout: Duration,
+    idle_timeout: Duration,
+) -> impl Stream<Item = io::Result<Bytes>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<Box<dyn std::error::Error + Send + Sync>> + std::fmt::Display,
+{
+    futures_util::stream::unfold(Some((upstream, true)), move |state| async move {
+        let (mut upstream, first) = state?;
+        let timeout = if first {
+            first_byte_timeout
+        } else {
+            idle_timeout
+        };
+        match tokio::time::timeout(timeout, upstream.next()).await {
+            Ok(Some(Ok(bytes))) => Some((Ok(bytes), Some((upstream, false)))),
+            Ok(Some(Err(e))) => {
+                error!("Stream error: {}", e);
+                Some((Err(io::Error::other(e)), None))
+            }
+            Ok(None) => None,
+            Err(_) => {
+                let message = if first {
+                    "upstream stream sent no data before the first byte timeout"
+                } else {
+                    "upstream stream idle timeout"
+                };
+                error!("{} ({:?}), closing", message, timeout);
+                Some((Err(io::Error::new(io::ErrorKind::TimedOut, message)), None))
+            }
+        }
+    })
+}
+
+/// Emit `: keep-alive` comments while an SSE response is quiet for `interval`.
+/// Comments are only inserted between events, never inside a partially sent one.
+pub(super) fn with_heartbeats(body: Body, interval: Duration) -> Body {
+    let stream = futures_util::stream::unfold(
+        Some((body.into_data_stream(), true)),
+        move |state| async move {
+            let (mut upstream, mut at_boundary) = state?;
+            loop {
+                match tokio::time::timeout(interval, upstream.next()).await {
+                    Ok(Some(Ok(bytes))) => {
+                        if !bytes.is_empty() {
+                            at_boundary = bytes.ends_with(b"\n\n") || bytes.ends_with(b"\r\n\r\n");
+                        }
+                        return Some((Ok(bytes), Some((upstream, at_boundary))));
+                    }
+                    Ok(Some(Err(e))) => return Some((Err(io::Error::other(e)), None)),
+                    Ok(None) => return None,
+                    Err(_) if at_boundary => {
+                        debug!("Upstream quiet for {:?}, sending keep-alive", interval);
+                        return Some((Ok(Bytes::from_static(KEEP_ALIVE)), Some((upstream, true))));
+                    }
+                    Err(_) => continue,
+                }
+            }
+        },
+    );
+    Body::from_stream(stream)
+}
+
+/// End an SSE response with an OpenAI-style `data: {"error": ...}` event when the stream fails,
+/// or is still running when the shutdown drain deadline passes, instead of dropping the connection
+pub(super) fn with_error_events(body: Body, provider: String, shutdown: Arc<Shutdown>) -> Body {
+    let stream = futures_util::stream::unfold(
+        Some((body.into_data_stream(), true, provider)),
+        move |state| {
+            let shutdown = shutdown.clone();
+            async move {
+                let (mut upstream, at_boundary, provider) = state?;
+                let next = tokio::select! {
+                    next = upstream.next() => next,
+                    _ = shutdown.reached(Phase::Terminating) => {
+                        warn!(provider = %provider, "Terminating stream for shutdown");
+                        let event = error_body(
+                            "The gateway is shutting down, retry the request",
+                            "api_error",
+                            "shutting_down",
+                            None,
+                        );
+                        return Some((Ok(error_event(event, at_boundary)), None));
+                    }
+                };
+                match next {
+                    Some(Ok(bytes)) => {
+                        let at_boundary = if bytes.is_empty() {
+                            at_boundary
+                        } else {
+                            bytes.ends_with(b"\n\n") || bytes.ends_with(b"\r\n\r\n")
+                        };
+                        Some((
+                            Ok::<_, io::Error>(bytes),
+                            Some((upstream, at_boundary, provider)),
+                        ))
+                    }
+                    Some(Err(e)) => {
+                        error!(provider = %provider, error = %e, "Stream failed mid-response");
+                        let (error_type, code) = if is_timeout(&e) {
+                            ("timeout_error", "upstream_timeout")
+                        } else {
+                            ("api_error", "stream_error")
+                        };
+                        let mut event = error_body(&e.to_string(), error_type, code, None);
+                        event["error"]["provider"] = json!(provider);
+                        Some((Ok(error_event(event, at_boundary)), None))
+                    }
+                    None => None,
+                }
+            }
+        },
+    );
+    Body::from_stream(stream)
+}
+
+/// Terminal error event, closing a partially sent event first so it parses on its own
+fn error_event(event: Value, at_boundary: bool) -> Bytes {
+    let separator = if at_boundary { "" } else { "\n\n" };
+    Bytes::from(format!("{}data: {}\n\n", separator, event))
+}
+
+/// Whether a stream error was caused by one of the gateway's stream timeouts,
+/// possibly wrapped by provider stream transforms
+fn is_timeout(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if error
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::TimedOut)
+        {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
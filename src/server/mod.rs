@@ -0,0 +1,333 @@
+use crate::shutdown::{Phase, Shutdown};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use futures_util::future::join_all;
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{
+        conn::auto,
+        graceful::{GracefulShutdown, Watcher},
+    },
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{unix::UCred, TcpListener, TcpStream, UnixListener, UnixStream};
+use tower_service::Service;
+use tracing::{debug, info, warn};
+
+mod proxy_protocol;
+pub mod tls;
+
+use tls::{ClientCertificate, Tls};
+
+/// Connections waiting to be accepted, beyond which the kernel refuses new ones
+const BACKLOG: i32 = 1024;
+/// Time a client gets to send its PROXY protocol header and complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client end of a connection, available to handlers as `ConnectInfo<Peer>`
+#[derive(Debug, Clone, Copy)]
+pub enum Peer {
+    Tcp {
+        address: SocketAddr,
+        /// Client address a PROXY protocol header reported for the connection, only
+        /// believed when `address` is a trusted proxy
+        proxied: Option<SocketAddr>,
+    },
+    /// Local process connected through the Unix domain socket, with its credentials
+    /// when the platform reports them
+    Unix(Option<UCred>),
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Peer::Tcp { address, .. } => write!(f, "{}", address),
+            Peer::Unix(Some(cred)) => match cred.pid() {
+                Some(pid) => write!(f, "unix:pid={},uid={}", pid, cred.uid()),
+                None => write!(f, "unix:uid={}", cred.uid()),
+            },
+            Peer::Unix(None) => write!(f, "unix"),
+        }
+    }
+}
+
+/// A bound socket the gateway accepts connections on, optionally terminating TLS
+pub struct Listener {
+    bound: Bound,
+    tls: Option<Arc<Tls>>,
+    /// Whether connections start with a PROXY protocol header
+    proxy_protocol: bool,
+}
+
+enum Bound {
+    Tcp {
+        listener: TcpListener,
+        address: SocketAddr,
+    },
+    Unix {
+        listener: UnixListener,
+        path: PathBuf,
+    },
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Listener {
+    pub fn new(
+        listener: TcpListener,
+        tls: Option<Arc<Tls>>,
+        proxy_protocol: bool,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            bound: Bound::Tcp {
+                address: listener.local_addr()?,
+                listener,
+            },
+            tls,
+            proxy_protocol,
+        })
+    }
+
+    /// Bind a Unix domain socket at `path`, replacing a socket file left behind by a
+    /// previous run. `mode` sets the file permissions, e.g. `0o660`.
+    pub fn unix(path: &str, mode: Option<u32>) -> io::Result<Self> {
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                // A socket that still accepts connections belongs to a running process
+                if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is in use by another process", path),
+                    ));
+                }
+                std::fs::remove_file(path)?;
+            }
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path),
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let listener = UnixListener::bind(path)?;
+        if let Some(mode) = mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        Ok(Self {
+            bound: Bound::Unix {
+                listener,
+                path: PathBuf::from(path),
+            },
+            tls: None,
+            proxy_protocol: false,
+        })
+    }
+
+    async fn accept(&self) -> io::Result<(Stream, Peer)> {
+        match &self.bound {
+            Bound::Tcp { listener, .. } => {
+                let (stream, address) = listener.accept().await?;
+                let peer = Peer::Tcp {
+                    address,
+                    proxied: None,
+                };
+                Ok((Stream::Tcp(stream), peer))
+            }
+            Bound::Unix { listener, .. } => {
+                let (stream, _) = listener.accept().await?;
+                let cred = stream.peer_cred().ok();
+                Ok((Stream::Unix(stream), Peer::Unix(cred)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.bound {
+            Bound::Tcp { address, .. } => {
+                let scheme = if self.tls.is_some() { "https" } else { "http" };
+                write!(f, "{}://{}", scheme, address)
+            }
+            Bound::Unix { path, .. } => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Bind `port` on every address of a comma-separated host list such as `0.0.0.0,::`.
+/// IPv6 sockets only accept IPv6 connections so both families can be listed side by side.
+/// An empty list binds nothing.
+pub fn bind(hosts: &str, port: u16) -> io::Result<Vec<TcpListener>> {
+    let mut addresses = Vec::new();
+    let mut seen = HashSet::new();
+    for host in hosts.split(',').map(str::trim).filter(|h| !h.is_empty()) {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        for address in (host, port).to_socket_addrs()? {
+            if seen.insert(address) {
+                addresses.push(address);
+            }
+        }
+    }
+    addresses
+        .into_iter()
+        .map(|address| {
+            let socket = Socket::new(
+                Domain::for_address(address),
+                Type::STREAM,
+                Some(Protocol::TCP),
+            )?;
+            socket.set_reuse_address(true)?;
+            if address.is_ipv6() {
+                socket.set_only_v6(true)?;
+            }
+            socket.bind(&address.into())?;
+            socket.listen(BACKLOG)?;
+            socket.set_nonblocking(true)?;
+            TcpListener::from_std(socket.into())
+        })
+        .collect()
+}
+
+/// Serve `app` on every listener until the shutdown closes the listeners, then wait for
+/// open connections to finish their requests
+pub async fn serve(listeners: Vec<Listener>, app: Router, shutdown: Arc<Shutdown>) {
+    let graceful = Arc::new(GracefulShutdown::new());
+    let accepting = listeners.into_iter().map(|listener| {
+        tokio::spawn(accept(
+            listener,
+            app.clone(),
+            graceful.clone(),
+            shutdown.clone(),
+        ))
+    });
+    join_all(accepting).await;
+
+    if let Ok(graceful) = Arc::try_unwrap(graceful) {
+        debug!("Listeners closed, waiting for open connections");
+        graceful.shutdown().await;
+    }
+}
+
+async fn accept(
+    listener: Listener,
+    app: Router,
+    graceful: Arc<GracefulShutdown>,
+    shutdown: Arc<Shutdown>,
+) {
+    info!("AI Gateway listening on {}", listener);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.reached(Phase::Closing) => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Running out of file descriptors must not spin the loop
+                warn!("Failed to accept connection on {}: {}", listener, e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let watcher = graceful.watcher();
+        let app = app.clone();
+        let tls = listener.tls.clone();
+        let proxy_protocol = listener.proxy_protocol;
+        tokio::spawn(async move {
+            match stream {
+                Stream::Tcp(mut stream) => {
+                    let mut peer = peer;
+                    if proxy_protocol {
+                        let header = proxy_protocol::read_header(&mut stream);
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, header).await {
+                            Ok(Ok(source)) => {
+                                if let Peer::Tcp { proxied, .. } = &mut peer {
+                                    *proxied = source;
+                                }
+                            }
+                            Ok(Err(e)) => return debug!("PROXY header from {}: {}", peer, e),
+                            Err(_) => return debug!("PROXY header from {} timed out", peer),
+                        }
+                    }
+                    handshake(stream, peer, tls, app, watcher).await
+                }
+                Stream::Unix(stream) => handshake(stream, peer, tls, app, watcher).await,
+            }
+        });
+    }
+    info!("Stopped listening on {}", listener);
+    if let Bound::Unix { path, .. } = &listener.bound {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Terminate TLS when the listener has it, then serve the connection
+async fn handshake<I>(io: I, peer: Peer, tls: Option<Arc<Tls>>, app: Router, watcher: Watcher)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let Some(tls) = tls else {
+        return connection(io, peer, None, app, watcher).await;
+    };
+    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.acceptor().accept(io)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return debug!("TLS handshake with {} failed: {}", peer, e),
+        Err(_) => return debug!("TLS handshake with {} timed out", peer),
+    };
+    let certificate = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .and_then(|cert| ClientCertificate::parse(cert));
+    if let Some(certificate) = &certificate {
+        debug!("Client {} authenticated as {}", peer, certificate.subject);
+    }
+    connection(stream, peer, certificate, app, watcher).await
+}
+
+/// Serve HTTP/1.1 and HTTP/2 requests on one connection
+async fn connection<I>(
+    io: I,
+    peer: Peer,
+    certificate: Option<ClientCertificate>,
+    app: Router,
+    watcher: Watcher,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+        request.extensions_mut().insert(ConnectInfo(peer));
+        if let Some(certificate) = &certificate {
+            request.extensions_mut().insert(certificate.clone());
+        }
+        let mut app = app.clone();
+        async move { app.call(request.map(Body::new)).await }
+    });
+
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+    if let Err(e) = watcher.watch(connection.into_owned()).await {
+        debug!("Connection from {} closed with error: {}", peer, e);
+    }
+}
@@ -0,0 +1,303 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// Longest possible version 1 header, including the CRLF
+const V1_MAX_LENGTH: usize = 107;
+
+enum Header {
+    Incomplete,
+    Complete {
+        length: usize,
+        source: Option<SocketAddr>,
+    },
+}
+
+/// Read the PROXY protocol version 1 or 2 header a load balancer sends at the start of
+/// a connection, leaving the stream at the first byte after it. Returns the client
+/// address the header reports, `None` for health checks of the proxy itself.
+pub async fn read_header(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut buf = [0u8; V1_MAX_LENGTH];
+    loop {
+        let read = stream.peek(&mut buf).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        match parse(&buf[..read]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))? {
+            Header::Complete { length, source } => {
+                let mut header = vec![0u8; length];
+                stream.read_exact(&mut header).await?;
+                return Ok(source);
+            }
+            // The header arrived split across segments, wait for the rest
+            Header::Incomplete => tokio::time::sleep(Duration::from_millis(5)).await,
+        }
+    }
+}
+
+fn parse(buf: &[u8]) -> Result<Header, String> {
+    if buf.len() < V2_SIGNATURE.len() {
+        let v1 = b"PROXY ".starts_with(&buf[..buf.len().min(6)]);
+        let v2 = V2_SIGNATURE.starts_with(buf);
+        return if v1 || v2 {
+            Ok(Header::Incomplete)
+        } else {
+            Err("connection did not start with a PROXY protocol header".to_string())
+        };
+    }
+    if buf.starts_with(V2_SIGNATURE) {
+        parse_v2(buf)
+    } else if buf.starts_with(b"PROXY ") {
+        parse_v1(buf)
+    } else {
+        Err("connection did not start with a PROXY protocol header".to_string())
+    }
+}
+
+/// `PROXY TCP4 203.0.113.7 10.0.0.1 51234 443\r\n` or `PROXY UNKNOWN ...\r\n`
+fn parse_v1(buf: &[u8]) -> Result<Header, String> {
+    let Some(end) = buf.windows(2).position(|w| w == b"\r\n") else {
+        return if buf.len() >= V1_MAX_LENGTH {
+            Err("PROXY protocol v1 header is too long".to_string())
+        } else {
+            Ok(Header::Incomplete)
+        };
+    };
+    let line = std::str::from_utf8(&buf[..end])
+        .map_err(|_| "PROXY protocol v1 header is not ASCII".to_string())?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    let source = match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => None,
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| format!("invalid PROXY protocol source address {}", source))?;
+            let port: u16 = port
+                .parse()
+                .map_err(|_| format!("invalid PROXY protocol source port {}", port))?;
+            Some(SocketAddr::new(ip, port))
+        }
+        _ => return Err(format!("invalid PROXY protocol v1 header: {}", line)),
+    };
+    Ok(Header::Complete {
+        length: end + 2,
+        source,
+    })
+}
+
+/// Binary header: signature, version and command, address family, length, addresses
+fn parse_v2(buf: &[u8]) -> Result<Header, String> {
+    if buf.len() < 16 {
+        return Ok(Header::Incomplete);
+    }
+    let version = buf[12] >> 4;
+    let command = buf[12] & 0x0f;
+    if version != 2 {
+        return Err(format!("unsupported PROXY protocol version {}", version));
+    }
+    let length = 16 + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    // LOCAL connections come from the proxy itself and carry no client address
+    if command == 0 {
+        return Ok(Header::Complete {
+            length,
+            source: None,
+        });
+    }
+    let addresses = match buf[13] >> 4 {
+        1 => 12,
+        2 => 36,
+        // Unix sockets and unspecified families have no IP address to report
+        _ => {
+            return Ok(Header::Complete {
+                length,
+                source: None,
+            })
+        }
+    };
+    if length < 16 + addresses {
+        return Err("PROXY protocol v2 header is shorter than its addresses".to_string());
+    }
+    if buf.len() < 16 + addresses {
+        return Ok(Header::Incomplete);
+    }
+    let body = &buf[16..16 + addresses];
+    let source = if addresses == 12 {
+        let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+        SocketAddr::new(ip.into(), u16::from_be_bytes([body[8], body[9]]))
+    } else {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&body[..16]);
+        let ip = Ipv6Addr::from(octets);
+        SocketAddr::new(ip.into(), u16::from_be_bytes([body[32], body[33]]))
+    };
+    Ok(Header::Complete {
+        length,
+        source: Some(source),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(buf: &[u8]) -> (usize, Option<SocketAddr>) {
+        match parse(buf) {
+            Ok(Header::Complete { length, source }) => (length, source),
+            Ok(Header::Incomplete) => panic!("header reported incomplete"),
+            Err(e) => panic!("header rejected: {}", e),
+        }
+    }
+
+    fn incomplete(buf: &[u8]) -> bool {
+        matches!(parse(buf), Ok(Header::Incomplete))
+    }
+
+    /// Version 2 header with `command` (0 LOCAL, 1 PROXY), address family and protocol
+    /// byte, the declared length and the bytes following the fixed part
+    fn v2(command: u8, family: u8, length: u16, rest: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&length.to_be_bytes());
+        header.extend_from_slice(rest);
+        header
+    }
+
+    fn v4_addresses() -> Vec<u8> {
+        let mut addresses = vec![203, 0, 113, 7, 10, 0, 0, 1];
+        addresses.extend_from_slice(&51234u16.to_be_bytes());
+        addresses.extend_from_slice(&443u16.to_be_bytes());
+        addresses
+    }
+
+    #[test]
+    fn parses_v1_tcp4() {
+        let header = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 443\r\n";
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(b"GET / HTTP/1.1\r\n");
+        let (length, source) = complete(&buf);
+        assert_eq!(length, header.len());
+        assert_eq!(source, Some("203.0.113.7:51234".parse().unwrap()));
+    }
+
+    #[test]
+    fn parses_v1_tcp6() {
+        let header = b"PROXY TCP6 2001:db8::1 2001:db8::2 4711 443\r\n";
+        let (length, source) = complete(header);
+        assert_eq!(length, header.len());
+        assert_eq!(source, Some("[2001:db8::1]:4711".parse().unwrap()));
+    }
+
+    #[test]
+    fn parses_v1_unknown_without_source() {
+        let header = b"PROXY UNKNOWN\r\n";
+        assert_eq!(complete(header), (header.len(), None));
+        let header = b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n";
+        assert_eq!(complete(header), (header.len(), None));
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_v1_header() {
+        assert!(incomplete(b"PRO"));
+        assert!(incomplete(b"PROXY TCP4 203.0.113.7 10.0"));
+        assert!(incomplete(b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 443\r"));
+    }
+
+    #[test]
+    fn rejects_invalid_v1_headers() {
+        for header in [
+            &b"GET / HTTP/1.1\r\nHost: example.com\r\n"[..],
+            b"PROXY TCP4 not-an-address 10.0.0.1 51234 443\r\n",
+            b"PROXY TCP4 203.0.113.7 10.0.0.1 99999 443\r\n",
+            b"PROXY TCP4 203.0.113.7 10.0.0.1 51234\r\n",
+            b"PROXY UDP4 203.0.113.7 10.0.0.1 51234 443\r\n",
+            b"PROXY TCP4 \xff\xfe 10.0.0.1 51234 443\r\n",
+        ] {
+            assert!(
+                parse(header).is_err(),
+                "{:?}",
+                String::from_utf8_lossy(header)
+            );
+        }
+        let endless = [b"PROXY ".as_slice(), &[b'1'; V1_MAX_LENGTH]].concat();
+        assert!(parse(&endless[..V1_MAX_LENGTH]).is_err());
+    }
+
+    #[test]
+    fn parses_v2_local_without_source() {
+        let header = v2(0, 0x00, 0, &[]);
+        assert_eq!(complete(&header), (16, None));
+        // LOCAL headers may still carry addresses, which are not the client's
+        let header = v2(0, 0x11, 12, &v4_addresses());
+        assert_eq!(complete(&header), (28, None));
+    }
+
+    #[test]
+    fn parses_v2_ipv4() {
+        let header = v2(1, 0x11, 12, &v4_addresses());
+        let (length, source) = complete(&header);
+        assert_eq!(length, 28);
+        assert_eq!(source, Some("203.0.113.7:51234".parse().unwrap()));
+    }
+
+    #[test]
+    fn parses_v2_ipv6() {
+        let source: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let destination: Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let mut addresses = source.octets().to_vec();
+        addresses.extend_from_slice(&destination.octets());
+        addresses.extend_from_slice(&4711u16.to_be_bytes());
+        addresses.extend_from_slice(&443u16.to_be_bytes());
+        let header = v2(1, 0x21, 36, &addresses);
+        assert_eq!(
+            complete(&header),
+            (52, Some("[2001:db8::1]:4711".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn skips_v2_tlvs_after_the_addresses() {
+        let mut rest = v4_addresses();
+        rest.extend_from_slice(&[0x04, 0x00, 0x02, 0xab, 0xcd]);
+        let header = v2(1, 0x11, 17, &rest);
+        assert_eq!(
+            complete(&header),
+            (33, Some("203.0.113.7:51234".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn reports_unix_and_unspecified_v2_families_without_source() {
+        let header = v2(1, 0x31, 216, &[0; 216]);
+        assert_eq!(complete(&header), (232, None));
+        let header = v2(1, 0x00, 0, &[]);
+        assert_eq!(complete(&header), (16, None));
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_v2_header() {
+        let header = v2(1, 0x11, 12, &v4_addresses());
+        for end in 1..header.len() {
+            assert!(incomplete(&header[..end]), "truncated at {}", end);
+        }
+    }
+
+    #[test]
+    fn rejects_v2_headers_shorter_than_their_addresses() {
+        let addresses = v4_addresses();
+        let header = v2(1, 0x11, 4, &addresses);
+        assert!(parse(&header).is_err());
+        let header = v2(1, 0x21, 12, &[0; 36]);
+        assert!(parse(&header).is_err());
+    }
+
+    #[test]
+    fn rejects_other_v2_versions() {
+        let mut header = v2(1, 0x11, 12, &v4_addresses());
+        header[12] = 0x11;
+        assert!(parse(&header).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::config::TlsConfig;
+use parking_lot::{Mutex, RwLock};
+use rustls::{server::WebPkiClientVerifier, RootCertStore, ServerConfig};
+use rustls_pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+use x509_parser::extensions::GeneralName;
+
+/// TLS settings of the listeners, rebuilt when the certificate, key or client CA files change
+pub struct Tls {
+    config: TlsConfig,
+    current: RwLock<Arc<ServerConfig>>,
+    /// Modification times of the files the current settings were built from
+    loaded: Mutex<Vec<Option<SystemTime>>>,
+}
+
+impl Tls {
+    pub fn load(config: TlsConfig) -> Result<Self, String> {
+        let loaded = modified(&config);
+        let current = build(&config)?;
+        Ok(Self {
+            config,
+            current: RwLock::new(Arc::new(current)),
+            loaded: Mutex::new(loaded),
+        })
+    }
+
+    /// Acceptor with the current certificate, handshakes in progress keep the one they started with
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().clone())
+    }
+
+    /// Check the files for changes every `reload_interval` seconds and apply them.
+    /// A change that does not load keeps the previous certificate in use.
+    pub async fn watch(self: Arc<Self>) {
+        let interval = Duration::from_secs(self.config.reload_interval.max(1));
+        loop {
+            tokio::time::sleep(interval).await;
+            let modified = modified(&self.config);
+            if *self.loaded.lock() == modified {
+                continue;
+            }
+            match build(&self.config) {
+                Ok(current) => {
+                    *self.current.write() = Arc::new(current);
+                    info!("Reloaded TLS certificate from {}", self.config.cert);
+                }
+                Err(e) => warn!("Keeping the previous TLS certificate: {}", e),
+            }
+            *self.loaded.lock() = modified;
+        }
+    }
+}
+
+fn modified(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [
+        Some(&config.cert),
+        Some(&config.key),
+        config.client_ca.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+    .collect()
+}
+
+fn build(config: &TlsConfig) -> Result<ServerConfig, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = CertificateDer::pem_file_iter(&config.cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read TLS certificate {}: {}", config.cert, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {}", config.cert));
+    }
+    let key = PrivateKeyDer::from_pem_file(&config.key)
+        .map_err(|e| format!("Failed to read TLS key {}: {}", config.key, e))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            let cas = CertificateDer::pem_file_iter(path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| format!("Failed to read TLS client CA {}: {}", path, e))?;
+            for ca in cas {
+                roots
+                    .add(ca)
+                    .map_err(|e| format!("Invalid TLS client CA in {}: {}", path, e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.client_auth_required {
+                verifier.build()
+            } else {
+                verifier.allow_unauthenticated().build()
+            }
+            .map_err(|e| format!("Invalid TLS client CA in {}: {}", path, e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server)
+}
+
+/// Verified client certificate of a mutual TLS connection, added to each of its requests
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// Subject distinguished name, e.g. `CN=billing, O=Example`
+    pub subject: String,
+    /// Subject common names and DNS and URI alternative names, matched against the
+    /// `client_certs` of `[[keys]]`
+    pub names: Vec<String>,
+}
+
+impl ClientCertificate {
+    pub fn parse(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let mut names: Vec<String> = cert
+            .subject()
+            .iter_common_name()
+            .filter_map(|cn| cn.as_str().ok())
+            .map(String::from)
+            .collect();
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                if let GeneralName::DNSName(name) | GeneralName::URI(name) = name {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Some(Self {
+            subject: cert.subject().to_string(),
+            names,
+        })
+    }
+}
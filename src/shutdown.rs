@@ -0,0 +1,88 @@
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Steps of a graceful shutdown, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Running,
+    /// `/readyz` reports not ready while connections are still accepted, so load balancers
+    /// stop sending traffic before the listener closes
+    Draining,
+    /// No new connections, in-flight requests and streams run to completion
+    Closing,
+    /// The drain deadline passed, remaining streams end with an error event
+    Terminating,
+}
+
+/// Shutdown sequence started by SIGINT or SIGTERM
+pub struct Shutdown {
+    phase: watch::Sender<Phase>,
+    /// Time between turning not ready and closing the listeners
+    delay: Duration,
+    /// Time in-flight requests and streams may take after the listeners close
+    timeout: Duration,
+}
+
+impl Shutdown {
+    pub fn new(delay: Duration, timeout: Duration) -> Self {
+        Self {
+            phase: watch::channel(Phase::Running).0,
+            delay,
+            timeout,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        *self.phase.borrow()
+    }
+
+    /// Resolve once the shutdown has reached `phase`
+    pub async fn reached(&self, phase: Phase) {
+        let mut receiver = self.phase.subscribe();
+        // The sender lives as long as `self`, so waiting cannot fail
+        let _ = receiver.wait_for(|current| *current >= phase).await;
+    }
+
+    /// Wait for a shutdown signal, then step through the shutdown phases
+    pub async fn run(&self) {
+        signal().await;
+        info!(
+            "Shutdown signal received, reporting not ready for {:?} before closing listeners",
+            self.delay
+        );
+        self.phase.send_replace(Phase::Draining);
+        tokio::time::sleep(self.delay).await;
+
+        info!(
+            "Closing listeners, waiting up to {:?} for in-flight requests",
+            self.timeout
+        );
+        self.phase.send_replace(Phase::Closing);
+        tokio::time::sleep(self.timeout).await;
+
+        warn!("Drain deadline reached, terminating remaining streams");
+        self.phase.send_replace(Phase::Terminating);
+    }
+}
+
+async fn signal() {
+    info!("Registering shutdown signal handler");
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install CTRL+C signal handler")
+    };
+
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{
+    catalog,
+    config::{AppConfig, FileConfig},
+    guardrails::Guardrails,
+    health::{self, ProviderHealth},
+    metrics::Metrics,
+    providers::ProviderRegistry,
+    shutdown::Shutdown,
+    usage::Usage,
+};
+use axum::extract::FromRef;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use tracing::info;
+
+/// State shared by every request, built from one configuration
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<AppConfig>,
+    pub providers: Arc<ProviderRegistry>,
+    pub guardrails: Arc<Guardrails>,
+    pub metrics: Arc<Metrics>,
+    pub health: Arc<ProviderHealth>,
+    pub usage: Arc<Usage>,
+    pub shutdown: Arc<Shutdown>,
+}
+
+impl AppState {
+    pub fn new(
+        config: Arc<AppConfig>,
+        metrics: Arc<Metrics>,
+        health: Arc<ProviderHealth>,
+        usage: Arc<Usage>,
+        shutdown: Arc<Shutdown>,
+    ) -> Result<Self, String> {
+        let providers = Arc::new(ProviderRegistry::new(&config)?);
+        let guardrails = Arc::new(Guardrails::new(
+            &config,
+            &providers.shared_client(),
+            metrics.clone(),
+        )?);
+        for probe in &config.health.probes {
+            if !providers.names().contains(&probe.provider.as_str()) {
+                return Err(format!(
+                    "Health probe refers to unknown provider {}",
+                    probe.provider
+                ));
+            }
+        }
+        for name in &config.health.required {
+            if health::targets_for(&config, &providers, name).is_empty() {
+                return Err(format!(
+                    "Readiness requires unknown model or provider {}",
+                    name
+                ));
+            }
+        }
+        Ok(Self {
+            config,
+            providers,
+            guardrails,
+            metrics,
+            health,
+            usage,
+            shutdown,
+        })
+    }
+}
+
+/// The current `AppState`, replaced when the configuration is reloaded.
+/// Requests keep the state they started with until they finish.
+pub struct Gateway {
+    current: RwLock<AppState>,
+}
+
+impl Gateway {
+    pub fn new(state: AppState) -> Self {
+        Self {
+            current: RwLock::new(state),
+        }
+    }
+
+    pub fn state(&self) -> AppState {
+        self.current.read().clone()
+    }
+
+    /// Build the state for the environment, config file and `managed` entries without
+    /// applying it. Metrics, provider health, usage and the shutdown state carry over.
+    pub fn build(&self, managed: FileConfig) -> Result<AppState, String> {
+        let current = self.state();
+        let config = AppConfig::load(managed)?;
+        AppState::new(
+            Arc::new(config),
+            current.metrics,
+            current.health,
+            current.usage,
+            current.shutdown,
+        )
+    }
+
+    /// Serve new requests with `state`
+    pub fn swap(&self, state: AppState) {
+        *self.current.write() = state;
+        catalog::invalidate();
+        info!("Configuration reloaded");
+    }
+}
+
+impl FromRef<Arc<Gateway>> for AppState {
+    fn from_ref(gateway: &Arc<Gateway>) -> Self {
+        gateway.state()
+    }
+}
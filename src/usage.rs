@@ -0,0 +1,388 @@
+use crate::{config::AppConfig, error::AppError, state::AppState};
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+/// Requests kept for the live tail
+const RECENT_REQUESTS: usize = 200;
+/// How long per-minute totals are kept
+const RETENTION_MINUTES: i64 = 24 * 60;
+/// Records a slow live tail subscriber may fall behind before it skips ahead
+const LIVE_CAPACITY: usize = 256;
+/// Largest non-streamed response body scanned for token usage
+const MAX_SCANNED_BODY: usize = 4 * 1024 * 1024;
+
+/// One proxied request as seen by usage accounting
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRecord {
+    pub time: DateTime<Utc>,
+    /// Name of the gateway key the request was made with
+    pub key: Option<String>,
+    pub provider: String,
+    pub model: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// Time until the response, or the stream, finished
+    pub latency_ms: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Cost in USD from the `input_price` and `output_price` of the matching `[[models]]` entry
+    pub cost: f64,
+    pub error: Option<String>,
+}
+
+impl UsageRecord {
+    fn failed(&self) -> bool {
+        self.status >= 400 || self.error.is_some()
+    }
+}
+
+/// Summed usage of a group of requests
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Totals {
+    pub requests: u64,
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl Totals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.errors += u64::from(record.failed());
+        self.prompt_tokens += record.prompt_tokens;
+        self.completion_tokens += record.completion_tokens;
+        self.cost += record.cost;
+    }
+}
+
+/// Totals of one key, provider and model within one minute
+#[derive(Debug, Clone, Serialize)]
+pub struct Bucket {
+    pub time: DateTime<Utc>,
+    pub key: Option<String>,
+    pub provider: String,
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub totals: Totals,
+}
+
+type Series = (Option<String>, String, Option<String>);
+
+/// Usage of proxied requests: per-minute totals for the last day, the most recent
+/// requests and a feed of requests as they finish. Kept in memory, reset on restart.
+pub struct Usage {
+    minutes: Mutex<BTreeMap<i64, HashMap<Series, Totals>>>,
+    recent: Mutex<VecDeque<UsageRecord>>,
+    live: broadcast::Sender<UsageRecord>,
+}
+
+impl Default for Usage {
+    fn default() -> Self {
+        Self {
+            minutes: Mutex::default(),
+            recent: Mutex::default(),
+            live: broadcast::channel(LIVE_CAPACITY).0,
+        }
+    }
+}
+
+impl Usage {
+    pub fn record(&self, record: UsageRecord) {
+        let minute = record.time.timestamp() / 60;
+        {
+            let mut minutes = self.minutes.lock();
+            minutes
+                .entry(minute)
+                .or_default()
+                .entry((
+                    record.key.clone(),
+                    record.provider.clone(),
+                    record.model.clone(),
+                ))
+                .or_default()
+                .add(&record);
+            let expired = minute - RETENTION_MINUTES;
+            while minutes
+                .first_key_value()
+                .is_some_and(|(m, _)| *m <= expired)
+            {
+                minutes.pop_first();
+            }
+        }
+        {
+            let mut recent = self.recent.lock();
+            if recent.len() == RECENT_REQUESTS {
+                recent.pop_front();
+            }
+            recent.push_back(record.clone());
+        }
+        // Nobody may be listening
+        let _ = self.live.send(record);
+    }
+
+    /// Per-minute totals since `since`, oldest first
+    pub fn buckets(&self, since: DateTime<Utc>) -> Vec<Bucket> {
+        let since = since.timestamp() / 60;
+        self.minutes
+            .lock()
+            .range(since..)
+            .flat_map(|(minute, series)| {
+                let time = DateTime::from_timestamp(minute * 60, 0).unwrap_or_default();
+                series
+                    .iter()
+                    .map(move |((key, provider, model), totals)| Bucket {
+                        time,
+                        key: key.clone(),
+                        provider: provider.clone(),
+                        model: model.clone(),
+                        totals: totals.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Most recent requests, newest first
+    pub fn recent(&self) -> Vec<UsageRecord> {
+        self.recent.lock().iter().rev().cloned().collect()
+    }
+
+    /// Requests as they finish
+    pub fn subscribe(&self) -> broadcast::Receiver<UsageRecord> {
+        self.live.subscribe()
+    }
+}
+
+/// Provider and model that served a response, set by the proxy for usage accounting
+#[derive(Debug, Clone)]
+pub struct Routed {
+    pub provider: String,
+    pub model: Option<String>,
+}
+
+/// Usage record of a request being proxied, completed from its response
+pub struct Tracker {
+    usage: Arc<Usage>,
+    config: Arc<AppConfig>,
+    started: Instant,
+    record: UsageRecord,
+}
+
+impl Tracker {
+    pub fn start(state: &AppState, provider: &str, request: &Request<Body>) -> Self {
+        let key = state
+            .config
+            .key_for_request(request)
+            .map(|key| key.name.clone());
+        Self {
+            usage: state.usage.clone(),
+            config: state.config.clone(),
+            started: Instant::now(),
+            record: UsageRecord {
+                time: Utc::now(),
+                key,
+                provider: provider.to_string(),
+                model: None,
+                method: request.method().to_string(),
+                path: request.uri().path().to_string(),
+                status: 0,
+                latency_ms: 0,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                cost: 0.0,
+                error: None,
+            },
+        }
+    }
+
+    /// Record a failed request, or record a response once its body has been sent
+    pub fn finish(mut self, result: Result<Response, AppError>) -> Response {
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                self.record.error = Some(e.to_string());
+                let response = e.into_response();
+                self.record.status = response.status().as_u16();
+                self.complete();
+                return response;
+            }
+        };
+
+        self.record.status = response.status().as_u16();
+        if let Some(routed) = response.extensions().get::<Routed>() {
+            self.record.provider = routed.provider.clone();
+            self.record.model = routed.model.clone();
+        }
+        let scan = match response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(ct) if ct.starts_with("text/event-stream") => Scan::Events(Vec::new()),
+            Some(ct) if ct.starts_with("application/json") => Scan::Json(Vec::new()),
+            _ => Scan::None,
+        };
+        let mut observed = Observed {
+            tracker: Some(self),
+            scan,
+        };
+        let (parts, body) = response.into_parts();
+        let body = body.into_data_stream().map(move |chunk| {
+            match &chunk {
+                Ok(bytes) => observed.chunk(bytes),
+                Err(e) => observed.failed(e.to_string()),
+            }
+            chunk
+        });
+        Response::from_parts(parts, Body::from_stream(body))
+    }
+
+    /// Take the model and token counts from a response or stream event. Token counts keep
+    /// the largest value seen so that cumulative stream counts are not added up.
+    fn observe(&mut self, value: &Value) {
+        // Request bodies streamed to the provider unread leave the model to the response
+        if self.record.model.is_none() {
+            self.record.model = value
+                .get("model")
+                .or_else(|| value.pointer("/message/model"))
+                .and_then(Value::as_str)
+                .map(String::from);
+        }
+        let usage = value
+            .get("usage")
+            .or_else(|| value.pointer("/message/usage"))
+            .filter(|usage| usage.is_object());
+        let Some(usage) = usage else {
+            return;
+        };
+        let count = |fields: &[&str]| {
+            fields
+                .iter()
+                .find_map(|field| usage.get(*field).and_then(Value::as_u64))
+                .unwrap_or(0)
+        };
+        let prompt = count(&["prompt_tokens", "input_tokens", "inputTokens"]);
+        let completion = count(&["completion_tokens", "output_tokens", "outputTokens"]);
+        self.record.prompt_tokens = self.record.prompt_tokens.max(prompt);
+        self.record.completion_tokens = self.record.completion_tokens.max(completion);
+    }
+
+    fn complete(mut self) {
+        let record = &mut self.record;
+        record.latency_ms = self.started.elapsed().as_millis() as u64;
+        record.cost = cost(&self.config, record);
+        self.usage.record(self.record);
+    }
+}
+
+/// Price a record with the `[[models]]` entry for its provider and model
+fn cost(config: &AppConfig, record: &UsageRecord) -> f64 {
+    let Some(model) = record.model.as_deref() else {
+        return 0.0;
+    };
+    config
+        .models
+        .iter()
+        .find(|m| m.provider == record.provider && (m.upstream_model() == model || m.id == model))
+        .map(|m| {
+            m.input_price.unwrap_or(0.0) * record.prompt_tokens as f64 / 1_000_000.0
+                + m.output_price.unwrap_or(0.0) * record.completion_tokens as f64 / 1_000_000.0
+        })
+        .unwrap_or(0.0)
+}
+
+/// How a response body is scanned for token usage
+enum Scan {
+    /// SSE events, scanned line by line as they pass
+    Events(Vec<u8>),
+    /// A JSON body, scanned once complete
+    Json(Vec<u8>),
+    None,
+}
+
+/// Response body being sent, recorded when it ends or the client goes away
+struct Observed {
+    tracker: Option<Tracker>,
+    scan: Scan,
+}
+
+impl Observed {
+    fn chunk(&mut self, bytes: &Bytes) {
+        let Some(tracker) = self.tracker.as_mut() else {
+            return;
+        };
+        match &mut self.scan {
+            Scan::Events(pending) => {
+                pending.extend_from_slice(bytes);
+                while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=end).collect();
+                    event_line(tracker, &line);
+                }
+            }
+            Scan::Json(body) => {
+                if body.len() + bytes.len() <= MAX_SCANNED_BODY {
+                    body.extend_from_slice(bytes);
+                } else {
+                    self.scan = Scan::None;
+                }
+            }
+            Scan::None => {}
+        }
+    }
+
+    fn failed(&mut self, error: String) {
+        if let Some(tracker) = self.tracker.as_mut() {
+            tracker.record.error = Some(error);
+        }
+    }
+}
+
+impl Drop for Observed {
+    fn drop(&mut self) {
+        let Some(mut tracker) = self.tracker.take() else {
+            return;
+        };
+        if let Scan::Json(body) = &self.scan {
+            if let Ok(value) = serde_json::from_slice::<Value>(body) {
+                tracker.observe(&value);
+            }
+        }
+        tracker.complete();
+    }
+}
+
+/// Model, token usage and terminal error events in one SSE line
+fn event_line(tracker: &mut Tracker, line: &[u8]) {
+    let Some(data) = line.strip_prefix(b"data:") else {
+        return;
+    };
+    let data = String::from_utf8_lossy(data);
+    let wanted = data.contains("\"usage\"")
+        || data.contains("\"error\"")
+        || (tracker.record.model.is_none() && data.contains("\"model\""));
+    if !wanted {
+        return;
+    }
+    let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+        return;
+    };
+    if let Some(error) = event.get("error") {
+        let message = error["message"].as_str().unwrap_or("stream error");
+        tracker.record.error = Some(message.to_string());
+    }
+    tracker.observe(&event);
+}